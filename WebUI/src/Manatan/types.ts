@@ -5,6 +5,25 @@ export interface OcrBlock {
     tightBoundingBox: Rect;
     forcedOrientation?: 'vertical' | 'horizontal';
     isMerged?: boolean;
+    bubbleId?: number;
+    readingOrder?: number;
+    ruby?: OcrRuby[];
+    words?: OcrWordBox[];
+}
+
+/** Furigana over the chars `start..end` of its block's text. */
+export interface OcrRuby {
+    text: string;
+    start: number;
+    end: number;
+    tightBoundingBox: Rect;
+}
+
+/** Box of the chars `start..end` of its block's text. */
+export interface OcrWordBox {
+    start: number;
+    end: number;
+    tightBoundingBox: Rect;
 }
 
 export interface SiteConfig {
@@ -13,6 +13,7 @@ export const DEFAULT_SYNC_CONFIG: SyncConfig = {
     ln_metadata: true,
     ln_content: true,
     ln_files: false,
+    ocr_corrections: true,
     sync_on_chapter_read: false,
     sync_on_chapter_open: false,
     sync_on_app_start: false,
@@ -84,7 +85,7 @@ export const DELETION_BEHAVIOR_SELECT_VALUES: SelectSettingValue<DeletionBehavio
 );
 
 // What to sync options
-export const SYNC_DATA_OPTIONS: { key: keyof Pick<SyncConfig, 'ln_progress' | 'ln_metadata' | 'ln_content' | 'ln_files'>; labelKey: TranslationKey; descriptionKey?: TranslationKey; warning?: boolean }[] = [
+export const SYNC_DATA_OPTIONS: { key: keyof Pick<SyncConfig, 'ln_progress' | 'ln_metadata' | 'ln_content' | 'ln_files' | 'ocr_corrections'>; labelKey: TranslationKey; descriptionKey?: TranslationKey; warning?: boolean }[] = [
     {
         key: 'ln_progress',
         labelKey: 'sync.data.option.progress.label',
@@ -106,6 +107,11 @@ export const SYNC_DATA_OPTIONS: { key: keyof Pick<SyncConfig, 'ln_progress' | 'l
         descriptionKey: 'sync.data.option.files.description',
         warning: true,
     },
+    {
+        key: 'ocr_corrections',
+        labelKey: 'sync.data.option.ocr_corrections.label',
+        descriptionKey: 'sync.data.option.ocr_corrections.description',
+    },
 ];
 
 // Sync trigger options
@@ -70,6 +70,11 @@ export interface FileReference {
     driveFileId?: string;
 }
 
+export interface OcrPageCorrections {
+    edits: unknown[];
+    updatedAt: number;
+}
+
 export interface SyncPayload {
     schemaVersion: number;
     deviceId: string;
@@ -78,6 +83,7 @@ export interface SyncPayload {
     lnMetadata: Record<string, LNMetadata>;
     lnContent?: Record<string, LNParsedBook>;
     lnFiles?: Record<string, string>;
+    ocrCorrections?: Record<string, OcrPageCorrections>;
     deletedBookIds?: string[];
     deletedFileRefs?: FileReference[];
 }
@@ -93,6 +99,7 @@ export interface SyncConfig {
     ln_metadata: boolean;
     ln_content: boolean;
     ln_files: boolean;
+    ocr_corrections: boolean;
     sync_on_chapter_read: boolean;
     sync_on_chapter_open: boolean;
     sync_on_app_start: boolean;
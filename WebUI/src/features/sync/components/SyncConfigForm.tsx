@@ -12,7 +12,7 @@ import { useSync } from '../services/SyncContext';
 import { SyncConfig } from '../Sync.types';
 
 interface DataOption {
-    key: keyof Pick<SyncConfig, 'lnProgress' | 'lnMetadata' | 'lnContent' | 'lnFiles'>;
+    key: keyof Pick<SyncConfig, 'lnProgress' | 'lnMetadata' | 'lnContent' | 'lnFiles' | 'ocrCorrections'>;
     label: string;
     description: string;
     warning?: boolean;
@@ -40,6 +40,11 @@ const DATA_OPTIONS: DataOption[] = [
         description: 'Original EPUB files (can be very large!)',
         warning: true,
     },
+    {
+        key: 'ocrCorrections',
+        label: 'OCR Corrections',
+        description: 'Your fixes to manga OCR text and boxes',
+    },
 ];
 
 export function SyncConfigForm() {
@@ -1,9 +1,11 @@
 import { AppStorage, LNMetadata, LNProgress, LNParsedBook } from '@/lib/storage/AppStorage';
+import { apiRequest } from '@/Manatan/utils/api';
 import { SyncApi } from './SyncApi';
-import { SyncConfig, SyncPayload, MergeResponse, SyncProgress } from '../Sync.types';
+import { SyncConfig, SyncPayload, MergeResponse, SyncProgress, OcrPageCorrections } from '../Sync.types';
 
 const DEVICE_ID_KEY = 'manatan_device_id';
 const LAST_SYNC_KEY = 'manatan_last_sync';
+const OCR_CORRECTIONS_BASE = '/api/ocr/corrections';
 
 export class SyncService {
     private static deviceId: string | null = null;
@@ -246,6 +248,19 @@ export class SyncService {
             console.log('[SYNC] Collected %d files', Object.keys(payload.lnFiles || {}).length);
         }
 
+        // Collect OCR corrections
+        if (config.ocrCorrections) {
+            onProgress?.({ phase: 'collecting', message: 'Collecting OCR corrections...' });
+            try {
+                payload.ocrCorrections = await apiRequest<Record<string, OcrPageCorrections>>(
+                    `${OCR_CORRECTIONS_BASE}/export`,
+                );
+                console.log('[SYNC] Collected %d OCR corrections', Object.keys(payload.ocrCorrections).length);
+            } catch (e) {
+                console.warn('[SYNC] Failed to collect OCR corrections', e);
+            }
+        }
+
         console.log('[SYNC] ===== LOCAL DATA COLLECTION COMPLETE =====');
         console.log('[SYNC] Summary: %d progress, %d metadata, %d content, %d files',
             Object.keys(payload.lnProgress).length,
@@ -337,6 +352,15 @@ export class SyncService {
                 });
             }
         }
+
+        // Apply OCR corrections (the OCR server keeps whichever side is newer)
+        if (config.ocrCorrections && payload.ocrCorrections) {
+            onProgress?.({ phase: 'applying', message: 'Applying OCR corrections...' });
+            await apiRequest(`${OCR_CORRECTIONS_BASE}/import`, {
+                method: 'POST',
+                body: payload.ocrCorrections,
+            });
+        }
     }
 
     // ========================================================================
@@ -1080,8 +1080,10 @@ async fn run_server(
 
     info!("🌍 Starting Web Interface at http://{}:{}", host, port);
 
-    let ocr_router = manatan_ocr_server::create_router(data_dir.clone());
-    let yomitan_router = manatan_yomitan_server::create_router(data_dir.clone());
+    // The dictionary server reads the OCR cache directly, so both share one OCR state.
+    let ocr_state = manatan_ocr_server::state::AppState::new(data_dir.clone());
+    let ocr_router = manatan_ocr_server::create_router_with_state(ocr_state.clone());
+    let yomitan_router = manatan_yomitan_server::create_router(data_dir.clone(), ocr_state);
     let audio_router = manatan_audio_server::create_router(data_dir.clone());
     let sync_router = manatan_sync_server::create_router(data_dir.clone());
     let system_router = Router::new().route("/version", any(current_version_handler));
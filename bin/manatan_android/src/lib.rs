@@ -1167,8 +1167,10 @@ async fn start_web_server(
     let manatan_state = build_state(manatan_config).await?;
     let manatan_router = build_router_without_cors(manatan_state);
 
-    let ocr_router = manatan_ocr_server::create_router(data_dir.clone());
-    let yomitan_router = manatan_yomitan_server::create_router(data_dir.clone());
+    // The dictionary server reads the OCR cache directly, so both share one OCR state.
+    let ocr_state = manatan_ocr_server::state::AppState::new(data_dir.clone());
+    let ocr_router = manatan_ocr_server::create_router_with_state(ocr_state.clone());
+    let yomitan_router = manatan_yomitan_server::create_router(data_dir.clone(), ocr_state);
     let audio_router = manatan_audio_server::create_router(data_dir.clone());
 
     let cors = CorsLayer::new()
@@ -0,0 +1,463 @@
+//! Streamed cache archives for moving OCR results between devices.
+//!
+//! An archive is zstd-compressed NDJSON: the first line is an [`ArchiveManifest`], followed by
+//! one [`ArchiveEntry`] per cached page, one [`ArchiveCorrections`] per corrected page, and an
+//! [`ArchiveTrailer`] to close it. Both directions work line by line, so neither the server
+//! nor the client ever holds the whole cache in memory.
+
+use std::io::{BufRead, BufReader, Read, Write};
+
+use anyhow::{Context, bail};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    corrections::{PageCorrections, corrections_key},
+    language::OcrLanguage,
+    logic::{self, OcrResult, RawChunk},
+    merge::{MERGE_VERSION, MergeConfig},
+    search,
+    state::{AppState, now_unix},
+};
+
+pub const ARCHIVE_FORMAT: &str = "manatan-ocr-cache";
+/// Version 2 added the trailer and moved corrections to their own lines.
+pub const ARCHIVE_VERSION: u32 = 2;
+
+/// Entries written per transaction while importing.
+const IMPORT_BATCH_SIZE: usize = 500;
+
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchiveManifest {
+    pub format: String,
+    pub version: u32,
+    /// [`MERGE_VERSION`] of the exporting server.
+    pub merge_version: u32,
+    pub created_at: i64,
+    #[serde(default)]
+    pub filter: ArchiveFilter,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchiveEntry {
+    pub cache_key: String,
+    pub context: String,
+    pub data: Vec<OcrResult>,
+    pub created_at: i64,
+    pub last_processed_at: i64,
+    /// Engine output the page was merged from, when the exporting server kept it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_chunks: Option<Vec<RawChunk>>,
+    #[serde(default)]
+    pub merge_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_config: Option<MergeConfig>,
+    /// Language the engine was asked for, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_language: Option<String>,
+    /// Engine that recognized the page; unset for pages cached before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine: Option<String>,
+}
+
+/// User corrections of one page. They are exported whether or not the page itself is cached,
+/// and apply under every merge setting of it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchiveCorrections {
+    /// Cache key of the page without its merge suffix.
+    pub corrections_for: String,
+    #[serde(flatten)]
+    pub corrections: PageCorrections,
+}
+
+/// Last line of an archive. An export that fails halfway still reaches the client as a
+/// well-formed zstd stream, so a missing trailer is how the importer tells it was cut short.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchiveTrailer {
+    pub end_of_archive: bool,
+    /// Lines of each kind written before the trailer.
+    pub entries: usize,
+    pub corrections: usize,
+}
+
+/// Restricts an export or import to one series and/or language. Empty matches everything.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct ArchiveFilter {
+    /// Suwayomi manga id.
+    pub series: Option<String>,
+    pub language: Option<OcrLanguage>,
+}
+
+impl ArchiveFilter {
+    pub fn matches(&self, cache_key: &str) -> bool {
+        let series_ok = self
+            .series
+            .as_deref()
+            .is_none_or(|series| logic::cache_key_series(cache_key) == Some(series));
+        let language_ok = self
+            .language
+            .is_none_or(|language| logic::cache_key_language(cache_key) == Some(language.as_str()));
+        series_ok && language_ok
+    }
+}
+
+/// What to do with entries that already exist locally.
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportPolicy {
+    /// Keep whichever side was OCR'd more recently.
+    #[default]
+    Merge,
+    /// The archive always wins.
+    Overwrite,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportReport {
+    pub manifest: ArchiveManifest,
+    pub imported: usize,
+    /// Entries filtered out or kept at their local version.
+    pub skipped: usize,
+    /// Pages whose corrections were taken from the archive.
+    pub corrections: usize,
+    /// Lines that could not be parsed.
+    pub invalid: usize,
+}
+
+impl AppState {
+    /// Writes every matching cache entry and page's corrections to `writer` as a compressed
+    /// archive. Returns the number of cache entries written.
+    pub fn export_archive<W: Write>(
+        &self,
+        writer: W,
+        filter: &ArchiveFilter,
+    ) -> anyhow::Result<usize> {
+        let conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection for export_archive")?;
+        let mut encoder = zstd::Encoder::new(writer, ZSTD_LEVEL)?;
+
+        let manifest = ArchiveManifest {
+            format: ARCHIVE_FORMAT.to_string(),
+            version: ARCHIVE_VERSION,
+            merge_version: MERGE_VERSION,
+            created_at: now_unix(),
+            filter: filter.clone(),
+        };
+        serde_json::to_writer(&mut encoder, &manifest)?;
+        encoder.write_all(b"\n")?;
+
+        let mut stmt = conn.prepare(
+            "SELECT c.cache_key, c.context, c.data, c.created_at, c.last_processed_at,
+                    c.raw_chunks, c.merge_version, c.merge_config, c.ocr_language, c.engine
+             FROM ocr_cache c",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut written = 0;
+        while let Some(row) = rows.next()? {
+            let cache_key: String = row.get(0)?;
+            if !filter.matches(&cache_key) {
+                continue;
+            }
+            let data_blob: Vec<u8> = row.get(2)?;
+            let entry = ArchiveEntry {
+                cache_key,
+                context: row.get(1)?,
+                data: serde_json::from_slice(&data_blob).unwrap_or_default(),
+                created_at: row.get(3)?,
+                last_processed_at: row.get(4)?,
+                raw_chunks: row
+                    .get::<_, Option<Vec<u8>>>(5)?
+                    .and_then(|blob| serde_json::from_slice(&blob).ok()),
+                merge_version: row.get(6)?,
+                merge_config: row
+                    .get::<_, Option<String>>(7)?
+                    .and_then(|json| serde_json::from_str(&json).ok()),
+                ocr_language: row.get(8)?,
+                engine: row.get(9)?,
+            };
+            serde_json::to_writer(&mut encoder, &entry)?;
+            encoder.write_all(b"\n")?;
+            written += 1;
+        }
+
+        let mut stmt = conn.prepare("SELECT cache_key, edits, updated_at FROM ocr_corrections")?;
+        let mut rows = stmt.query([])?;
+        let mut corrections_written = 0;
+        while let Some(row) = rows.next()? {
+            let cache_key: String = row.get(0)?;
+            if !filter.matches(&cache_key) {
+                continue;
+            }
+            let Ok(edits) = serde_json::from_str(&row.get::<_, String>(1)?) else {
+                continue;
+            };
+            let corrections = ArchiveCorrections {
+                corrections_for: cache_key,
+                corrections: PageCorrections {
+                    edits,
+                    updated_at: row.get(2)?,
+                },
+            };
+            serde_json::to_writer(&mut encoder, &corrections)?;
+            encoder.write_all(b"\n")?;
+            corrections_written += 1;
+        }
+
+        serde_json::to_writer(
+            &mut encoder,
+            &ArchiveTrailer {
+                end_of_archive: true,
+                entries: written,
+                corrections: corrections_written,
+            },
+        )?;
+        encoder.write_all(b"\n")?;
+        encoder.finish()?.flush()?;
+        Ok(written)
+    }
+
+    /// Reads an archive produced by [`AppState::export_archive`], committing in batches. An
+    /// archive without its trailer fails the import, though the batches before the cut stay
+    /// imported.
+    pub fn import_archive<R: Read>(
+        &self,
+        reader: R,
+        policy: ImportPolicy,
+        filter: &ArchiveFilter,
+    ) -> anyhow::Result<ImportReport> {
+        let mut lines = BufReader::new(zstd::Decoder::new(reader)?).lines();
+
+        let manifest_line = lines.next().context("Archive is empty")??;
+        let manifest: ArchiveManifest =
+            serde_json::from_str(&manifest_line).context("Archive manifest is malformed")?;
+        if manifest.format != ARCHIVE_FORMAT {
+            bail!("Not an OCR cache archive: {}", manifest.format);
+        }
+        if manifest.version > ARCHIVE_VERSION {
+            bail!(
+                "Archive version {} is newer than supported version {ARCHIVE_VERSION}",
+                manifest.version
+            );
+        }
+        if manifest.merge_version != MERGE_VERSION {
+            warn!(
+                "Importing OCR cache merged with version {} (current {MERGE_VERSION})",
+                manifest.merge_version
+            );
+        }
+
+        let mut report = ImportReport {
+            manifest,
+            imported: 0,
+            skipped: 0,
+            corrections: 0,
+            invalid: 0,
+        };
+        let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+        let mut corrections_batch = Vec::new();
+        let (mut entries_read, mut corrections_read) = (0, 0);
+        let mut trailer = None;
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Ok(end) = serde_json::from_str::<ArchiveTrailer>(&line) {
+                trailer = Some(end);
+                break;
+            }
+            if let Ok(corrections) = serde_json::from_str::<ArchiveCorrections>(&line) {
+                corrections_read += 1;
+                if filter.matches(&corrections.corrections_for) {
+                    corrections_batch.push(corrections);
+                }
+                if corrections_batch.len() >= IMPORT_BATCH_SIZE {
+                    self.import_corrections_batch(&mut corrections_batch, policy, &mut report)?;
+                }
+                continue;
+            }
+            entries_read += 1;
+            let entry: ArchiveEntry = match serde_json::from_str(&line) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn!("Skipping malformed archive entry: {err}");
+                    report.invalid += 1;
+                    continue;
+                }
+            };
+            if !filter.matches(&entry.cache_key) {
+                report.skipped += 1;
+                continue;
+            }
+            batch.push(entry);
+            if batch.len() >= IMPORT_BATCH_SIZE {
+                self.import_batch(&mut batch, policy, &mut report)?;
+            }
+        }
+        self.import_batch(&mut batch, policy, &mut report)?;
+        self.import_corrections_batch(&mut corrections_batch, policy, &mut report)?;
+
+        // Archives from before the trailer existed end without one.
+        if report.manifest.version >= 2 {
+            match trailer {
+                None => bail!("Archive is incomplete: it ends after {entries_read} entries"),
+                Some(trailer)
+                    if trailer.entries != entries_read
+                        || trailer.corrections != corrections_read =>
+                {
+                    bail!(
+                        "Archive is incomplete: read {entries_read} of {} entries and \
+                         {corrections_read} of {} corrected pages",
+                        trailer.entries,
+                        trailer.corrections
+                    )
+                }
+                Some(_) => {}
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn import_batch(
+        &self,
+        batch: &mut Vec<ArchiveEntry>,
+        policy: ImportPolicy,
+        report: &mut ImportReport,
+    ) -> anyhow::Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection for import_archive")?;
+        let tx = conn.transaction()?;
+        let sql = match policy {
+            ImportPolicy::Merge => {
+                "INSERT INTO ocr_cache
+                    (cache_key, context, data, created_at, last_processed_at, last_accessed_at, access_count,
+                     raw_chunks, merge_version, merge_config, ocr_language, engine)
+                 VALUES (?, ?, ?, ?, ?, ?, 0, ?, ?, ?, ?, ?)
+                 ON CONFLICT(cache_key) DO UPDATE SET
+                    context = excluded.context,
+                    data = excluded.data,
+                    last_processed_at = excluded.last_processed_at,
+                    raw_chunks = excluded.raw_chunks,
+                    merge_version = excluded.merge_version,
+                    merge_config = excluded.merge_config,
+                    ocr_language = excluded.ocr_language,
+                    engine = excluded.engine
+                 WHERE excluded.last_processed_at > ocr_cache.last_processed_at"
+            }
+            ImportPolicy::Overwrite => {
+                "INSERT INTO ocr_cache
+                    (cache_key, context, data, created_at, last_processed_at, last_accessed_at, access_count,
+                     raw_chunks, merge_version, merge_config, ocr_language, engine)
+                 VALUES (?, ?, ?, ?, ?, ?, 0, ?, ?, ?, ?, ?)
+                 ON CONFLICT(cache_key) DO UPDATE SET
+                    context = excluded.context,
+                    data = excluded.data,
+                    last_processed_at = excluded.last_processed_at,
+                    raw_chunks = excluded.raw_chunks,
+                    merge_version = excluded.merge_version,
+                    merge_config = excluded.merge_config,
+                    ocr_language = excluded.ocr_language,
+                    engine = excluded.engine"
+            }
+        };
+        let now = now_unix();
+        {
+            let mut stmt = tx.prepare(sql)?;
+            for entry in batch.drain(..) {
+                let data_blob = serde_json::to_vec(&entry.data)?;
+                let raw_blob = entry
+                    .raw_chunks
+                    .as_ref()
+                    .map(serde_json::to_vec)
+                    .transpose()?;
+                let merge_config = entry
+                    .merge_config
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?;
+                let changes = stmt.execute(params![
+                    entry.cache_key,
+                    entry.context,
+                    data_blob,
+                    entry.created_at,
+                    entry.last_processed_at,
+                    now,
+                    raw_blob,
+                    entry.merge_version,
+                    merge_config,
+                    entry.ocr_language,
+                    entry.engine,
+                ])?;
+                if changes > 0 {
+                    report.imported += 1;
+                    if let Err(err) = search::index_page(&tx, &entry.cache_key, &entry.data) {
+                        search::index_failed(&tx, &entry.cache_key, &err);
+                    }
+                } else {
+                    report.skipped += 1;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn import_corrections_batch(
+        &self,
+        batch: &mut Vec<ArchiveCorrections>,
+        policy: ImportPolicy,
+        report: &mut ImportReport,
+    ) -> anyhow::Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection for import_archive")?;
+        let tx = conn.transaction()?;
+        let sql = match policy {
+            ImportPolicy::Merge => {
+                "INSERT INTO ocr_corrections (cache_key, edits, updated_at) VALUES (?, ?, ?)
+                 ON CONFLICT(cache_key) DO UPDATE SET
+                    edits = excluded.edits,
+                    updated_at = excluded.updated_at
+                 WHERE excluded.updated_at > ocr_corrections.updated_at"
+            }
+            ImportPolicy::Overwrite => {
+                "INSERT OR REPLACE INTO ocr_corrections (cache_key, edits, updated_at)
+                 VALUES (?, ?, ?)"
+            }
+        };
+        {
+            let mut stmt = tx.prepare(sql)?;
+            for page in batch.drain(..) {
+                let key = corrections_key(&page.corrections_for);
+                let changes = stmt.execute(params![
+                    key,
+                    serde_json::to_string(&page.corrections.edits)?,
+                    page.corrections.updated_at,
+                ])?;
+                if changes > 0
+                    && let Err(err) = search::reindex_corrected(&tx, key)
+                {
+                    search::index_failed(&tx, key, &err);
+                }
+                report.corrections += changes;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
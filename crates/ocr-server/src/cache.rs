@@ -0,0 +1,445 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::Duration,
+};
+
+use rusqlite::{Connection, ToSql, params};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    language::OcrLanguage,
+    logic,
+    state::{AppState, now_unix},
+};
+
+/// How often the background task checks the cache budget.
+const EVICTION_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// When over the size budget, evict down to this fraction of it so we don't run again after
+/// the next few inserts.
+const EVICTION_TARGET_RATIO: f64 = 0.9;
+
+/// Approximate on-disk size of a cache row.
+const ENTRY_SIZE_SQL: &str =
+    "length(data) + length(cache_key) + length(context) + ifnull(length(raw_chunks), 0)";
+
+#[derive(Serialize, Default, Clone, Copy, Debug)]
+pub struct CacheUsage {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+impl CacheUsage {
+    fn add(&mut self, bytes: u64) {
+        self.entries += 1;
+        self.bytes += bytes;
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct CacheStats {
+    pub total: CacheUsage,
+    pub by_language: BTreeMap<String, CacheUsage>,
+    pub by_series: BTreeMap<String, CacheUsage>,
+    pub max_bytes: Option<u64>,
+    pub max_age_days: Option<u64>,
+    pub pinned_chapters: Vec<String>,
+    /// Pages recognized with another language than requested; see
+    /// [`PurgeTarget::LanguageMismatch`].
+    pub language_mismatches: usize,
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct EvictionReport {
+    pub evicted: usize,
+    pub freed_bytes: u64,
+}
+
+impl AppState {
+    pub fn pin_chapter(&self, chapter_key: &str) {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for pin_chapter");
+            return;
+        };
+        let _ = conn.execute(
+            "INSERT OR IGNORE INTO pinned_chapters (chapter_key, created_at) VALUES (?, ?)",
+            params![chapter_key, now_unix()],
+        );
+    }
+
+    pub fn unpin_chapter(&self, chapter_key: &str) -> bool {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for unpin_chapter");
+            return false;
+        };
+        conn.execute(
+            "DELETE FROM pinned_chapters WHERE chapter_key = ?",
+            params![chapter_key],
+        )
+        .map(|changed| changed > 0)
+        .unwrap_or(false)
+    }
+
+    pub fn pinned_chapters(&self) -> Vec<String> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for pinned_chapters");
+            return Vec::new();
+        };
+        let Ok(mut stmt) =
+            conn.prepare("SELECT chapter_key FROM pinned_chapters ORDER BY chapter_key")
+        else {
+            return Vec::new();
+        };
+        stmt.query_map([], |row| row.get(0))
+            .map(|rows| rows.flatten().collect())
+            .unwrap_or_default()
+    }
+
+    /// Cache keys that must survive eviction: pages of pinned chapters and of chapters that
+    /// have a job in the queue.
+    fn protected_cache_keys(&self) -> HashSet<String> {
+        let mut protected = HashSet::new();
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for protected_cache_keys");
+            return protected;
+        };
+
+        if let Ok(mut stmt) = conn.prepare(
+            "SELECT cache_key FROM chapter_cache
+             WHERE chapter_key IN (SELECT chapter_key FROM pinned_chapters)
+                OR chapter_key IN (SELECT job_id FROM ocr_jobs)",
+        ) && let Ok(rows) = stmt.query_map([], |row| row.get::<_, String>(0))
+        {
+            protected.extend(rows.flatten());
+        }
+
+        // Pages already cached before their job reached them are not linked to the chapter
+        // yet, so protect them by URL as well.
+        for job in self.list_jobs() {
+            for page in self.get_job_pages(&job.job_id) {
+                protected.insert(logic::get_merge_cache_key(&page.url, &job.merge_config));
+            }
+        }
+
+        protected
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        let config = self.config();
+        let mut stats = CacheStats {
+            total: CacheUsage::default(),
+            by_language: BTreeMap::new(),
+            by_series: BTreeMap::new(),
+            max_bytes: config.cache_max_bytes,
+            max_age_days: config.cache_max_age_days,
+            pinned_chapters: self.pinned_chapters(),
+            language_mismatches: 0,
+        };
+
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for cache_stats");
+            return stats;
+        };
+        let Ok(mut stmt) = conn.prepare(&format!(
+            "SELECT cache_key, {ENTRY_SIZE_SQL}, ocr_language FROM ocr_cache"
+        )) else {
+            return stats;
+        };
+        let Ok(rows) = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        }) else {
+            return stats;
+        };
+
+        for (cache_key, size, ocr_language) in rows.flatten() {
+            if is_language_mismatch(&cache_key, ocr_language.as_deref()) {
+                stats.language_mismatches += 1;
+            }
+            let size = size.max(0) as u64;
+            stats.total.add(size);
+            let language = logic::cache_key_language(&cache_key).unwrap_or("unknown");
+            stats
+                .by_language
+                .entry(language.to_string())
+                .or_default()
+                .add(size);
+            let series = logic::cache_key_series(&cache_key).unwrap_or("unknown");
+            stats
+                .by_series
+                .entry(series.to_string())
+                .or_default()
+                .add(size);
+        }
+
+        stats
+    }
+
+    /// Applies the configured age and size budgets, least recently read pages first.
+    pub fn evict_cache(&self) -> EvictionReport {
+        let config = self.config();
+        let mut report = EvictionReport::default();
+        if config.cache_max_bytes.is_none() && config.cache_max_age_days.is_none() {
+            return report;
+        }
+
+        let protected = self.protected_cache_keys();
+        let Ok(mut conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for evict_cache");
+            return report;
+        };
+
+        let candidates: Vec<(String, u64, i64)> = {
+            let Ok(mut stmt) = conn.prepare(&format!(
+                "SELECT cache_key, {ENTRY_SIZE_SQL}, last_accessed_at FROM ocr_cache
+                 ORDER BY last_accessed_at ASC, access_count ASC"
+            )) else {
+                return report;
+            };
+            stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?.max(0) as u64,
+                    row.get::<_, i64>(2)?,
+                ))
+            })
+            .map(|rows| rows.flatten().collect())
+            .unwrap_or_default()
+        };
+
+        let mut remaining: u64 = candidates.iter().map(|(_, size, _)| size).sum();
+        let target = config
+            .cache_max_bytes
+            .map(|max| (max as f64 * EVICTION_TARGET_RATIO) as u64);
+        let over_budget = config.cache_max_bytes.is_some_and(|max| remaining > max);
+        let cutoff = config
+            .cache_max_age_days
+            .map(|days| now_unix() - (days as i64) * 24 * 60 * 60);
+
+        let mut evict = Vec::new();
+        for (cache_key, size, last_accessed_at) in candidates {
+            if protected.contains(&cache_key) {
+                continue;
+            }
+            let too_old = cutoff.is_some_and(|cutoff| last_accessed_at < cutoff);
+            let too_big = over_budget && target.is_some_and(|target| remaining > target);
+            if !too_old && !too_big {
+                // Rows are oldest first, so nothing after this one qualifies either.
+                break;
+            }
+            remaining = remaining.saturating_sub(size);
+            report.freed_bytes += size;
+            evict.push(cache_key);
+        }
+
+        if evict.is_empty() {
+            return report;
+        }
+
+        match remove_cache_entries(&mut conn, &evict, &[]) {
+            Ok(evicted) => report.evicted = evicted,
+            Err(err) => {
+                warn!("Failed to evict OCR cache entries: {err}");
+                return EvictionReport::default();
+            }
+        }
+        report
+    }
+
+    /// Removes the cached pages selected by `target`, together with the chapter bookkeeping
+    /// that refers to them, so the affected chapters get processed again on the next request.
+    pub fn purge_cache(&self, target: &PurgeTarget) -> PurgeReport {
+        let Ok(mut conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for purge_cache");
+            return PurgeReport::default();
+        };
+
+        let (cache_keys, chapter_keys) = match target {
+            PurgeTarget::Page(cache_key) => (vec![cache_key.clone()], Vec::new()),
+            PurgeTarget::Chapter(chapter_key) => (
+                query_strings(
+                    &conn,
+                    "SELECT cache_key FROM chapter_cache WHERE chapter_key = ?",
+                    params![chapter_key],
+                ),
+                vec![chapter_key.clone()],
+            ),
+            PurgeTarget::OlderThan(timestamp) => (
+                query_strings(
+                    &conn,
+                    "SELECT cache_key FROM ocr_cache WHERE last_processed_at < ?",
+                    params![timestamp],
+                ),
+                Vec::new(),
+            ),
+            PurgeTarget::LanguageMismatch => {
+                let cache_keys = {
+                    let Ok(mut stmt) =
+                        conn.prepare("SELECT cache_key, ocr_language FROM ocr_cache")
+                    else {
+                        return PurgeReport::default();
+                    };
+                    stmt.query_map([], |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+                    })
+                    .map(|rows| {
+                        rows.flatten()
+                            .filter(|(key, ocr_language)| {
+                                is_language_mismatch(key, ocr_language.as_deref())
+                            })
+                            .map(|(key, _)| key)
+                            .collect()
+                    })
+                    .unwrap_or_default()
+                };
+                (cache_keys, Vec::new())
+            }
+            PurgeTarget::Series(_) | PurgeTarget::Language(_) => {
+                let mut cache_keys =
+                    query_strings(&conn, "SELECT cache_key FROM ocr_cache", params![]);
+                cache_keys.retain(|key| target.matches(key));
+                let mut chapter_keys = query_strings(
+                    &conn,
+                    "SELECT chapter_key FROM chapter_pages
+                     UNION SELECT chapter_key FROM chapter_cache",
+                    params![],
+                );
+                chapter_keys.retain(|key| target.matches(key));
+                (cache_keys, chapter_keys)
+            }
+        };
+
+        match remove_cache_entries(&mut conn, &cache_keys, &chapter_keys) {
+            Ok(entries) => PurgeReport {
+                entries,
+                chapters: chapter_keys.len(),
+            },
+            Err(err) => {
+                warn!("Failed to purge OCR cache entries: {err}");
+                PurgeReport::default()
+            }
+        }
+    }
+}
+
+/// Selects what [`AppState::purge_cache`] removes.
+#[derive(Debug, Clone)]
+pub enum PurgeTarget {
+    /// A single page, e.g. one whose image has changed since it was OCR'd.
+    Page(String),
+    /// Every page recorded for a chapter key in `chapter_cache`.
+    Chapter(String),
+    /// Every page of a Suwayomi manga id, in any language.
+    Series(String),
+    /// Every page under the `lang/<language>/` key prefix.
+    Language(OcrLanguage),
+    /// Pages last OCR'd before this unix timestamp.
+    OlderThan(i64),
+    /// Pages the engine recognized with a different language than their key asks for.
+    LanguageMismatch,
+}
+
+impl PurgeTarget {
+    fn matches(&self, key: &str) -> bool {
+        match self {
+            PurgeTarget::Chapter(chapter_key) => key == chapter_key,
+            PurgeTarget::Series(series) => logic::cache_key_series(key) == Some(series.as_str()),
+            PurgeTarget::Language(language) => {
+                logic::cache_key_language(key) == Some(language.as_str())
+            }
+            PurgeTarget::Page(_) | PurgeTarget::OlderThan(_) | PurgeTarget::LanguageMismatch => {
+                false
+            }
+        }
+    }
+}
+
+/// Whether a page was OCR'd with another language than its cache key says. Pages cached
+/// before the language was recorded were always recognized as Japanese.
+fn is_language_mismatch(cache_key: &str, ocr_language: Option<&str>) -> bool {
+    let Some(key_language) = logic::cache_key_language(cache_key) else {
+        return false;
+    };
+    key_language != ocr_language.unwrap_or(OcrLanguage::Japanese.as_str())
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct PurgeReport {
+    pub entries: usize,
+    pub chapters: usize,
+}
+
+fn query_strings(conn: &Connection, sql: &str, params: &[&dyn ToSql]) -> Vec<String> {
+    let Ok(mut stmt) = conn.prepare(sql) else {
+        warn!("Failed to prepare cache query: {sql}");
+        return Vec::new();
+    };
+    stmt.query_map(params, |row| row.get(0))
+        .map(|rows| rows.flatten().collect())
+        .unwrap_or_default()
+}
+
+/// Deletes `cache_keys` from `ocr_cache` and forgets `chapter_keys`. Chapters that lose a page
+/// also lose their `chapter_pages` row, since its processed count no longer holds.
+fn remove_cache_entries(
+    conn: &mut Connection,
+    cache_keys: &[String],
+    chapter_keys: &[String],
+) -> rusqlite::Result<usize> {
+    let tx = conn.transaction()?;
+    let mut removed = 0;
+    let mut affected: HashSet<String> = chapter_keys.iter().cloned().collect();
+    {
+        let mut chapters_of =
+            tx.prepare("SELECT chapter_key FROM chapter_cache WHERE cache_key = ?")?;
+        for cache_key in cache_keys {
+            affected.extend(
+                chapters_of
+                    .query_map(params![cache_key], |row| row.get::<_, String>(0))?
+                    .flatten(),
+            );
+            removed += tx.execute(
+                "DELETE FROM ocr_cache WHERE cache_key = ?",
+                params![cache_key],
+            )?;
+            tx.execute(
+                "DELETE FROM chapter_cache WHERE cache_key = ?",
+                params![cache_key],
+            )?;
+        }
+    }
+    for chapter_key in chapter_keys {
+        tx.execute(
+            "DELETE FROM chapter_cache WHERE chapter_key = ?",
+            params![chapter_key],
+        )?;
+    }
+    for chapter_key in &affected {
+        tx.execute(
+            "DELETE FROM chapter_pages WHERE chapter_key = ?",
+            params![chapter_key],
+        )?;
+    }
+    tx.commit()?;
+    Ok(removed)
+}
+
+/// Background task that keeps the cache inside its configured budget.
+pub async fn run_eviction(state: AppState) {
+    loop {
+        let worker_state = state.clone();
+        match tokio::task::spawn_blocking(move || worker_state.evict_cache()).await {
+            Ok(report) if report.evicted > 0 => info!(
+                "Evicted {} OCR cache entries ({} bytes)",
+                report.evicted, report.freed_bytes
+            ),
+            Ok(_) => {}
+            Err(err) => warn!("OCR cache eviction task failed: {err}"),
+        }
+        tokio::time::sleep(EVICTION_INTERVAL).await;
+    }
+}
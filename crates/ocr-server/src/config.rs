@@ -0,0 +1,78 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::engine::LENS_ENGINE_ID;
+
+/// Name of the [`FileConfig`] file in the data directory.
+pub const CONFIG_FILE_NAME: &str = "ocr-config.json";
+
+/// Runtime settings for the OCR server, persisted as JSON in the `metadata` table.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct OcrConfig {
+    /// Engine used when a request does not name one.
+    pub default_engine: String,
+    /// Upper bound for the OCR cache in bytes. Least recently read pages are evicted first.
+    pub cache_max_bytes: Option<u64>,
+    /// Pages that have not been read for this many days are evicted.
+    pub cache_max_age_days: Option<u64>,
+    /// How many pages may be with the OCR engine at once, across readers and jobs.
+    pub ocr_concurrency: usize,
+    /// Most calls each OCR engine gets per minute, across readers and jobs. Unlimited when
+    /// unset; rate-limit responses still pause the engine either way.
+    pub engine_requests_per_minute: Option<u32>,
+    /// Chapters after the one being read that are OCR'd ahead of time. `0` turns prefetching
+    /// off; series can override this.
+    pub prefetch_chapters: usize,
+}
+
+impl Default for OcrConfig {
+    fn default() -> Self {
+        Self {
+            default_engine: LENS_ENGINE_ID.to_string(),
+            // Phones have little room to spare, desktops keep everything unless told otherwise.
+            cache_max_bytes: if cfg!(target_os = "android") {
+                Some(512 * 1024 * 1024)
+            } else {
+                None
+            },
+            cache_max_age_days: None,
+            ocr_concurrency: if cfg!(target_os = "android") { 2 } else { 6 },
+            engine_requests_per_minute: None,
+            prefetch_chapters: if cfg!(target_os = "android") { 1 } else { 2 },
+        }
+    }
+}
+
+/// Settings read from [`CONFIG_FILE_NAME`] at startup. They expose the machine the server
+/// runs on, so unlike [`OcrConfig`] they cannot be changed over HTTP.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct FileConfig {
+    /// Directories local files may be OCR'd from. Local files are refused while this is empty.
+    pub library_roots: Vec<PathBuf>,
+}
+
+impl FileConfig {
+    /// Reads the file from `data_dir`. A missing or unreadable file means the defaults.
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(CONFIG_FILE_NAME);
+        let raw = match std::fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Self::default(),
+            Err(err) => {
+                warn!("Failed to read {}: {err}", path.display());
+                return Self::default();
+            }
+        };
+        serde_json::from_str(&raw).unwrap_or_else(|err| {
+            warn!("Failed to parse {}: {err}", path.display());
+            Self::default()
+        })
+    }
+}
@@ -0,0 +1,257 @@
+//! Indexing cached pages by a hash of their image as well as by URL.
+//!
+//! The same page reached through another source, mirror or chapter id is found by its hash
+//! and copied instead of being OCR'd again. Cache hits are re-checked against the image now
+//! and then, and a page whose image changed is dropped so the next read OCRs it afresh.
+
+use rusqlite::params;
+use tracing::{info, warn};
+
+use crate::{
+    cache::PurgeTarget,
+    corrections::corrections_key,
+    engine::{EngineContext, LENS_ENGINE_ID, OcrEngine},
+    logic::{self, OcrResult},
+    merge::MergeConfig,
+    scheduler::{OcrPriority, SharedPriority},
+    search,
+    sources::content_hash,
+    state::{AppState, now_unix},
+};
+
+/// How long a cache hit is trusted before its image is fetched again and compared.
+const CONTENT_RECHECK_SECS: i64 = 6 * 60 * 60;
+
+/// Image re-checks running at once. Hits beyond that are checked on a later read.
+pub(crate) const MAX_REVALIDATIONS: usize = 2;
+
+/// A page to OCR: where its image lives, the key its results are cached under and the
+/// context stored with them.
+#[derive(Clone, Copy)]
+pub struct PageTarget<'a> {
+    pub url: &'a str,
+    pub cache_key: &'a str,
+    pub context: &'a str,
+}
+
+/// Merge-settings suffix of a cache key (empty for the defaults).
+fn merge_suffix(cache_key: &str) -> &str {
+    &cache_key[logic::strip_merge_suffix(cache_key).len()..]
+}
+
+impl AppState {
+    /// Fetches `page.url` and OCRs it into `page.cache_key`, unless a page with the same image
+    /// was already OCR'd with the same language and merge settings under another key.
+    /// Concurrent calls for the same key share one OCR run, which waits for a slot in the
+    /// scheduler at the most urgent of their priorities.
+    pub async fn ocr_url(
+        &self,
+        page: &PageTarget<'_>,
+        engine: &dyn OcrEngine,
+        ctx: &EngineContext,
+        merge_config: &MergeConfig,
+        priority: OcrPriority,
+    ) -> anyhow::Result<Vec<OcrResult>> {
+        self.in_flight
+            .run(page.cache_key, priority, |priority| {
+                self.ocr_url_uncoalesced(page, engine, ctx, merge_config, priority)
+            })
+            .await
+    }
+
+    async fn ocr_url_uncoalesced(
+        &self,
+        page: &PageTarget<'_>,
+        engine: &dyn OcrEngine,
+        ctx: &EngineContext,
+        merge_config: &MergeConfig,
+        priority: SharedPriority,
+    ) -> anyhow::Result<Vec<OcrResult>> {
+        let PageTarget {
+            url,
+            cache_key,
+            context,
+        } = *page;
+
+        // A run for this key may have finished between the caller's cache check and now.
+        if let Some(entry) = self.get_cache_entry_from(cache_key, Some(engine.id())) {
+            return Ok(entry.data);
+        }
+
+        let (user, pass) = (ctx.user.as_deref(), ctx.pass.as_deref());
+        let image_bytes = logic::with_retries(url, || logic::fetch_image(url, user, pass)).await?;
+
+        let hash = content_hash(&image_bytes);
+        if let Some(results) = self.reuse_content(&hash, cache_key, context, engine.id()) {
+            return Ok(results);
+        }
+
+        let page = logic::with_retries(url, || async {
+            let _permit = self.scheduler.acquire(&priority).await;
+            logic::process_image(
+                &image_bytes,
+                engine,
+                ctx.user.clone(),
+                ctx.pass.clone(),
+                merge_config,
+            )
+            .await
+        })
+        .await?;
+        self.insert_ocr_page(cache_key, context, &page);
+        Ok(page.results)
+    }
+
+    /// Copies a cached page with image `hash` to `cache_key`, along with its corrections.
+    /// Only pages recognized by `engine` in the same language with the same merge settings
+    /// qualify.
+    fn reuse_content(
+        &self,
+        hash: &str,
+        cache_key: &str,
+        context: &str,
+        engine: &str,
+    ) -> Option<Vec<OcrResult>> {
+        let source = {
+            let Ok(conn) = self.pool.get() else {
+                warn!("Failed to get DB connection for reuse_content");
+                return None;
+            };
+            let mut stmt = conn
+                .prepare(
+                    "SELECT cache_key FROM ocr_cache
+                     WHERE content_hash = ? AND cache_key != ? AND COALESCE(engine, ?) = ?",
+                )
+                .ok()?;
+            let candidates: Vec<String> = stmt
+                .query_map(params![hash, cache_key, LENS_ENGINE_ID, engine], |row| {
+                    row.get(0)
+                })
+                .map(|rows| rows.flatten().collect())
+                .unwrap_or_default();
+            let source = candidates.into_iter().find(|candidate| {
+                logic::cache_key_language(candidate) == logic::cache_key_language(cache_key)
+                    && merge_suffix(candidate) == merge_suffix(cache_key)
+            })?;
+
+            let now = now_unix();
+            conn.execute(
+                "INSERT OR REPLACE INTO ocr_cache
+                    (cache_key, context, data, created_at, last_processed_at, last_accessed_at,
+                     access_count, raw_chunks, merge_version, merge_config, ocr_language,
+                     content_hash, hash_checked_at, engine)
+                 SELECT ?, ?, data, ?, last_processed_at, ?,
+                     1, raw_chunks, merge_version, merge_config, ocr_language,
+                     content_hash, ?, engine
+                 FROM ocr_cache WHERE cache_key = ?",
+                params![cache_key, context, now, now, now, source],
+            )
+            .ok()?;
+            let _ = conn.execute(
+                "INSERT OR IGNORE INTO ocr_corrections (cache_key, edits, updated_at)
+                 SELECT ?, edits, updated_at FROM ocr_corrections WHERE cache_key = ?",
+                params![corrections_key(cache_key), corrections_key(&source)],
+            );
+            // Indexed after the corrections, which may also apply to the page's other merge
+            // settings.
+            let key = corrections_key(cache_key);
+            if let Err(err) = search::reindex_corrected(&conn, key) {
+                search::index_failed(&conn, key, &err);
+            }
+            source
+        };
+        info!("OCR cache entry {cache_key} shares its image with {source}");
+        self.get_cache_entry(cache_key).map(|entry| entry.data)
+    }
+
+    /// Claims the periodic image check of `cache_key` when it is due. Returns the stored hash
+    /// (`None` for pages cached before hashes were kept), or `None` when no check is due.
+    fn claim_content_check(&self, cache_key: &str) -> Option<Option<String>> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for claim_content_check");
+            return None;
+        };
+        let now = now_unix();
+        let claimed = conn
+            .execute(
+                "UPDATE ocr_cache SET hash_checked_at = ?
+                 WHERE cache_key = ? AND (hash_checked_at IS NULL OR hash_checked_at < ?)",
+                params![now, cache_key, now - CONTENT_RECHECK_SECS],
+            )
+            .unwrap_or(0);
+        if claimed == 0 {
+            return None;
+        }
+        conn.query_row(
+            "SELECT content_hash FROM ocr_cache WHERE cache_key = ?",
+            params![cache_key],
+            |row| row.get(0),
+        )
+        .ok()
+    }
+
+    /// Re-checks the image behind a cache hit in the background when its check is due. Only
+    /// [`MAX_REVALIDATIONS`] run at once, each behind a bulk OCR slot, so reading through a
+    /// cached chapter doesn't turn into a burst of downloads.
+    pub fn spawn_revalidation(
+        &self,
+        url: String,
+        cache_key: String,
+        user: Option<String>,
+        pass: Option<String>,
+    ) {
+        let Ok(slot) = self.revalidations.clone().try_acquire_owned() else {
+            return;
+        };
+        let state = self.clone();
+        tokio::spawn(async move {
+            let _slot = slot;
+            state
+                .revalidate_content(&url, &cache_key, user.as_deref(), pass.as_deref())
+                .await;
+        });
+    }
+
+    /// Re-fetches the image behind a cache hit when its check is due. A changed image drops
+    /// the entry; pages cached before hashes were kept just get their hash recorded.
+    async fn revalidate_content(
+        &self,
+        url: &str,
+        cache_key: &str,
+        user: Option<&str>,
+        pass: Option<&str>,
+    ) {
+        let Some(stored_hash) = self.claim_content_check(cache_key) else {
+            return;
+        };
+        let _permit = self
+            .scheduler
+            .acquire(&SharedPriority::new(OcrPriority::Bulk))
+            .await;
+        let image_bytes = match logic::fetch_image(url, user, pass).await {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                warn!("Failed to re-check image of {cache_key}: {err:#}");
+                return;
+            }
+        };
+        let hash = content_hash(&image_bytes);
+
+        match stored_hash {
+            Some(stored_hash) if stored_hash != hash => {
+                info!("Image behind {cache_key} changed; dropping its OCR result");
+                self.purge_cache(&PurgeTarget::Page(cache_key.to_string()));
+            }
+            Some(_) => {}
+            None => {
+                let Ok(conn) = self.pool.get() else {
+                    return;
+                };
+                let _ = conn.execute(
+                    "UPDATE ocr_cache SET content_hash = ? WHERE cache_key = ?",
+                    params![hash, cache_key],
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,488 @@
+//! User corrections applied over the engine results of a page.
+//!
+//! Corrections live in their own table keyed by the page's cache key without its merge suffix,
+//! so re-running OCR, re-merging a page or reading it with another merge profile never loses
+//! them. They are stored as an ordered list of edits and replayed every time
+//! the page is served. Each edit remembers the text of the line it was made against, so edits
+//! still find their line when a fresh OCR run shifts the results around. The search index holds
+//! the corrected text, so every change re-indexes the page.
+
+use std::collections::HashMap;
+
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    logic::{self, BoundingBox, OcrResult},
+    search,
+    state::{AppState, now_unix},
+};
+
+/// One edit to a page's results.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Correction {
+    /// Position of the line in the results as they were shown when the edit was made.
+    pub index: usize,
+    /// Text of that line at the time, used to find it again if the results have moved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original: Option<String>,
+    #[serde(flatten)]
+    pub op: CorrectionOp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum CorrectionOp {
+    /// Replaces the text of the line.
+    EditText { text: String },
+    /// Splits the line in two before the char offset `at`.
+    Split { at: usize },
+    /// Joins the line with the one after it.
+    #[serde(rename_all = "camelCase")]
+    JoinNext {
+        #[serde(default)]
+        separator: String,
+    },
+    /// Moves or resizes the line's box.
+    #[serde(rename_all = "camelCase")]
+    MoveBox { tight_bounding_box: BoundingBox },
+    /// Removes the line, e.g. noise picked up from the artwork.
+    Delete,
+}
+
+/// All corrections of one page. An empty list is kept rather than deleted, so clearing a
+/// page's corrections wins over older edits when devices are synced.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PageCorrections {
+    pub edits: Vec<Correction>,
+    pub updated_at: i64,
+}
+
+/// Key the corrections of the page cached under `cache_key` are stored under.
+pub fn corrections_key(cache_key: &str) -> &str {
+    logic::strip_merge_suffix(cache_key)
+}
+
+/// The corrections of the page cached under `cache_key`, read through `conn`.
+pub(crate) fn stored_corrections(conn: &Connection, cache_key: &str) -> Option<PageCorrections> {
+    conn.query_row(
+        "SELECT edits, updated_at FROM ocr_corrections WHERE cache_key = ?",
+        params![corrections_key(cache_key)],
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)),
+    )
+    .optional()
+    .unwrap_or(None)
+    .and_then(|(edits, updated_at)| {
+        Some(PageCorrections {
+            edits: serde_json::from_str(&edits).ok()?,
+            updated_at,
+        })
+    })
+}
+
+impl AppState {
+    pub fn page_corrections(&self, cache_key: &str) -> Option<PageCorrections> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for page_corrections");
+            return None;
+        };
+        stored_corrections(&conn, cache_key)
+    }
+
+    /// Appends `edits` to the page's corrections and returns the stored list.
+    pub fn add_corrections(&self, cache_key: &str, edits: Vec<Correction>) -> PageCorrections {
+        let mut page = self.page_corrections(cache_key).unwrap_or_default();
+        page.edits.extend(edits);
+        page.updated_at = now_unix();
+        self.store_corrections(cache_key, &page, false);
+        page
+    }
+
+    /// Drops every correction of the page.
+    pub fn clear_corrections(&self, cache_key: &str) {
+        let page = PageCorrections {
+            edits: Vec::new(),
+            updated_at: now_unix(),
+        };
+        self.store_corrections(cache_key, &page, false);
+    }
+
+    /// Writes `page` for `cache_key`. With `only_if_newer` an existing row is only replaced
+    /// by a more recent one. Returns whether anything was written.
+    pub fn store_corrections(
+        &self,
+        cache_key: &str,
+        page: &PageCorrections,
+        only_if_newer: bool,
+    ) -> bool {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for store_corrections");
+            return false;
+        };
+        let sql = if only_if_newer {
+            "INSERT INTO ocr_corrections (cache_key, edits, updated_at) VALUES (?, ?, ?)
+             ON CONFLICT(cache_key) DO UPDATE SET
+                edits = excluded.edits,
+                updated_at = excluded.updated_at
+             WHERE excluded.updated_at > ocr_corrections.updated_at"
+        } else {
+            "INSERT INTO ocr_corrections (cache_key, edits, updated_at) VALUES (?, ?, ?)
+             ON CONFLICT(cache_key) DO UPDATE SET
+                edits = excluded.edits,
+                updated_at = excluded.updated_at"
+        };
+        let edits = serde_json::to_string(&page.edits).unwrap_or_else(|_| "[]".to_string());
+        let stored = conn
+            .execute(
+                sql,
+                params![corrections_key(cache_key), edits, page.updated_at],
+            )
+            .map(|changes| changes > 0)
+            .unwrap_or_else(|err| {
+                warn!("Failed to store corrections for {cache_key}: {err}");
+                false
+            });
+        if stored {
+            let key = corrections_key(cache_key);
+            if let Err(err) = search::reindex_corrected(&conn, key) {
+                search::index_failed(&conn, key, &err);
+            }
+        }
+        stored
+    }
+
+    /// Every page's corrections, for syncing them to another device.
+    pub fn all_corrections(&self) -> HashMap<String, PageCorrections> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for all_corrections");
+            return HashMap::new();
+        };
+        let Ok(mut stmt) = conn.prepare("SELECT cache_key, edits, updated_at FROM ocr_corrections")
+        else {
+            return HashMap::new();
+        };
+        stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })
+        .map(|rows| {
+            rows.flatten()
+                .filter_map(|(cache_key, edits, updated_at)| {
+                    let edits = serde_json::from_str(&edits).ok()?;
+                    Some((cache_key, PageCorrections { edits, updated_at }))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+    }
+
+    /// Stores the pages of another device's corrections that are newer than ours. Returns
+    /// how many were taken.
+    pub fn import_corrections(&self, pages: &HashMap<String, PageCorrections>) -> usize {
+        pages
+            .iter()
+            .filter(|(cache_key, page)| self.store_corrections(cache_key, page, true))
+            .count()
+    }
+
+    /// The page's results with its corrections applied, as they should be served.
+    pub fn corrected_results(
+        &self,
+        cache_key: &str,
+        mut results: Vec<OcrResult>,
+    ) -> Vec<OcrResult> {
+        if let Some(page) = self.page_corrections(cache_key) {
+            apply_corrections(&mut results, &page.edits);
+        }
+        results
+    }
+}
+
+/// Replays `edits` over `results` in order. Edits whose line can no longer be found are
+/// skipped.
+pub fn apply_corrections(results: &mut Vec<OcrResult>, edits: &[Correction]) {
+    if edits.is_empty() {
+        return;
+    }
+    for edit in edits {
+        let Some(index) = locate(results, edit) else {
+            debug!("Skipping correction for a line that no longer exists: {edit:?}");
+            continue;
+        };
+        match &edit.op {
+            CorrectionOp::EditText { text } => {
+                let result = &mut results[index];
+                // Offsets into the old text only stay meaningful if the length is unchanged.
+                if text.chars().count() != result.text.chars().count() {
+                    result.ruby.clear();
+                    result.words.clear();
+                }
+                result.text = text.clone();
+            }
+            CorrectionOp::Split { at } => {
+                if let Some(tail) = split_result(&mut results[index], *at) {
+                    results.insert(index + 1, tail);
+                }
+            }
+            CorrectionOp::JoinNext { separator } => {
+                if index + 1 < results.len() {
+                    let next = results.remove(index + 1);
+                    join_results(&mut results[index], next, separator);
+                }
+            }
+            CorrectionOp::MoveBox { tight_bounding_box } => {
+                move_result(&mut results[index], tight_bounding_box);
+            }
+            CorrectionOp::Delete => {
+                results.remove(index);
+            }
+        }
+    }
+
+    if results.iter().any(|result| result.reading_order.is_some()) {
+        for (order, result) in results.iter_mut().enumerate() {
+            result.reading_order = Some(order as u32);
+        }
+    }
+}
+
+fn locate(results: &[OcrResult], edit: &Correction) -> Option<usize> {
+    let Some(original) = edit.original.as_deref() else {
+        return (edit.index < results.len()).then_some(edit.index);
+    };
+    if results
+        .get(edit.index)
+        .is_some_and(|result| result.text == original)
+    {
+        return Some(edit.index);
+    }
+    results.iter().position(|result| result.text == original)
+}
+
+fn is_vertical(result: &OcrResult) -> bool {
+    result.forced_orientation.as_deref() == Some("vertical")
+}
+
+/// Cuts `result` before char `at`, keeping the head in place and returning the tail. The box
+/// is divided along the reading direction in proportion to the text.
+fn split_result(result: &mut OcrResult, at: usize) -> Option<OcrResult> {
+    let len = result.text.chars().count();
+    if at == 0 || at >= len {
+        return None;
+    }
+    let byte_at = result.text.char_indices().nth(at).map(|(i, _)| i)?;
+    let mut tail = result.clone();
+    tail.text = result.text.split_off(byte_at);
+
+    let share = at as f64 / len as f64;
+    let vertical = is_vertical(result);
+    let bbox = &mut result.tight_bounding_box;
+    if vertical {
+        let head_height = bbox.height * share;
+        tail.tight_bounding_box.y = bbox.y + head_height;
+        tail.tight_bounding_box.height = bbox.height - head_height;
+        bbox.height = head_height;
+    } else {
+        let head_width = bbox.width * share;
+        tail.tight_bounding_box.x = bbox.x + head_width;
+        tail.tight_bounding_box.width = bbox.width - head_width;
+        bbox.width = head_width;
+    }
+
+    result.ruby.retain(|ruby| ruby.end <= at);
+    result.words.retain(|word| word.end <= at);
+    tail.ruby.retain(|ruby| ruby.start >= at);
+    tail.words.retain(|word| word.start >= at);
+    for ruby in &mut tail.ruby {
+        ruby.start -= at;
+        ruby.end -= at;
+    }
+    for word in &mut tail.words {
+        word.start -= at;
+        word.end -= at;
+    }
+    Some(tail)
+}
+
+fn join_results(result: &mut OcrResult, next: OcrResult, separator: &str) {
+    let shift = result.text.chars().count() + separator.chars().count();
+    result.text.push_str(separator);
+    result.text.push_str(&next.text);
+
+    let (a, b) = (&result.tight_bounding_box, &next.tight_bounding_box);
+    let (left, top) = (a.x.min(b.x), a.y.min(b.y));
+    let right = (a.x + a.width).max(b.x + b.width);
+    let bottom = (a.y + a.height).max(b.y + b.height);
+    result.tight_bounding_box = BoundingBox {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+        rotation: None,
+    };
+    result.is_merged = Some(true);
+
+    result.ruby.extend(next.ruby.into_iter().map(|mut ruby| {
+        ruby.start += shift;
+        ruby.end += shift;
+        ruby
+    }));
+    result.words.extend(next.words.into_iter().map(|mut word| {
+        word.start += shift;
+        word.end += shift;
+        word
+    }));
+}
+
+/// Moves the box and carries the ruby and word boxes along with it.
+fn move_result(result: &mut OcrResult, target: &BoundingBox) {
+    let source = result.tight_bounding_box.clone();
+    let scale_x = if source.width > 0.0 {
+        target.width / source.width
+    } else {
+        1.0
+    };
+    let scale_y = if source.height > 0.0 {
+        target.height / source.height
+    } else {
+        1.0
+    };
+    let map = |bbox: &mut BoundingBox| {
+        bbox.x = target.x + (bbox.x - source.x) * scale_x;
+        bbox.y = target.y + (bbox.y - source.y) * scale_y;
+        bbox.width *= scale_x;
+        bbox.height *= scale_y;
+    };
+    for ruby in &mut result.ruby {
+        map(&mut ruby.tight_bounding_box);
+    }
+    for word in &mut result.words {
+        map(&mut word.tight_bounding_box);
+    }
+    result.tight_bounding_box = target.clone();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::WordBox;
+
+    fn result(text: &str, x: f64, width: f64) -> OcrResult {
+        OcrResult {
+            text: text.to_string(),
+            tight_bounding_box: BoundingBox {
+                x,
+                y: 0.0,
+                width,
+                height: 10.0,
+                rotation: None,
+            },
+            is_merged: None,
+            forced_orientation: Some("horizontal".into()),
+            bubble_id: None,
+            reading_order: None,
+            ruby: Vec::new(),
+            words: Vec::new(),
+        }
+    }
+
+    fn edit(index: usize, original: &str, op: CorrectionOp) -> Correction {
+        Correction {
+            index,
+            original: Some(original.to_string()),
+            op,
+        }
+    }
+
+    fn texts(results: &[OcrResult]) -> Vec<&str> {
+        results.iter().map(|result| result.text.as_str()).collect()
+    }
+
+    #[test]
+    fn split_divides_text_box_and_words() {
+        let mut head = result("abcdef", 0.0, 60.0);
+        head.words = [(0, 2), (2, 6)]
+            .map(|(start, end)| WordBox {
+                start,
+                end,
+                tight_bounding_box: BoundingBox::default(),
+            })
+            .to_vec();
+        let tail = split_result(&mut head, 2).unwrap();
+
+        assert_eq!((head.text.as_str(), tail.text.as_str()), ("ab", "cdef"));
+        assert_eq!(head.tight_bounding_box.width, 20.0);
+        assert_eq!(
+            (tail.tight_bounding_box.x, tail.tight_bounding_box.width),
+            (20.0, 40.0)
+        );
+        assert_eq!((head.words.len(), tail.words[0].start), (1, 0));
+        assert_eq!(tail.words[0].end, 4);
+
+        assert!(split_result(&mut head, 0).is_none());
+        assert!(split_result(&mut head, 2).is_none());
+    }
+
+    #[test]
+    fn join_concatenates_and_covers_both_boxes() {
+        let mut first = result("ab", 0.0, 20.0);
+        let mut second = result("cd", 50.0, 20.0);
+        second.words.push(WordBox {
+            start: 0,
+            end: 2,
+            tight_bounding_box: BoundingBox::default(),
+        });
+        join_results(&mut first, second, " ");
+
+        assert_eq!(first.text, "ab cd");
+        assert_eq!(
+            (first.tight_bounding_box.x, first.tight_bounding_box.width),
+            (0.0, 70.0)
+        );
+        assert_eq!((first.words[0].start, first.words[0].end), (3, 5));
+        assert_eq!(first.is_merged, Some(true));
+    }
+
+    #[test]
+    fn edits_replay_in_order_and_follow_moved_lines() {
+        let mut results = vec![
+            result("noise", 0.0, 10.0),
+            result("helo", 0.0, 40.0),
+            result("wor", 0.0, 30.0),
+            result("ld", 0.0, 20.0),
+        ];
+        for (order, result) in results.iter_mut().enumerate() {
+            result.reading_order = Some(order as u32);
+        }
+        let edits = [
+            // Made when "helo" was the first line; it has moved down since.
+            edit(
+                0,
+                "helo",
+                CorrectionOp::EditText {
+                    text: "hello".into(),
+                },
+            ),
+            edit(0, "noise", CorrectionOp::Delete),
+            edit(
+                1,
+                "wor",
+                CorrectionOp::JoinNext {
+                    separator: String::new(),
+                },
+            ),
+            edit(5, "gone", CorrectionOp::Delete),
+        ];
+        apply_corrections(&mut results, &edits);
+
+        assert_eq!(texts(&results), ["hello", "world"]);
+        let order: Vec<_> = results.iter().map(|result| result.reading_order).collect();
+        assert_eq!(order, [Some(0), Some(1)]);
+    }
+}
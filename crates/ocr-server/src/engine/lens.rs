@@ -0,0 +1,273 @@
+use anyhow::anyhow;
+use chrome_lens_ocr::LensClient;
+use futures::future::BoxFuture;
+use reqwest::{StatusCode, header::ACCEPT};
+use serde::Deserialize;
+
+use super::{EngineContext, EngineLine, EngineWord, OcrEngine};
+use crate::{language::OcrLanguage, throttle::FailureKind};
+
+pub const LENS_ENGINE_ID: &str = "lens";
+
+#[derive(Deserialize)]
+struct SettingsResponse {
+    settings: Option<ProxySettingsRaw>,
+}
+
+#[derive(Deserialize)]
+struct ProxySettingsRaw {
+    #[serde(rename = "socksProxyEnabled")]
+    socks_proxy_enabled: Option<bool>,
+    #[serde(rename = "socksProxyVersion")]
+    socks_proxy_version: Option<i32>,
+    #[serde(rename = "socksProxyHost")]
+    socks_proxy_host: Option<String>,
+    #[serde(rename = "socksProxyPort")]
+    socks_proxy_port: Option<String>,
+    #[serde(rename = "socksProxyUsername")]
+    socks_proxy_username: Option<String>,
+    #[serde(rename = "socksProxyPassword")]
+    socks_proxy_password: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct ProxySettings {
+    socks_proxy_enabled: bool,
+    socks_proxy_version: i32,
+    socks_proxy_host: String,
+    socks_proxy_port: String,
+    socks_proxy_username: Option<String>,
+    socks_proxy_password: Option<String>,
+}
+
+async fn get_proxy_settings(
+    user: Option<String>,
+    pass: Option<String>,
+) -> anyhow::Result<Option<ProxySettings>> {
+    let client = reqwest::Client::new();
+    let settings_url = "http://127.0.0.1:4568/api/v1/settings";
+    let mut request = client.get(settings_url).header(ACCEPT, "application/json");
+    if let Some(username) = user {
+        request = request.basic_auth(username, pass);
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "[Failed to read body]".to_string());
+        return Err(anyhow!(
+            "REST request failed (Status: {status}). Body: {body}"
+        ));
+    }
+    let json_response: SettingsResponse = response
+        .json()
+        .await
+        .map_err(|err| anyhow!("Error decoding settings REST response: {err}"))?;
+    let Some(raw) = json_response.settings else {
+        return Ok(None);
+    };
+    let settings = ProxySettings {
+        socks_proxy_enabled: raw.socks_proxy_enabled.unwrap_or(false),
+        socks_proxy_version: raw.socks_proxy_version.unwrap_or(5),
+        socks_proxy_host: raw.socks_proxy_host.unwrap_or_default(),
+        socks_proxy_port: raw.socks_proxy_port.unwrap_or_default(),
+        socks_proxy_username: raw.socks_proxy_username.filter(|value| !value.is_empty()),
+        socks_proxy_password: raw.socks_proxy_password.filter(|value| !value.is_empty()),
+    };
+    Ok(Some(settings))
+}
+
+/// Language hint sent to Lens, `None` to let it detect the script itself.
+fn lens_language_code(language: OcrLanguage) -> Option<&'static str> {
+    let code = match language {
+        OcrLanguage::Japanese => "jp",
+        OcrLanguage::English => "en",
+        OcrLanguage::Chinese => "zh",
+        OcrLanguage::Korean => "ko",
+        OcrLanguage::Arabic => "ar",
+        OcrLanguage::Spanish => "es",
+        OcrLanguage::French => "fr",
+        OcrLanguage::German => "de",
+        OcrLanguage::Portuguese => "pt",
+        OcrLanguage::Bulgarian => "bg",
+        OcrLanguage::Czech => "cs",
+        OcrLanguage::Danish => "da",
+        OcrLanguage::Greek => "el",
+        OcrLanguage::Estonian => "et",
+        OcrLanguage::Persian => "fa",
+        OcrLanguage::Finnish => "fi",
+        OcrLanguage::Hebrew => "he",
+        OcrLanguage::Hindi => "hi",
+        OcrLanguage::Hungarian => "hu",
+        OcrLanguage::Indonesian => "id",
+        OcrLanguage::Italian => "it",
+        OcrLanguage::Latin => "la",
+        OcrLanguage::Lao => "lo",
+        OcrLanguage::Latvian => "lv",
+        OcrLanguage::Georgian => "ka",
+        OcrLanguage::Kannada => "kn",
+        OcrLanguage::Khmer => "km",
+        OcrLanguage::Mongolian => "mn",
+        OcrLanguage::Maltese => "mt",
+        OcrLanguage::Dutch => "nl",
+        OcrLanguage::Norwegian => "no",
+        OcrLanguage::Polish => "pl",
+        OcrLanguage::Romanian => "ro",
+        OcrLanguage::Russian => "ru",
+        OcrLanguage::Swedish => "sv",
+        OcrLanguage::Thai => "th",
+        OcrLanguage::Tagalog => "tl",
+        OcrLanguage::Turkish => "tr",
+        OcrLanguage::Ukrainian => "uk",
+        OcrLanguage::Vietnamese => "vi",
+        OcrLanguage::Welsh => "cy",
+        // Lens has no Cantonese model; it is written in traditional characters.
+        OcrLanguage::Cantonese => "zh-Hant",
+        OcrLanguage::Auto => return None,
+    };
+    Some(code)
+}
+
+/// HTTP status of a failed Lens call. The client reports error responses as
+/// `API Error <status>: <body>`, so the status is read from the start of its own message and
+/// never from the body.
+fn lens_status(err: &anyhow::Error) -> Option<StatusCode> {
+    if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+        return err.status();
+    }
+    let code = err
+        .to_string()
+        .strip_prefix("API Error ")?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    StatusCode::from_u16(code).ok()
+}
+
+/// Google Lens, going through Suwayomi's SOCKS proxy when one is configured.
+pub struct LensEngine;
+
+impl LensEngine {
+    async fn client(&self, ctx: &EngineContext) -> anyhow::Result<LensClient> {
+        let proxy_settings = get_proxy_settings(ctx.user.clone(), ctx.pass.clone())
+            .await
+            .ok()
+            .flatten();
+
+        let Some(proxy) = proxy_settings else {
+            return Ok(LensClient::new(None));
+        };
+        if !proxy.socks_proxy_enabled || proxy.socks_proxy_host.is_empty() {
+            return Ok(LensClient::new(None));
+        }
+
+        // Build proxy URL with authentication if provided
+        let proxy_url = match (&proxy.socks_proxy_username, &proxy.socks_proxy_password) {
+            (Some(username), Some(password)) if !username.is_empty() && !password.is_empty() => {
+                format!(
+                    "socks{}://{username}:{password}@{}:{}",
+                    proxy.socks_proxy_version, proxy.socks_proxy_host, proxy.socks_proxy_port
+                )
+            }
+            _ => format!(
+                "socks{}://{}:{}",
+                proxy.socks_proxy_version, proxy.socks_proxy_host, proxy.socks_proxy_port
+            ),
+        };
+
+        tracing::info!(
+            "Using SOCKS{} proxy for Google Lens: {}:{}",
+            proxy.socks_proxy_version,
+            proxy.socks_proxy_host,
+            proxy.socks_proxy_port
+        );
+
+        LensClient::new_with_proxy(None, Some(&proxy_url))
+            .map_err(|e| anyhow!("Failed to create LensClient with proxy: {e}"))
+    }
+}
+
+impl OcrEngine for LensEngine {
+    fn id(&self) -> &str {
+        LENS_ENGINE_ID
+    }
+
+    fn recognize<'a>(
+        &'a self,
+        image_png: &'a [u8],
+        language: OcrLanguage,
+        ctx: &'a EngineContext,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<EngineLine>>> {
+        Box::pin(async move {
+            let lens_client = self.client(ctx).await?;
+            let lens_response = lens_client
+                .process_image_bytes(image_png, lens_language_code(language))
+                .await
+                .map_err(|err| {
+                    let status = lens_status(&err);
+                    let err = anyhow!("Failed process_image_bytes: {err:?}");
+                    if status == Some(StatusCode::TOO_MANY_REQUESTS) {
+                        err.context(FailureKind::RateLimited)
+                    } else {
+                        err
+                    }
+                })?;
+
+            let mut lines = Vec::new();
+            for paragraph in lens_response.paragraphs {
+                for line in paragraph.lines {
+                    let Some(geometry) = line.geometry else {
+                        continue;
+                    };
+                    let words = line
+                        .words
+                        .into_iter()
+                        .filter_map(|word| {
+                            let geometry = word.geometry?;
+                            Some(EngineWord {
+                                text: word.plain_text,
+                                center_x: geometry.center_x as f64,
+                                center_y: geometry.center_y as f64,
+                                width: geometry.width as f64,
+                                height: geometry.height as f64,
+                                rotation: geometry.rotation_z as f64,
+                            })
+                        })
+                        .collect();
+                    lines.push(EngineLine {
+                        text: line.text,
+                        center_x: geometry.center_x as f64,
+                        center_y: geometry.center_y as f64,
+                        width: geometry.width as f64,
+                        height: geometry.height as f64,
+                        rotation: geometry.rotation_z as f64,
+                        words,
+                    });
+                }
+            }
+            Ok(lines)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_is_read_from_the_message_head_only() {
+        let status = |message: &str| lens_status(&anyhow!("{message}"));
+        assert_eq!(
+            status("API Error 429 Too Many Requests: slow down"),
+            Some(StatusCode::TOO_MANY_REQUESTS)
+        );
+        assert_eq!(
+            status("API Error 500 Internal Server Error: quota 429"),
+            Some(StatusCode::INTERNAL_SERVER_ERROR)
+        );
+        assert_eq!(status("Failed to decode protobuf response: 429"), None);
+    }
+}
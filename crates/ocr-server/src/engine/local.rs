@@ -0,0 +1,414 @@
+//! Offline OCR on the CPU through ONNX Runtime.
+//!
+//! Text blocks are found with comic-text-detector and each block is read with a manga-ocr
+//! encoder/decoder export. Model files live under `<data dir>/ocr-models/`:
+//!
+//! ```text
+//! ocr-models/
+//!   comictextdetector.onnx
+//!   manga-ocr/encoder_model.onnx
+//!   manga-ocr/decoder_model.onnx
+//!   manga-ocr/vocab.txt
+//! ```
+//!
+//! ONNX Runtime itself is loaded at runtime: `ocr-models/` is searched for the platform's
+//! `onnxruntime` library first, then `ORT_DYLIB_PATH`.
+
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, anyhow};
+use futures::future::BoxFuture;
+use image::{
+    DynamicImage, GenericImageView, ImageReader, RgbImage,
+    imageops::{self, FilterType},
+};
+use ort::{session::Session, value::Tensor};
+use tokio::sync::OnceCell;
+
+use super::{EngineContext, EngineLine, OcrEngine};
+use crate::language::OcrLanguage;
+
+pub const LOCAL_ENGINE_ID: &str = "local";
+
+const DETECTOR_INPUT_SIZE: u32 = 1024;
+const DETECTOR_CONFIDENCE: f32 = 0.4;
+const DETECTOR_NMS_IOU: f32 = 0.35;
+
+const RECOGNIZER_INPUT_SIZE: u32 = 224;
+const RECOGNIZER_MAX_TOKENS: usize = 300;
+const TOKEN_START: i64 = 2;
+const TOKEN_END: i64 = 3;
+
+/// comic-text-detector + manga-ocr, loaded lazily from the data dir on first use.
+pub struct LocalEngine {
+    model_dir: PathBuf,
+    /// Loaded once; a failed load is kept too, so every call doesn't retry it.
+    models: OnceCell<Result<Arc<Models>, String>>,
+}
+
+struct Models {
+    detector: Mutex<Session>,
+    encoder: Mutex<Session>,
+    decoder: Mutex<Session>,
+    vocab: Vec<String>,
+}
+
+impl LocalEngine {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            model_dir: data_dir.join("ocr-models"),
+            models: OnceCell::new(),
+        }
+    }
+
+    /// Loading the sessions takes seconds, so it runs on the blocking pool; concurrent first
+    /// calls wait for the same load.
+    async fn models(&self) -> anyhow::Result<Arc<Models>> {
+        let loaded = self
+            .models
+            .get_or_init(|| async {
+                let model_dir = self.model_dir.clone();
+                let loaded = tokio::task::spawn_blocking(move || Models::load(&model_dir))
+                    .await
+                    .map_err(|err| anyhow!("Local OCR model loading failed: {err}"))
+                    .and_then(|loaded| loaded);
+                match loaded {
+                    Ok(models) => Ok(Arc::new(models)),
+                    Err(err) => {
+                        tracing::warn!("Local OCR engine unavailable: {err:#}");
+                        Err(format!("{err:#}"))
+                    }
+                }
+            })
+            .await;
+        loaded
+            .clone()
+            .map_err(|err| anyhow!("{err} (restart the server after fixing the OCR model files)"))
+    }
+}
+
+impl OcrEngine for LocalEngine {
+    fn id(&self) -> &str {
+        LOCAL_ENGINE_ID
+    }
+
+    fn recognize<'a>(
+        &'a self,
+        image_png: &'a [u8],
+        language: OcrLanguage,
+        _ctx: &'a EngineContext,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<EngineLine>>> {
+        Box::pin(async move {
+            if !matches!(language, OcrLanguage::Japanese | OcrLanguage::Auto) {
+                return Err(anyhow!(
+                    "The local OCR engine only supports Japanese (requested {})",
+                    language.as_str()
+                ));
+            }
+            let models = self.models().await?;
+            let image_png = image_png.to_vec();
+            tokio::task::spawn_blocking(move || models.recognize(&image_png))
+                .await
+                .map_err(|err| anyhow!("Local OCR task failed: {err}"))?
+        })
+    }
+}
+
+fn init_runtime(model_dir: &Path) {
+    let bundled = model_dir.join(format!(
+        "{}onnxruntime{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    ));
+    let committed = if bundled.exists() {
+        ort::init_from(bundled.to_string_lossy()).commit()
+    } else {
+        ort::init().commit()
+    };
+    if let Err(err) = committed {
+        tracing::warn!("Failed to initialize ONNX Runtime: {err}");
+    }
+}
+
+fn load_session(path: &Path) -> anyhow::Result<Session> {
+    if !path.exists() {
+        return Err(anyhow!("Missing OCR model file: {}", path.display()));
+    }
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    Session::builder()
+        .and_then(|builder| builder.with_intra_threads(threads))
+        .and_then(|builder| builder.commit_from_file(path))
+        .map_err(|err| anyhow!("Failed to load {}: {err}", path.display()))
+}
+
+impl Models {
+    fn load(model_dir: &Path) -> anyhow::Result<Self> {
+        init_runtime(model_dir);
+
+        let vocab_path = model_dir.join("manga-ocr").join("vocab.txt");
+        let vocab = std::fs::read_to_string(&vocab_path)
+            .with_context(|| format!("Missing OCR vocab file: {}", vocab_path.display()))?
+            .lines()
+            .map(str::to_string)
+            .collect();
+
+        Ok(Self {
+            detector: Mutex::new(load_session(&model_dir.join("comictextdetector.onnx"))?),
+            encoder: Mutex::new(load_session(
+                &model_dir.join("manga-ocr").join("encoder_model.onnx"),
+            )?),
+            decoder: Mutex::new(load_session(
+                &model_dir.join("manga-ocr").join("decoder_model.onnx"),
+            )?),
+            vocab,
+        })
+    }
+
+    fn recognize(&self, image_png: &[u8]) -> anyhow::Result<Vec<EngineLine>> {
+        let image = ImageReader::new(Cursor::new(image_png))
+            .with_guessed_format()
+            .map_err(|err| anyhow!("Failed with_guessed_format: {err:?}"))?
+            .decode()
+            .map_err(|err| anyhow!("Failed decode: {err:?}"))?;
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut lines = Vec::new();
+        for block in self.detect(&image)? {
+            let x = block.x1.max(0.0) as u32;
+            let y = block.y1.max(0.0) as u32;
+            let x2 = (block.x2.max(0.0) as u32).min(width);
+            let y2 = (block.y2.max(0.0) as u32).min(height);
+            if x2 <= x || y2 <= y {
+                continue;
+            }
+
+            let crop = image.crop_imm(x, y, x2 - x, y2 - y);
+            let text = self.read_text(&crop)?;
+            if text.is_empty() {
+                continue;
+            }
+
+            lines.push(EngineLine {
+                text,
+                center_x: (x + x2) as f64 / 2.0 / width as f64,
+                center_y: (y + y2) as f64 / 2.0 / height as f64,
+                width: (x2 - x) as f64 / width as f64,
+                height: (y2 - y) as f64 / height as f64,
+                rotation: 0.0,
+                words: Vec::new(),
+            });
+        }
+        Ok(lines)
+    }
+
+    /// Runs comic-text-detector and returns text blocks in source image pixels.
+    fn detect(&self, image: &DynamicImage) -> anyhow::Result<Vec<TextBlock>> {
+        let (width, height) = image.dimensions();
+        let scale = DETECTOR_INPUT_SIZE as f32 / width.max(height) as f32;
+        let scaled_w = ((width as f32 * scale).round() as u32).clamp(1, DETECTOR_INPUT_SIZE);
+        let scaled_h = ((height as f32 * scale).round() as u32).clamp(1, DETECTOR_INPUT_SIZE);
+
+        // Letterbox: the scaled page sits in the top-left corner, padded with black.
+        let resized = imageops::resize(&image.to_rgb8(), scaled_w, scaled_h, FilterType::Triangle);
+        let mut canvas = RgbImage::new(DETECTOR_INPUT_SIZE, DETECTOR_INPUT_SIZE);
+        imageops::replace(&mut canvas, &resized, 0, 0);
+
+        let input = Tensor::from_array((
+            [
+                1usize,
+                3,
+                DETECTOR_INPUT_SIZE as usize,
+                DETECTOR_INPUT_SIZE as usize,
+            ],
+            to_chw(&canvas, |v| v as f32 / 255.0),
+        ))
+        .map_err(|err| anyhow!("Failed to build detector input: {err}"))?;
+
+        let mut session = self.detector.lock().expect("lock poisoned");
+        let outputs = session
+            .run(ort::inputs!["images" => input])
+            .map_err(|err| anyhow!("Text detection failed: {err}"))?;
+        let (shape, data) = outputs["blk"]
+            .try_extract_tensor::<f32>()
+            .map_err(|err| anyhow!("Unexpected detector output: {err}"))?;
+
+        let stride = shape.last().copied().unwrap_or(0) as usize;
+        if stride < 6 {
+            return Err(anyhow!("Unexpected detector output shape: {shape:?}"));
+        }
+
+        let mut blocks: Vec<TextBlock> = data
+            .chunks_exact(stride)
+            .filter_map(|row| {
+                let class_conf = row[5..].iter().copied().fold(0.0f32, f32::max);
+                let confidence = row[4] * class_conf;
+                if confidence < DETECTOR_CONFIDENCE {
+                    return None;
+                }
+                let (cx, cy, w, h) = (row[0], row[1], row[2], row[3]);
+                Some(TextBlock {
+                    x1: (cx - w / 2.0) / scale,
+                    y1: (cy - h / 2.0) / scale,
+                    x2: (cx + w / 2.0) / scale,
+                    y2: (cy + h / 2.0) / scale,
+                    confidence,
+                })
+            })
+            .collect();
+
+        blocks.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        let mut kept: Vec<TextBlock> = Vec::new();
+        for block in blocks {
+            if kept.iter().all(|k| k.iou(&block) < DETECTOR_NMS_IOU) {
+                kept.push(block);
+            }
+        }
+        Ok(kept)
+    }
+
+    /// Greedy manga-ocr decoding of a single text block.
+    fn read_text(&self, crop: &DynamicImage) -> anyhow::Result<String> {
+        let gray = DynamicImage::ImageLuma8(crop.to_luma8()).to_rgb8();
+        let resized = imageops::resize(
+            &gray,
+            RECOGNIZER_INPUT_SIZE,
+            RECOGNIZER_INPUT_SIZE,
+            FilterType::Triangle,
+        );
+        let pixel_values = Tensor::from_array((
+            [
+                1usize,
+                3,
+                RECOGNIZER_INPUT_SIZE as usize,
+                RECOGNIZER_INPUT_SIZE as usize,
+            ],
+            to_chw(&resized, |v| (v as f32 / 255.0 - 0.5) / 0.5),
+        ))
+        .map_err(|err| anyhow!("Failed to build recognizer input: {err}"))?;
+
+        let (hidden_shape, hidden) = {
+            let mut encoder = self.encoder.lock().expect("lock poisoned");
+            let outputs = encoder
+                .run(ort::inputs!["pixel_values" => pixel_values])
+                .map_err(|err| anyhow!("Recognizer encoder failed: {err}"))?;
+            let (shape, data) = outputs["last_hidden_state"]
+                .try_extract_tensor::<f32>()
+                .map_err(|err| anyhow!("Unexpected encoder output: {err}"))?;
+            let shape: Vec<usize> = shape.iter().map(|&d| d as usize).collect();
+            (shape, data.to_vec())
+        };
+
+        let mut decoder = self.decoder.lock().expect("lock poisoned");
+        let mut tokens = vec![TOKEN_START];
+        while tokens.len() < RECOGNIZER_MAX_TOKENS {
+            let input_ids = Tensor::from_array(([1usize, tokens.len()], tokens.clone()))
+                .map_err(|err| anyhow!("Failed to build decoder input: {err}"))?;
+            let encoder_hidden_states = Tensor::from_array((hidden_shape.clone(), hidden.clone()))
+                .map_err(|err| anyhow!("Failed to build decoder input: {err}"))?;
+            let outputs = decoder
+                .run(ort::inputs![
+                    "input_ids" => input_ids,
+                    "encoder_hidden_states" => encoder_hidden_states,
+                ])
+                .map_err(|err| anyhow!("Recognizer decoder failed: {err}"))?;
+            let (shape, logits) = outputs["logits"]
+                .try_extract_tensor::<f32>()
+                .map_err(|err| anyhow!("Unexpected decoder output: {err}"))?;
+
+            let vocab_size = shape.last().copied().unwrap_or(0) as usize;
+            if vocab_size == 0 || logits.len() < vocab_size {
+                return Err(anyhow!("Unexpected decoder output shape: {shape:?}"));
+            }
+            let last = &logits[logits.len() - vocab_size..];
+            let next = last
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .map(|(i, _)| i as i64)
+                .unwrap_or(TOKEN_END);
+            if next == TOKEN_END {
+                break;
+            }
+            tokens.push(next);
+        }
+
+        Ok(self.detokenize(&tokens[1..]))
+    }
+
+    fn detokenize(&self, tokens: &[i64]) -> String {
+        let mut text = String::new();
+        for &token in tokens {
+            let Some(piece) = self.vocab.get(token as usize) else {
+                continue;
+            };
+            if piece.starts_with('[') && piece.ends_with(']') {
+                continue;
+            }
+            text.push_str(piece.trim_start_matches("##"));
+        }
+        post_process_manga_ocr(&text)
+    }
+}
+
+/// Mirrors manga-ocr's own post-processing: drop whitespace, normalize ellipses and widen
+/// ASCII to full-width forms.
+fn post_process_manga_ocr(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .replace('…', "...")
+        .chars()
+        .map(|c| match c {
+            '!'..='~' => char::from_u32(c as u32 + 0xFEE0).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+fn to_chw(image: &RgbImage, normalize: impl Fn(u8) -> f32) -> Vec<f32> {
+    let (width, height) = image.dimensions();
+    let plane = (width * height) as usize;
+    let mut out = vec![0.0f32; plane * 3];
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let idx = (y * width + x) as usize;
+        for channel in 0..3 {
+            out[channel * plane + idx] = normalize(pixel[channel]);
+        }
+    }
+    out
+}
+
+struct TextBlock {
+    x1: f32,
+    y1: f32,
+    x2: f32,
+    y2: f32,
+    confidence: f32,
+}
+
+impl TextBlock {
+    fn area(&self) -> f32 {
+        (self.x2 - self.x1).max(0.0) * (self.y2 - self.y1).max(0.0)
+    }
+
+    fn iou(&self, other: &TextBlock) -> f32 {
+        let w = (self.x2.min(other.x2) - self.x1.max(other.x1)).max(0.0);
+        let h = (self.y2.min(other.y2) - self.y1.max(other.y1)).max(0.0);
+        let intersection = w * h;
+        let union = self.area() + other.area() - intersection;
+        if union <= 0.0 {
+            0.0
+        } else {
+            intersection / union
+        }
+    }
+}
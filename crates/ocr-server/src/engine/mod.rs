@@ -0,0 +1,116 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::language::OcrLanguage;
+
+mod lens;
+#[cfg(feature = "local-ocr")]
+mod local;
+
+pub use lens::{LENS_ENGINE_ID, LensEngine};
+#[cfg(feature = "local-ocr")]
+pub use local::{LOCAL_ENGINE_ID, LocalEngine};
+
+/// A single recognized line, with geometry normalized to the image that was passed to the
+/// engine (`0.0..=1.0` on both axes).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EngineLine {
+    pub text: String,
+    pub center_x: f64,
+    pub center_y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// Clockwise rotation of the line box in radians.
+    #[serde(default)]
+    pub rotation: f64,
+    /// Word boxes inside the line, for engines that report them.
+    #[serde(default)]
+    pub words: Vec<EngineWord>,
+}
+
+/// A word of an [`EngineLine`] (a single character for scripts written without spaces), in
+/// the same normalized coordinates as its line.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EngineWord {
+    pub text: String,
+    pub center_x: f64,
+    pub center_y: f64,
+    pub width: f64,
+    pub height: f64,
+    #[serde(default)]
+    pub rotation: f64,
+}
+
+/// Credentials for the Suwayomi instance the request came through. Engines that need
+/// server-side settings (e.g. the SOCKS proxy for Lens) use these to fetch them.
+#[derive(Clone, Debug, Default)]
+pub struct EngineContext {
+    pub user: Option<String>,
+    pub pass: Option<String>,
+}
+
+/// Something that turns an encoded image into text lines.
+///
+/// Engines only do recognition. Decoding, chunking, merging and caching stay in
+/// [`crate::logic`] so every engine gets the same treatment.
+pub trait OcrEngine: Send + Sync {
+    fn id(&self) -> &str;
+
+    fn recognize<'a>(
+        &'a self,
+        image_png: &'a [u8],
+        language: OcrLanguage,
+        ctx: &'a EngineContext,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<EngineLine>>>;
+}
+
+#[derive(Clone)]
+pub struct EngineRegistry {
+    engines: Arc<RwLock<HashMap<String, Arc<dyn OcrEngine>>>>,
+}
+
+impl EngineRegistry {
+    /// Creates a registry with the built-in engines. `data_dir` is where engines that run
+    /// locally look for their model files.
+    pub fn new(data_dir: &Path) -> Self {
+        let registry = Self {
+            engines: Arc::new(RwLock::new(HashMap::new())),
+        };
+        registry.register(Arc::new(LensEngine));
+        #[cfg(feature = "local-ocr")]
+        registry.register(Arc::new(LocalEngine::new(data_dir)));
+        #[cfg(not(feature = "local-ocr"))]
+        let _ = data_dir;
+        registry
+    }
+
+    /// Adds an engine, replacing any previously registered engine with the same id.
+    pub fn register(&self, engine: Arc<dyn OcrEngine>) {
+        self.engines
+            .write()
+            .expect("lock poisoned")
+            .insert(engine.id().to_string(), engine);
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<dyn OcrEngine>> {
+        self.engines.read().expect("lock poisoned").get(id).cloned()
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .engines
+            .read()
+            .expect("lock poisoned")
+            .keys()
+            .cloned()
+            .collect();
+        ids.sort();
+        ids
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::state::{AppState, JobProgress, JobStatus};
+
+/// Capacity of the event channel. Slow subscribers that fall further behind than this skip
+/// ahead (they get a `lagged` event) instead of holding the workers back.
+pub const EVENT_BUFFER: usize = 256;
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobEvent {
+    JobStarted {
+        job_id: String,
+        #[serde(flatten)]
+        progress: JobProgress,
+    },
+    Progress {
+        job_id: String,
+        #[serde(flatten)]
+        progress: JobProgress,
+    },
+    PageFailed {
+        job_id: String,
+        page_index: usize,
+        url: String,
+        error: String,
+    },
+    JobCompleted {
+        job_id: String,
+        processed: usize,
+        failed: usize,
+        total: usize,
+    },
+    /// The job stopped before finishing because it was paused or cancelled.
+    JobStopped { job_id: String, status: JobStatus },
+    /// The subscriber missed `skipped` events and should re-sync through the status endpoints.
+    Lagged { skipped: u64 },
+}
+
+impl JobEvent {
+    pub fn job_id(&self) -> Option<&str> {
+        match self {
+            JobEvent::JobStarted { job_id, .. }
+            | JobEvent::Progress { job_id, .. }
+            | JobEvent::PageFailed { job_id, .. }
+            | JobEvent::JobCompleted { job_id, .. }
+            | JobEvent::JobStopped { job_id, .. } => Some(job_id),
+            JobEvent::Lagged { .. } => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobEvent::JobStarted { .. } => "job_started",
+            JobEvent::Progress { .. } => "progress",
+            JobEvent::PageFailed { .. } => "page_failed",
+            JobEvent::JobCompleted { .. } => "job_completed",
+            JobEvent::JobStopped { .. } => "job_stopped",
+            JobEvent::Lagged { .. } => "lagged",
+        }
+    }
+}
+
+impl AppState {
+    /// Publishes a job event to every open subscription. Having no subscribers is fine.
+    pub fn emit(&self, event: JobEvent) {
+        let _ = self.events.send(event);
+    }
+}
+
+/// Every event for the jobs in `filter` (all jobs when `None`), starting with the current
+/// progress of matching running jobs so late subscribers don't have to poll first.
+pub fn subscribe(
+    state: &AppState,
+    filter: Option<HashSet<String>>,
+) -> impl Stream<Item = JobEvent> + use<> {
+    let wanted = move |event: &JobEvent| match (&filter, event.job_id()) {
+        (Some(filter), Some(job_id)) => filter.contains(job_id),
+        _ => true,
+    };
+
+    let receiver = state.events.subscribe();
+    let snapshot: Vec<JobEvent> = state
+        .active_chapter_jobs
+        .read()
+        .expect("lock poisoned")
+        .iter()
+        .map(|(job_id, progress)| JobEvent::Progress {
+            job_id: job_id.clone(),
+            progress: *progress,
+        })
+        .collect();
+
+    let live = futures::stream::unfold(receiver, |mut receiver| async move {
+        match receiver.recv().await {
+            Ok(event) => Some((event, receiver)),
+            Err(RecvError::Lagged(skipped)) => Some((JobEvent::Lagged { skipped }, receiver)),
+            Err(RecvError::Closed) => None,
+        }
+    });
+
+    futures::stream::iter(snapshot)
+        .chain(live)
+        .filter(move |event| std::future::ready(wanted(event)))
+}
+
+pub fn channel() -> broadcast::Sender<JobEvent> {
+    broadcast::channel(EVENT_BUFFER).0
+}
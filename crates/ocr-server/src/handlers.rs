@@ -1,21 +1,45 @@
-use std::sync::atomic::Ordering;
+use std::{convert::Infallible, io, sync::atomic::Ordering, time::Duration};
 
 use axum::{
     Json,
-    extract::{Query, State},
-    http::StatusCode,
+    body::Body,
+    extract::{Multipart, Query, State},
+    http::{StatusCode, header},
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
 };
-use futures::StreamExt;
-use serde::Deserialize;
+use base64::{Engine as _, prelude::BASE64_STANDARD};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use tokio_util::io::{ReaderStream, StreamReader, SyncIoBridge};
 use tracing::{info, warn};
 
 use crate::{
-    jobs,
+    archive::{ArchiveFilter, ImportPolicy, ImportReport},
+    cache::{CacheStats, EvictionReport, PurgeReport, PurgeTarget},
+    config::OcrConfig,
+    content::PageTarget,
+    corrections::{Correction, PageCorrections},
+    engine::EngineContext,
+    events,
     language::OcrLanguage,
-    logic,
-    state::{AppState, CacheEntry},
+    logic::{self, BoundingBox},
+    merge::{MergeConfig, MergeProfile},
+    prefetch::SeriesPrefetch,
+    profiles::MergeProfiles,
+    region,
+    remerge::RemergeReport,
+    scheduler::{OcrPriority, SharedPriority},
+    search::{self, SearchFilter},
+    series::{self, SeriesJob, SeriesJobSummary, SeriesStatus},
+    sources,
+    state::{
+        AppState, CacheEntry, JobPage, JobPageCounts, JobRecord, JobStatus, PageStatus, now_unix,
+    },
 };
 
 #[derive(Deserialize)]
@@ -29,13 +53,17 @@ pub struct OcrRequest {
     pub context: String,
     pub add_space_on_merge: Option<bool>,
     pub language: Option<OcrLanguage>,
+    pub engine: Option<String>,
+    /// Named merge profile; the series profile (if any) applies otherwise.
+    pub merge_profile: Option<String>,
+    pub merge_enabled: Option<bool>,
+    pub font_size_ratio: Option<f64>,
 }
 
 fn default_context() -> String {
     "No Context".to_string()
 }
 
-
 // --- Handlers ---
 
 pub async fn status_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
@@ -46,67 +74,149 @@ pub async fn status_handler(State(state): State<AppState>) -> Json<serde_json::V
         "requests_processed": state.requests_processed.load(Ordering::Relaxed),
         "items_in_cache": cache_size,
         "active_jobs": state.active_jobs.load(Ordering::Relaxed),
+        "pages_in_flight": state.in_flight.count(),
+        "ocr_scheduler": state.scheduler.stats(),
+        "engine_health": state.engine_health(),
+        "default_engine": state.config().default_engine,
+        "cache_max_bytes": state.config().cache_max_bytes,
+    }))
+}
+
+pub async fn engines_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "engines": state.engines.ids(),
+        "default": state.config().default_engine,
     }))
 }
 
+pub async fn get_config_handler(State(state): State<AppState>) -> Json<OcrConfig> {
+    Json(state.config())
+}
+
+pub async fn set_config_handler(
+    State(state): State<AppState>,
+    Json(config): Json<OcrConfig>,
+) -> Result<Json<OcrConfig>, (StatusCode, String)> {
+    if state.engines.get(&config.default_engine).is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Unknown OCR engine: {}", config.default_engine),
+        ));
+    }
+    if config.ocr_concurrency == 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "ocr_concurrency must be at least 1".to_string(),
+        ));
+    }
+    state.set_config(config.clone());
+    // Apply a tightened budget right away instead of waiting for the next sweep.
+    tokio::task::spawn_blocking(move || state.evict_cache());
+    Ok(Json(config))
+}
+
 pub async fn ocr_handler(
     State(state): State<AppState>,
     Query(params): Query<OcrRequest>,
 ) -> Result<Json<Vec<crate::logic::OcrResult>>, (StatusCode, String)> {
     let language = params.language.unwrap_or_default();
-    let cache_key = logic::get_cache_key(&params.url, Some(language));
+    let engine = state
+        .resolve_engine(params.engine.as_deref())
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    let merge_config = state
+        .resolve_merge_config(
+            &params.url,
+            language,
+            params.merge_profile.as_deref(),
+            &MergeProfile {
+                enabled: params.merge_enabled,
+                font_size_ratio: params.font_size_ratio,
+                add_space_on_merge: params.add_space_on_merge,
+            },
+        )
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    let cache_key = logic::get_merge_cache_key(&params.url, &merge_config);
     let chapter_key = params
         .base_url
         .as_ref()
-        .map(|base| logic::get_cache_key(base, Some(language)));
+        .map(|base| logic::get_merge_cache_key(base, &merge_config));
     info!("OCR Handler: Incoming request for cache_key={}", cache_key);
+    state.supply_credentials(&params.url, params.user.as_deref(), params.pass.as_deref());
+
+    // A read of a known chapter queues the chapters after it.
+    if let (Some(base_url), Some(chapter_key)) = (&params.base_url, &chapter_key) {
+        let prefetch_state = state.clone();
+        let now = now_unix();
+        let reading = JobRecord {
+            job_id: chapter_key.clone(),
+            base_url: base_url.clone(),
+            context: params.context.clone(),
+            language,
+            engine: params.engine.clone(),
+            merge_config: merge_config.clone(),
+            user: params.user.clone(),
+            pass: params.pass.clone(),
+            status: JobStatus::Queued,
+            priority: 0,
+            ocr_priority: OcrPriority::NextChapter,
+            created_at: now,
+            updated_at: now,
+        };
+        tokio::spawn(async move { prefetch_state.prefetch_after(reading).await });
+    }
 
     info!("OCR Handler: Checking cache...");
-    if let Some(entry) = state.get_cache_entry(&cache_key) {
+    if let Some(entry) = state.get_cache_entry_from(&cache_key, Some(engine.id())) {
         info!("OCR Handler: Cache HIT for cache_key={}", cache_key);
         if let Some(chapter_key) = chapter_key.as_deref() {
             state.insert_chapter_cache(chapter_key, &cache_key);
         }
         state.requests_processed.fetch_add(1, Ordering::Relaxed);
-        return Ok(Json(entry.data));
+        // Notice a changed image in the background; the next read then gets fresh text.
+        state.spawn_revalidation(
+            params.url.clone(),
+            cache_key.clone(),
+            params.user.clone(),
+            params.pass.clone(),
+        );
+        return Ok(Json(state.corrected_results(&cache_key, entry.data)));
     }
     info!(
         "OCR Handler: Cache MISS for cache_key={}. Starting processing.",
         cache_key
     );
 
-    let result = logic::fetch_and_process(
-        &params.url,
-        params.user.clone(),
-        params.pass.clone(),
-        params.add_space_on_merge,
-        language,
-    )
-    .await;
+    // Writes the cache entry itself, or reuses one with the same image.
+    let result = state
+        .ocr_url(
+            &PageTarget {
+                url: &params.url,
+                cache_key: &cache_key,
+                context: &params.context,
+            },
+            engine.as_ref(),
+            &EngineContext {
+                user: params.user.clone(),
+                pass: params.pass.clone(),
+            },
+            &merge_config,
+            OcrPriority::Interactive,
+        )
+        .await;
 
     match result {
-        Ok(data) => {
+        Ok(results) => {
             state.requests_processed.fetch_add(1, Ordering::Relaxed);
             info!(
                 "OCR Handler: Processing successful for cache_key={}",
                 cache_key
             );
 
-            info!("OCR Handler: Writing cache entry to DB...");
-            state.insert_cache_entry(
-                &cache_key,
-                &CacheEntry {
-                    context: params.context,
-                    data: data.clone(),
-                },
-            );
-            info!("OCR Handler: Cache write complete.");
-
             if let Some(chapter_key) = chapter_key.as_deref() {
                 state.insert_chapter_cache(chapter_key, &cache_key);
             }
 
-            Ok(Json(data))
+            Ok(Json(state.corrected_results(&cache_key, results)))
         }
         Err(e) => {
             warn!(
@@ -118,6 +228,239 @@ pub async fn ocr_handler(
     }
 }
 
+/// Settings for OCR of an uploaded image or a local file. Such pages are cached by content
+/// hash rather than by URL.
+#[derive(Deserialize)]
+pub struct DirectOcrParams {
+    /// File to read, for `/ocr-local`. Must lie inside one of the configured library roots.
+    pub path: Option<String>,
+    pub user: Option<String>,
+    pub pass: Option<String>,
+    #[serde(default = "default_context")]
+    pub context: String,
+    pub add_space_on_merge: Option<bool>,
+    pub language: Option<OcrLanguage>,
+    pub engine: Option<String>,
+    pub merge_profile: Option<String>,
+    pub merge_enabled: Option<bool>,
+    pub font_size_ratio: Option<f64>,
+}
+
+/// Shared by uploads and local files: looks the image up by content hash and OCRs it on a
+/// miss.
+async fn ocr_image_bytes(
+    state: &AppState,
+    params: &DirectOcrParams,
+    image_bytes: Vec<u8>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let language = params.language.unwrap_or_default();
+    let engine = state
+        .resolve_engine(params.engine.as_deref())
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    let hash = sources::content_hash(&image_bytes);
+    let url = sources::content_url(&hash);
+    let merge_config = state
+        .resolve_merge_config(
+            &url,
+            language,
+            params.merge_profile.as_deref(),
+            &MergeProfile {
+                enabled: params.merge_enabled,
+                font_size_ratio: params.font_size_ratio,
+                add_space_on_merge: params.add_space_on_merge,
+            },
+        )
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    let cache_key = logic::get_merge_cache_key(&url, &merge_config);
+
+    let results = match state.get_cache_entry_from(&cache_key, Some(engine.id())) {
+        Some(entry) => entry.data,
+        None => {
+            let permit = state
+                .scheduler
+                .acquire(&SharedPriority::new(OcrPriority::Interactive))
+                .await;
+            let page = logic::process_image(
+                &image_bytes,
+                engine.as_ref(),
+                params.user.clone(),
+                params.pass.clone(),
+                &merge_config,
+            )
+            .await
+            .map_err(|err| {
+                warn!("Direct OCR failed for {cache_key}: {err}");
+                (StatusCode::UNPROCESSABLE_ENTITY, err.to_string())
+            })?;
+            drop(permit);
+            state.insert_ocr_page(&cache_key, &params.context, &page);
+            page.results
+        }
+    };
+    state.requests_processed.fetch_add(1, Ordering::Relaxed);
+
+    Ok(Json(serde_json::json!({
+        "cache_key": cache_key,
+        "hash": hash,
+        "results": state.corrected_results(&cache_key, results),
+    })))
+}
+
+/// OCRs the image sent as the `image` (or `file`) field of a multipart form.
+pub async fn ocr_upload_handler(
+    State(state): State<AppState>,
+    Query(params): Query<DirectOcrParams>,
+    mut multipart: Multipart,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let mut image_bytes = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?
+    {
+        if matches!(field.name(), Some("image" | "file")) {
+            let bytes = field
+                .bytes()
+                .await
+                .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+            image_bytes = Some(bytes.to_vec());
+            break;
+        }
+    }
+    let Some(image_bytes) = image_bytes else {
+        return Err((StatusCode::BAD_REQUEST, "Missing image field".to_string()));
+    };
+    ocr_image_bytes(&state, &params, image_bytes).await
+}
+
+/// OCRs a file under one of the configured library roots.
+pub async fn ocr_local_handler(
+    State(state): State<AppState>,
+    Query(params): Query<DirectOcrParams>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let Some(path) = params.path.as_deref() else {
+        return Err((StatusCode::BAD_REQUEST, "Missing path".to_string()));
+    };
+    let path = state
+        .resolve_library_path(path)
+        .map_err(|err| (StatusCode::FORBIDDEN, err))?;
+    let image_bytes = tokio::fs::read(&path)
+        .await
+        .map_err(|err| (StatusCode::NOT_FOUND, format!("{}: {err}", path.display())))?;
+    ocr_image_bytes(&state, &params, image_bytes).await
+}
+
+/// A page-normalized rectangle to OCR on its own. The image is either fetched from `url` or
+/// sent base64-encoded in `image`; only URL requests can be appended to the cached page.
+#[derive(Deserialize)]
+pub struct RegionRequest {
+    pub url: Option<String>,
+    pub image: Option<String>,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// Factor the crop is enlarged by before OCR; picked from the region size when omitted.
+    pub upscale: Option<f64>,
+    /// Adds the region's lines to the page's cached results.
+    #[serde(default)]
+    pub append: bool,
+    pub user: Option<String>,
+    pub pass: Option<String>,
+    pub language: Option<OcrLanguage>,
+    pub engine: Option<String>,
+    pub merge_profile: Option<String>,
+    pub merge_enabled: Option<bool>,
+    pub font_size_ratio: Option<f64>,
+    pub add_space_on_merge: Option<bool>,
+}
+
+pub async fn ocr_region_handler(
+    State(state): State<AppState>,
+    Json(req): Json<RegionRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let language = req.language.unwrap_or_default();
+    let engine = state
+        .resolve_engine(req.engine.as_deref())
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    let merge_config = state
+        .resolve_merge_config(
+            req.url.as_deref().unwrap_or_default(),
+            language,
+            req.merge_profile.as_deref(),
+            &MergeProfile {
+                enabled: req.merge_enabled,
+                font_size_ratio: req.font_size_ratio,
+                add_space_on_merge: req.add_space_on_merge,
+            },
+        )
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+
+    let image_bytes = match (&req.image, &req.url) {
+        (Some(image), _) => BASE64_STANDARD
+            .decode(image.trim())
+            .map_err(|err| (StatusCode::BAD_REQUEST, format!("Invalid image: {err}")))?,
+        (None, Some(url)) => logic::fetch_image(url, req.user.as_deref(), req.pass.as_deref())
+            .await
+            .map_err(|err| (StatusCode::BAD_GATEWAY, err.to_string()))?,
+        (None, None) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Either url or image is required".to_string(),
+            ));
+        }
+    };
+
+    let region = BoundingBox {
+        x: req.x,
+        y: req.y,
+        width: req.width,
+        height: req.height,
+        rotation: None,
+    };
+    let ctx = EngineContext {
+        user: req.user.clone(),
+        pass: req.pass.clone(),
+    };
+    let permit = state
+        .scheduler
+        .acquire(&SharedPriority::new(OcrPriority::Interactive))
+        .await;
+    let chunk = region::recognize_region(
+        &image_bytes,
+        &region,
+        req.upscale,
+        engine.as_ref(),
+        &ctx,
+        language,
+    )
+    .await
+    .map_err(|err| (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()))?;
+    drop(permit);
+    let results = region::region_results(chunk.clone(), &merge_config);
+
+    let cache_key = req
+        .url
+        .as_deref()
+        .map(|url| logic::get_merge_cache_key(url, &merge_config));
+    let appended = match cache_key.clone() {
+        Some(cache_key) if req.append => {
+            let worker_state = state.clone();
+            tokio::task::spawn_blocking(move || worker_state.append_region(&cache_key, chunk))
+                .await
+                .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+                .map_err(|err| (StatusCode::CONFLICT, format!("{err:#}")))?
+        }
+        _ => false,
+    };
+
+    Ok(Json(serde_json::json!({
+        "results": results,
+        "cache_key": cache_key,
+        "appended": appended,
+    })))
+}
+
 #[derive(Deserialize)]
 pub struct JobRequest {
     pub base_url: String,
@@ -127,6 +470,35 @@ pub struct JobRequest {
     pub pages: Option<Vec<String>>,
     pub add_space_on_merge: Option<bool>,
     pub language: Option<OcrLanguage>,
+    #[serde(default)]
+    pub engine: Option<String>,
+    #[serde(default)]
+    pub merge_profile: Option<String>,
+    #[serde(default)]
+    pub merge_enabled: Option<bool>,
+    #[serde(default)]
+    pub font_size_ratio: Option<f64>,
+    /// Queue priority; higher runs first.
+    #[serde(default)]
+    pub priority: Option<i64>,
+    /// The chapter after the one being read. Its pages are OCR'd ahead of other jobs.
+    #[serde(default)]
+    pub next_chapter: bool,
+}
+
+impl JobRequest {
+    fn merge_config(&self, state: &AppState) -> Result<MergeConfig, String> {
+        state.resolve_merge_config(
+            &self.base_url,
+            self.language.unwrap_or_default(),
+            self.merge_profile.as_deref(),
+            &MergeProfile {
+                enabled: self.merge_enabled,
+                font_size_ratio: self.font_size_ratio,
+                add_space_on_merge: self.add_space_on_merge,
+            },
+        )
+    }
 }
 
 #[derive(Deserialize)]
@@ -153,8 +525,11 @@ pub struct ChapterStatusBatchRequest {
 }
 
 async fn chapter_status(state: &AppState, req: JobRequest) -> Json<serde_json::Value> {
-    let language = req.language.unwrap_or_default();
-    let job_key = logic::get_cache_key(&req.base_url, Some(language));
+    let merge_config = match req.merge_config(state) {
+        Ok(merge_config) => merge_config,
+        Err(err) => return Json(serde_json::json!({ "error": err })),
+    };
+    let job_key = logic::get_merge_cache_key(&req.base_url, &merge_config);
     let progress = {
         state
             .active_chapter_jobs
@@ -172,6 +547,20 @@ async fn chapter_status(state: &AppState, req: JobRequest) -> Json<serde_json::V
         }));
     }
 
+    // Queued jobs count as processing so the reader shows them as on their way.
+    if state
+        .get_job(&job_key)
+        .is_some_and(|job| job.status == JobStatus::Queued)
+    {
+        let counts = state.count_job_pages(&job_key);
+        return Json(serde_json::json!({
+            "status": "processing",
+            "job_status": JobStatus::Queued,
+            "progress": counts.done + counts.failed,
+            "total": counts.total()
+        }));
+    }
+
     let mut cached_count = 0usize;
     let mut total_expected = 0usize;
     if let Some(page_list) = req.pages.as_ref() {
@@ -184,10 +573,9 @@ async fn chapter_status(state: &AppState, req: JobRequest) -> Json<serde_json::V
         }
         let mut cached_keys = Vec::new();
         for page in page_list {
-            let cache_key = logic::get_cache_key(page, Some(language));
+            let cache_key = logic::get_merge_cache_key(page, &merge_config);
             if state.has_cache_entry(&cache_key)
-                || state.has_cache_entry_prefix(&format!("{cache_key}?sourceId="))
-                || state.has_cache_entry_prefix(&format!("{cache_key}&sourceId="))
+                || state.has_source_cache_entry(page, &merge_config)
             {
                 cached_count += 1;
                 cached_keys.push(cache_key);
@@ -216,7 +604,13 @@ async fn chapter_status(state: &AppState, req: JobRequest) -> Json<serde_json::V
     // This commonly happens when pages were OCR'd on-demand (per-page) rather than via
     // a preprocess job that supplies the full page list.
     if cached_count > 0 && total_expected == 0 {
-        match logic::resolve_total_pages_from_graphql(&req.base_url, req.user.clone(), req.pass.clone()).await {
+        match logic::resolve_total_pages_from_graphql(
+            &req.base_url,
+            req.user.clone(),
+            req.pass.clone(),
+        )
+        .await
+        {
             Ok(page_count) if page_count > 0 => {
                 total_expected = page_count;
                 state.set_chapter_pages(&job_key, total_expected);
@@ -268,6 +662,12 @@ pub async fn is_chapter_preprocessed_get_handler(
             pages: None,
             add_space_on_merge: None,
             language: req.language,
+            engine: None,
+            merge_profile: None,
+            merge_enabled: None,
+            font_size_ratio: None,
+            priority: None,
+            next_chapter: false,
         },
     )
     .await
@@ -301,6 +701,12 @@ pub async fn is_chapters_preprocessed_handler(
                         pages: item.pages,
                         add_space_on_merge: None,
                         language,
+                        engine: None,
+                        merge_profile: None,
+                        merge_enabled: None,
+                        font_size_ratio: None,
+                        priority: None,
+                        next_chapter: false,
                     },
                 )
                 .await;
@@ -319,40 +725,410 @@ pub async fn preprocess_handler(
     Json(req): Json<JobRequest>,
 ) -> Json<serde_json::Value> {
     let language = req.language.unwrap_or_default();
+    let merge_config = match req.merge_config(&state) {
+        Ok(merge_config) => merge_config,
+        Err(err) => return Json(serde_json::json!({ "error": err })),
+    };
     let pages = match req.pages {
         Some(p) => p,
         None => return Json(serde_json::json!({ "error": "No pages provided" })),
     };
-    let chapter_key = logic::get_cache_key(&req.base_url, Some(language));
+    if let Err(err) = state.resolve_engine(req.engine.as_deref()) {
+        return Json(serde_json::json!({ "error": err }));
+    }
+    let chapter_key = logic::get_merge_cache_key(&req.base_url, &merge_config);
+    state.supply_credentials(&req.base_url, req.user.as_deref(), req.pass.as_deref());
 
     let is_processing = {
         state
             .active_chapter_jobs
             .read()
             .expect("lock poisoned")
-            .contains_key(&logic::get_cache_key(&req.base_url, Some(language)))
-    };
+            .contains_key(&chapter_key)
+    } || state
+        .get_job(&chapter_key)
+        .is_some_and(|job| job.status == JobStatus::Queued);
 
     if is_processing {
         return Json(serde_json::json!({ "status": "already_processing" }));
     }
 
-    let state_clone = state.clone();
-    tokio::spawn(async move {
-        jobs::run_chapter_job(
-            state_clone,
-            req.base_url,
-            pages,
-            req.user,
-            req.pass,
-            req.context,
-            req.add_space_on_merge,
+    let now = now_unix();
+    state.enqueue_job(
+        &JobRecord {
+            job_id: chapter_key,
+            base_url: req.base_url,
+            context: req.context,
             language,
+            engine: req.engine,
+            merge_config,
+            user: req.user,
+            pass: req.pass,
+            status: JobStatus::Queued,
+            priority: req.priority.unwrap_or_default(),
+            ocr_priority: if req.next_chapter {
+                OcrPriority::NextChapter
+            } else {
+                OcrPriority::Bulk
+            },
+            created_at: now,
+            updated_at: now,
+        },
+        &pages,
+    );
+
+    Json(serde_json::json!({ "status": "started" }))
+}
+
+/// Preprocess request for a whole series, or the chapters `from..=to` of it (Suwayomi chapter
+/// indexes).
+#[derive(Deserialize)]
+pub struct SeriesJobRequest {
+    /// Suwayomi manga id.
+    pub manga_id: String,
+    /// Suwayomi server as the reader sees it; chapter URLs are built against it.
+    #[serde(default = "default_server_url")]
+    pub server_url: String,
+    pub from: Option<u32>,
+    pub to: Option<u32>,
+    pub user: Option<String>,
+    pub pass: Option<String>,
+    #[serde(default = "default_context")]
+    pub context: String,
+    pub add_space_on_merge: Option<bool>,
+    pub language: Option<OcrLanguage>,
+    pub engine: Option<String>,
+    pub merge_profile: Option<String>,
+    pub merge_enabled: Option<bool>,
+    pub font_size_ratio: Option<f64>,
+    /// Queue priority of the chapter jobs; higher runs first.
+    pub priority: Option<i64>,
+}
+
+fn default_server_url() -> String {
+    "http://127.0.0.1:4568".to_string()
+}
+
+#[derive(Deserialize)]
+pub struct SeriesJobControlRequest {
+    pub series_id: String,
+}
+
+pub async fn preprocess_series_handler(
+    State(state): State<AppState>,
+    Json(req): Json<SeriesJobRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let language = req.language.unwrap_or_default();
+    state
+        .resolve_engine(req.engine.as_deref())
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    let series_url = format!(
+        "{}/api/v1/manga/{}/",
+        req.server_url.trim_end_matches('/'),
+        req.manga_id
+    );
+    let merge_config = state
+        .resolve_merge_config(
+            &series_url,
+            language,
+            req.merge_profile.as_deref(),
+            &MergeProfile {
+                enabled: req.merge_enabled,
+                font_size_ratio: req.font_size_ratio,
+                add_space_on_merge: req.add_space_on_merge,
+            },
         )
-        .await;
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    let series_id = logic::get_merge_cache_key(&series_url, &merge_config);
+    state.supply_credentials(&series_url, req.user.as_deref(), req.pass.as_deref());
+
+    if let Some(existing) = state.get_series_job(&series_id)
+        && state.series_summary(existing).status != SeriesStatus::Done
+    {
+        return Ok(Json(serde_json::json!({
+            "status": "already_processing",
+            "series_id": series_id,
+        })));
+    }
+
+    let chapters: Vec<(u32, String)> = logic::resolve_series_chapters(
+        &req.server_url,
+        &req.manga_id,
+        req.user.clone(),
+        req.pass.clone(),
+    )
+    .await
+    .map_err(|err| (StatusCode::BAD_GATEWAY, format!("{err:#}")))?
+    .into_iter()
+    .filter(|index| req.from.is_none_or(|from| *index >= from))
+    .filter(|index| req.to.is_none_or(|to| *index <= to))
+    .map(|index| {
+        (
+            index,
+            logic::chapter_base_url(&req.server_url, &req.manga_id, index),
+        )
+    })
+    .collect();
+    if chapters.is_empty() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "No chapters to preprocess".to_string(),
+        ));
+    }
+
+    let now = now_unix();
+    let created = state.create_series_job(
+        &SeriesJob {
+            series_id: series_id.clone(),
+            manga_id: req.manga_id,
+            context: req.context,
+            language,
+            engine: req.engine,
+            merge_config,
+            user: req.user,
+            pass: req.pass,
+            priority: req.priority.unwrap_or_default(),
+            created_at: now,
+            updated_at: now,
+        },
+        &chapters,
+    );
+    if !created {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to store the series job".to_string(),
+        ));
+    }
+    tokio::spawn(series::run_series_job(state.clone(), series_id.clone()));
+
+    Ok(Json(serde_json::json!({
+        "status": "started",
+        "series_id": series_id,
+        "chapters": chapters.len(),
+    })))
+}
+
+pub async fn list_series_jobs_handler(
+    State(state): State<AppState>,
+) -> Json<Vec<SeriesJobSummary>> {
+    let summaries = state
+        .list_series_jobs()
+        .into_iter()
+        .map(|job| state.series_summary(job))
+        .collect();
+    Json(summaries)
+}
+
+pub async fn cancel_series_job_handler(
+    State(state): State<AppState>,
+    Json(req): Json<SeriesJobControlRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if !state.cancel_series_job(&req.series_id) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("No series job for {}", req.series_id),
+        ));
+    }
+    Ok(Json(serde_json::json!({ "status": "cancelled" })))
+}
+
+// --- Job Queue ---
+
+#[derive(Serialize)]
+pub struct JobSummary {
+    #[serde(flatten)]
+    pub job: JobRecord,
+    pub pages: JobPageCounts,
+    pub failed_pages: Vec<JobPage>,
+}
+
+#[derive(Deserialize)]
+pub struct JobControlRequest {
+    pub job_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct JobPriorityRequest {
+    pub job_id: String,
+    pub priority: i64,
+}
+
+#[derive(Deserialize)]
+pub struct JobRetryRequest {
+    pub job_id: String,
+    /// Retry a single page; all failed pages of the job when omitted.
+    pub page_index: Option<usize>,
+}
+
+fn job_not_found(job_id: &str) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, format!("No job for {job_id}"))
+}
+
+pub async fn list_jobs_handler(State(state): State<AppState>) -> Json<Vec<JobSummary>> {
+    let jobs = state
+        .list_jobs()
+        .into_iter()
+        .map(|job| {
+            let pages = state.count_job_pages(&job.job_id);
+            let failed_pages = state
+                .get_job_pages(&job.job_id)
+                .into_iter()
+                .filter(|page| page.status == PageStatus::Failed)
+                .collect();
+            JobSummary {
+                job,
+                pages,
+                failed_pages,
+            }
+        })
+        .collect();
+    Json(jobs)
+}
+
+pub async fn pause_job_handler(
+    State(state): State<AppState>,
+    Json(req): Json<JobControlRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let job = state
+        .get_job(&req.job_id)
+        .ok_or_else(|| job_not_found(&req.job_id))?;
+    if !matches!(job.status, JobStatus::Queued | JobStatus::Running) {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Job is {}", job.status.as_str()),
+        ));
+    }
+    state.set_job_status(&req.job_id, JobStatus::Paused);
+    Ok(Json(serde_json::json!({ "status": "paused" })))
+}
+
+pub async fn resume_job_handler(
+    State(state): State<AppState>,
+    Json(req): Json<JobControlRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let job = state
+        .get_job(&req.job_id)
+        .ok_or_else(|| job_not_found(&req.job_id))?;
+    if job.status != JobStatus::Paused {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Job is {}", job.status.as_str()),
+        ));
+    }
+    state.set_job_status(&req.job_id, JobStatus::Queued);
+    Ok(Json(serde_json::json!({ "status": "queued" })))
+}
+
+pub async fn cancel_job_handler(
+    State(state): State<AppState>,
+    Json(req): Json<JobControlRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if state.get_job(&req.job_id).is_none() {
+        return Err(job_not_found(&req.job_id));
+    }
+    state.cancel_job(&req.job_id);
+    Ok(Json(serde_json::json!({ "status": "cancelled" })))
+}
+
+pub async fn reprioritize_job_handler(
+    State(state): State<AppState>,
+    Json(req): Json<JobPriorityRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if !state.set_job_priority(&req.job_id, req.priority) {
+        return Err(job_not_found(&req.job_id));
+    }
+    Ok(Json(
+        serde_json::json!({ "status": "ok", "priority": req.priority }),
+    ))
+}
+
+pub async fn retry_job_handler(
+    State(state): State<AppState>,
+    Json(req): Json<JobRetryRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if state.get_job(&req.job_id).is_none() {
+        return Err(job_not_found(&req.job_id));
+    }
+    let retried = state.retry_job_pages(&req.job_id, req.page_index);
+    Ok(Json(
+        serde_json::json!({ "status": "ok", "retried": retried }),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    /// Comma-separated job ids (chapter keys) to follow. Every job when omitted.
+    pub jobs: Option<String>,
+}
+
+/// Server-Sent Events stream of job progress. One connection can follow many chapters.
+pub async fn events_handler(
+    State(state): State<AppState>,
+    Query(query): Query<EventsQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let filter = query.jobs.map(|jobs| {
+        jobs.split(',')
+            .map(str::trim)
+            .filter(|job| !job.is_empty())
+            .map(str::to_string)
+            .collect()
     });
 
-    Json(serde_json::json!({ "status": "started" }))
+    let stream = events::subscribe(&state, filter).map(|event| {
+        let sse = Event::default().event(event.name());
+        Ok(sse.json_data(&event).unwrap_or(Event::default()))
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+}
+
+// --- Cache Budget ---
+
+/// The chapter key a reader without per-request merge settings ends up using.
+fn reader_chapter_key(state: &AppState, base_url: &str, language: Option<OcrLanguage>) -> String {
+    let language = language.unwrap_or_default();
+    state
+        .resolve_merge_config(base_url, language, None, &MergeProfile::default())
+        .map(|merge_config| logic::get_merge_cache_key(base_url, &merge_config))
+        .unwrap_or_else(|_| logic::get_cache_key(base_url, Some(language)))
+}
+
+#[derive(Deserialize)]
+pub struct PinChapterRequest {
+    pub base_url: String,
+    pub language: Option<OcrLanguage>,
+}
+
+pub async fn cache_stats_handler(State(state): State<AppState>) -> Json<CacheStats> {
+    Json(state.cache_stats())
+}
+
+pub async fn evict_cache_handler(
+    State(state): State<AppState>,
+) -> Result<Json<EvictionReport>, (StatusCode, String)> {
+    tokio::task::spawn_blocking(move || state.evict_cache())
+        .await
+        .map(Json)
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+}
+
+/// Pinned chapters are never evicted, no matter how long ago they were read.
+pub async fn pin_chapter_handler(
+    State(state): State<AppState>,
+    Json(req): Json<PinChapterRequest>,
+) -> Json<serde_json::Value> {
+    let chapter_key = reader_chapter_key(&state, &req.base_url, req.language);
+    state.pin_chapter(&chapter_key);
+    Json(serde_json::json!({ "status": "pinned", "chapter_key": chapter_key }))
+}
+
+pub async fn unpin_chapter_handler(
+    State(state): State<AppState>,
+    Json(req): Json<PinChapterRequest>,
+) -> Json<serde_json::Value> {
+    let chapter_key = reader_chapter_key(&state, &req.base_url, req.language);
+    let removed = state.unpin_chapter(&chapter_key);
+    Json(serde_json::json!({ "status": if removed { "unpinned" } else { "not_pinned" } }))
 }
 
 pub async fn purge_cache_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
@@ -360,6 +1136,77 @@ pub async fn purge_cache_handler(State(state): State<AppState>) -> Json<serde_js
     Json(serde_json::json!({ "status": "cleared" }))
 }
 
+#[derive(Deserialize)]
+pub struct PurgeChapterRequest {
+    pub base_url: String,
+    pub language: Option<OcrLanguage>,
+}
+
+#[derive(Deserialize)]
+pub struct PurgeSeriesRequest {
+    /// Suwayomi manga id, as reported by `/cache/stats`.
+    pub series: String,
+}
+
+#[derive(Deserialize)]
+pub struct PurgeLanguageRequest {
+    pub language: OcrLanguage,
+}
+
+#[derive(Deserialize)]
+pub struct PurgeOlderThanRequest {
+    /// Unix timestamp in seconds.
+    pub timestamp: i64,
+}
+
+async fn purge(
+    state: AppState,
+    target: PurgeTarget,
+) -> Result<Json<PurgeReport>, (StatusCode, String)> {
+    info!("Purging OCR cache: {target:?}");
+    tokio::task::spawn_blocking(move || state.purge_cache(&target))
+        .await
+        .map(Json)
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+}
+
+pub async fn purge_chapter_handler(
+    State(state): State<AppState>,
+    Json(req): Json<PurgeChapterRequest>,
+) -> Result<Json<PurgeReport>, (StatusCode, String)> {
+    let chapter_key = reader_chapter_key(&state, &req.base_url, req.language);
+    purge(state, PurgeTarget::Chapter(chapter_key)).await
+}
+
+pub async fn purge_series_handler(
+    State(state): State<AppState>,
+    Json(req): Json<PurgeSeriesRequest>,
+) -> Result<Json<PurgeReport>, (StatusCode, String)> {
+    purge(state, PurgeTarget::Series(req.series)).await
+}
+
+pub async fn purge_language_handler(
+    State(state): State<AppState>,
+    Json(req): Json<PurgeLanguageRequest>,
+) -> Result<Json<PurgeReport>, (StatusCode, String)> {
+    purge(state, PurgeTarget::Language(req.language)).await
+}
+
+pub async fn purge_older_than_handler(
+    State(state): State<AppState>,
+    Json(req): Json<PurgeOlderThanRequest>,
+) -> Result<Json<PurgeReport>, (StatusCode, String)> {
+    purge(state, PurgeTarget::OlderThan(req.timestamp)).await
+}
+
+/// Drops pages recognized with another language than their cache key asks for, so they are
+/// OCR'd again with the right one.
+pub async fn purge_language_mismatch_handler(
+    State(state): State<AppState>,
+) -> Result<Json<PurgeReport>, (StatusCode, String)> {
+    purge(state, PurgeTarget::LanguageMismatch).await
+}
+
 pub async fn export_cache_handler(
     State(state): State<AppState>,
 ) -> Json<std::collections::HashMap<String, CacheEntry>> {
@@ -373,3 +1220,298 @@ pub async fn import_cache_handler(
     let added = state.import_cache(data);
     Json(serde_json::json!({ "message": "Import successful", "added": added }))
 }
+
+#[derive(Deserialize)]
+pub struct RemergeRequest {
+    #[serde(flatten)]
+    pub filter: ArchiveFilter,
+    #[serde(flatten)]
+    pub overrides: MergeProfile,
+}
+
+/// Re-merges cached pages from their raw chunks, e.g. after changing merge settings.
+pub async fn remerge_handler(
+    State(state): State<AppState>,
+    Json(req): Json<RemergeRequest>,
+) -> Result<Json<RemergeReport>, (StatusCode, String)> {
+    let report =
+        tokio::task::spawn_blocking(move || state.remerge_cache(&req.filter, &req.overrides))
+            .await
+            .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    info!(
+        "Re-merged {} cached pages ({} without raw chunks)",
+        report.remerged, report.missing_raw
+    );
+    Ok(Json(report))
+}
+
+// --- Merge Profiles ---
+
+#[derive(Deserialize)]
+pub struct SaveMergeProfileRequest {
+    pub name: String,
+    #[serde(flatten)]
+    pub profile: MergeProfile,
+}
+
+#[derive(Deserialize)]
+pub struct MergeProfileNameRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct AssignMergeProfileRequest {
+    /// Suwayomi manga id.
+    pub series: String,
+    /// Profile to use for the series; clears the assignment when omitted.
+    pub profile: Option<String>,
+}
+
+pub async fn list_merge_profiles_handler(State(state): State<AppState>) -> Json<MergeProfiles> {
+    Json(state.merge_profiles())
+}
+
+pub async fn save_merge_profile_handler(
+    State(state): State<AppState>,
+    Json(req): Json<SaveMergeProfileRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if req.name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Profile name is empty".to_string()));
+    }
+    state.save_merge_profile(&req.name, &req.profile);
+    Ok(Json(
+        serde_json::json!({ "status": "saved", "name": req.name }),
+    ))
+}
+
+pub async fn delete_merge_profile_handler(
+    State(state): State<AppState>,
+    Json(req): Json<MergeProfileNameRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if !state.delete_merge_profile(&req.name) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Unknown merge profile: {}", req.name),
+        ));
+    }
+    Ok(Json(serde_json::json!({ "status": "deleted" })))
+}
+
+pub async fn assign_merge_profile_handler(
+    State(state): State<AppState>,
+    Json(req): Json<AssignMergeProfileRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if let Some(profile) = req.profile.as_deref()
+        && state.get_merge_profile(profile).is_none()
+    {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Unknown merge profile: {profile}"),
+        ));
+    }
+    state.assign_series_profile(&req.series, req.profile.as_deref());
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+#[derive(Deserialize)]
+pub struct SeriesPrefetchRequest {
+    /// Suwayomi manga id.
+    pub series: String,
+    /// The series' own settings; it goes back to the global ones when omitted.
+    pub settings: Option<SeriesPrefetch>,
+}
+
+pub async fn list_series_prefetch_handler(
+    State(state): State<AppState>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "chapters": state.config().prefetch_chapters,
+        "series": state.all_series_prefetch(),
+    }))
+}
+
+pub async fn set_series_prefetch_handler(
+    State(state): State<AppState>,
+    Json(req): Json<SeriesPrefetchRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if req.series.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Series is empty".to_string()));
+    }
+    state.set_series_prefetch(&req.series, req.settings.as_ref());
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+/// Identifies a page the same way [`OcrRequest`] does, so corrections attach to the results
+/// the reader is actually shown.
+#[derive(Deserialize)]
+pub struct CorrectionsQuery {
+    pub url: String,
+    pub language: Option<OcrLanguage>,
+    pub merge_profile: Option<String>,
+    pub merge_enabled: Option<bool>,
+    pub font_size_ratio: Option<f64>,
+    pub add_space_on_merge: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct AddCorrectionsRequest {
+    #[serde(flatten)]
+    pub page: CorrectionsQuery,
+    pub edits: Vec<Correction>,
+}
+
+fn corrections_cache_key(
+    state: &AppState,
+    page: &CorrectionsQuery,
+) -> Result<String, (StatusCode, String)> {
+    let merge_config = state
+        .resolve_merge_config(
+            &page.url,
+            page.language.unwrap_or_default(),
+            page.merge_profile.as_deref(),
+            &MergeProfile {
+                enabled: page.merge_enabled,
+                font_size_ratio: page.font_size_ratio,
+                add_space_on_merge: page.add_space_on_merge,
+            },
+        )
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    Ok(logic::get_merge_cache_key(&page.url, &merge_config))
+}
+
+/// Corrections of a page, plus its corrected results when the page is cached.
+fn corrections_response(state: &AppState, cache_key: String) -> serde_json::Value {
+    let corrections = state.page_corrections(&cache_key).unwrap_or_default();
+    let results = state
+        .get_cache_entry(&cache_key)
+        .map(|entry| state.corrected_results(&cache_key, entry.data));
+    serde_json::json!({
+        "cache_key": cache_key,
+        "corrections": corrections,
+        "results": results,
+    })
+}
+
+pub async fn get_corrections_handler(
+    State(state): State<AppState>,
+    Query(page): Query<CorrectionsQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let cache_key = corrections_cache_key(&state, &page)?;
+    Ok(Json(corrections_response(&state, cache_key)))
+}
+
+pub async fn add_corrections_handler(
+    State(state): State<AppState>,
+    Json(req): Json<AddCorrectionsRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let cache_key = corrections_cache_key(&state, &req.page)?;
+    state.add_corrections(&cache_key, req.edits);
+    Ok(Json(corrections_response(&state, cache_key)))
+}
+
+pub async fn clear_corrections_handler(
+    State(state): State<AppState>,
+    Json(page): Json<CorrectionsQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let cache_key = corrections_cache_key(&state, &page)?;
+    state.clear_corrections(&cache_key);
+    Ok(Json(corrections_response(&state, cache_key)))
+}
+
+/// Every page's corrections keyed by cache key, for the sync payload.
+pub async fn export_corrections_handler(
+    State(state): State<AppState>,
+) -> Json<HashMap<String, PageCorrections>> {
+    Json(state.all_corrections())
+}
+
+pub async fn import_corrections_handler(
+    State(state): State<AppState>,
+    Json(pages): Json<HashMap<String, PageCorrections>>,
+) -> Json<serde_json::Value> {
+    let imported = state.import_corrections(&pages);
+    Json(serde_json::json!({ "imported": imported, "skipped": pages.len() - imported }))
+}
+
+#[derive(Deserialize)]
+pub struct ImportArchiveQuery {
+    #[serde(default)]
+    pub policy: ImportPolicy,
+    #[serde(flatten)]
+    pub filter: ArchiveFilter,
+}
+
+/// Streams the cache as a zstd-compressed NDJSON archive.
+pub async fn export_archive_handler(
+    State(state): State<AppState>,
+    Query(filter): Query<ArchiveFilter>,
+) -> impl IntoResponse {
+    let (reader, writer) = tokio::io::duplex(64 * 1024);
+    let writer = SyncIoBridge::new(writer);
+    tokio::task::spawn_blocking(move || match state.export_archive(writer, &filter) {
+        Ok(written) => info!("Exported {written} OCR cache entries"),
+        Err(err) => warn!("OCR cache export failed: {err:#}"),
+    });
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/zstd"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"ocr-cache.ndjson.zst\"",
+            ),
+        ],
+        Body::from_stream(ReaderStream::new(reader)),
+    )
+}
+
+pub async fn import_archive_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ImportArchiveQuery>,
+    body: Body,
+) -> Result<Json<ImportReport>, (StatusCode, String)> {
+    let stream = body
+        .into_data_stream()
+        .map(|chunk| chunk.map_err(io::Error::other));
+    let reader = SyncIoBridge::new(StreamReader::new(stream));
+    let report = tokio::task::spawn_blocking(move || {
+        state.import_archive(reader, query.policy, &query.filter)
+    })
+    .await
+    .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+    .map_err(|err| (StatusCode::BAD_REQUEST, format!("{err:#}")))?;
+
+    info!(
+        "Imported {} OCR cache entries ({} skipped, {} invalid)",
+        report.imported, report.skipped, report.invalid
+    );
+    Ok(Json(report))
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub series: Option<String>,
+    pub chapter: Option<String>,
+    pub language: Option<OcrLanguage>,
+    pub limit: Option<usize>,
+}
+
+pub async fn search_handler(
+    State(state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let filter = SearchFilter {
+        series: query.series,
+        chapter: query.chapter,
+        language: query.language,
+    };
+    let limit = query.limit.unwrap_or(search::DEFAULT_SEARCH_LIMIT);
+    let hits = tokio::task::spawn_blocking(move || state.search_text(&query.q, &filter, limit))
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, format!("{err:#}")))?;
+    Ok(Json(
+        serde_json::json!({ "count": hits.len(), "hits": hits }),
+    ))
+}
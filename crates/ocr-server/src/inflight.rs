@@ -0,0 +1,155 @@
+//! Coalescing of concurrent OCR calls for the same page.
+//!
+//! The reader, a preprocess job and a second tab can all ask for a page at once. The first
+//! caller does the work; everyone else arriving while it runs waits for its result instead of
+//! sending the same image to the engine again. A waiter more urgent than the running caller
+//! raises the run's place in the [`crate::scheduler`] queue.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::OnceCell;
+use tracing::info;
+
+use crate::{
+    logic::OcrResult,
+    scheduler::{OcrPriority, SharedPriority},
+};
+
+/// Errors are kept as text so every waiter gets a copy.
+type Outcome = Result<Vec<OcrResult>, String>;
+
+#[derive(Clone)]
+struct Flight {
+    outcome: Arc<OnceCell<Outcome>>,
+    priority: SharedPriority,
+}
+
+/// Pages being OCR'd right now, keyed by cache key.
+#[derive(Clone, Default)]
+pub struct InFlight {
+    pages: Arc<Mutex<HashMap<String, Flight>>>,
+}
+
+impl InFlight {
+    /// Number of pages currently being OCR'd.
+    pub fn count(&self) -> usize {
+        self.pages.lock().expect("lock poisoned").len()
+    }
+
+    /// Runs `work` for `cache_key`, or waits for the call already running for it. If the
+    /// running caller goes away before finishing, one of the waiters takes over the work.
+    /// `work` is handed the run's priority, raised to that of its most urgent waiter.
+    pub async fn run<Fut>(
+        &self,
+        cache_key: &str,
+        priority: OcrPriority,
+        work: impl FnOnce(SharedPriority) -> Fut,
+    ) -> anyhow::Result<Vec<OcrResult>>
+    where
+        Fut: Future<Output = anyhow::Result<Vec<OcrResult>>>,
+    {
+        let flight = {
+            let mut pages = self.pages.lock().expect("lock poisoned");
+            let flight = pages
+                .entry(cache_key.to_string())
+                .or_insert_with(|| Flight {
+                    outcome: Arc::default(),
+                    priority: SharedPriority::new(priority),
+                });
+            if flight.outcome.initialized() || Arc::strong_count(&flight.outcome) > 1 {
+                info!("Waiting for the OCR already running for {cache_key}");
+            }
+            flight.priority.raise(priority);
+            flight.clone()
+        };
+        let caller = Caller {
+            pages: &self.pages,
+            cache_key,
+            outcome: Some(flight.outcome),
+        };
+
+        let outcome = caller
+            .outcome()
+            .get_or_init(|| async {
+                work(flight.priority.clone())
+                    .await
+                    .map_err(|err| format!("{err:#}"))
+            })
+            .await
+            .clone();
+
+        outcome.map_err(anyhow::Error::msg)
+    }
+}
+
+/// A caller's hold on a flight. Dropping it, whether the call finished or was cancelled,
+/// takes the flight off the map once it has an outcome or nobody else is waiting on it.
+struct Caller<'a> {
+    pages: &'a Mutex<HashMap<String, Flight>>,
+    cache_key: &'a str,
+    outcome: Option<Arc<OnceCell<Outcome>>>,
+}
+
+impl Caller<'_> {
+    fn outcome(&self) -> &OnceCell<Outcome> {
+        self.outcome
+            .as_ref()
+            .expect("outcome is only taken on drop")
+    }
+}
+
+impl Drop for Caller<'_> {
+    fn drop(&mut self) {
+        let Some(outcome) = self.outcome.take() else {
+            return;
+        };
+        let mut pages = self.pages.lock().unwrap_or_else(|err| err.into_inner());
+        let ours = pages
+            .get(self.cache_key)
+            .is_some_and(|current| Arc::ptr_eq(&current.outcome, &outcome));
+        // The map holds one reference and every caller still waiting one more. Ours is let go
+        // while the lock is held, so callers leaving at once still see each other leave.
+        if ours && (outcome.initialized() || Arc::strong_count(&outcome) == 2) {
+            pages.remove(self.cache_key);
+        }
+        drop(outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn a_cancelled_sole_caller_leaves_no_flight_behind() {
+        let in_flight = InFlight::default();
+        let call = in_flight.run("page", OcrPriority::Interactive, |_| {
+            std::future::pending::<anyhow::Result<Vec<OcrResult>>>()
+        });
+        let _ = tokio::time::timeout(Duration::from_millis(10), call).await;
+        assert_eq!(in_flight.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_waiter_takes_over_from_a_cancelled_caller() {
+        let in_flight = InFlight::default();
+        let first = in_flight.run("page", OcrPriority::Bulk, |_| {
+            std::future::pending::<anyhow::Result<Vec<OcrResult>>>()
+        });
+        let second = in_flight.run("page", OcrPriority::Interactive, |_| async { Ok(Vec::new()) });
+        let mut first = Box::pin(first);
+        assert!(futures::poll!(&mut first).is_pending());
+        let mut second = Box::pin(second);
+        assert!(futures::poll!(&mut second).is_pending());
+        assert_eq!(in_flight.count(), 1);
+
+        drop(first);
+        assert!(second.await.is_ok());
+        assert_eq!(in_flight.count(), 0);
+    }
+}
@@ -1,97 +1,219 @@
-use std::sync::{
-    Arc,
-    atomic::{AtomicUsize, Ordering},
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
 };
 
 use futures::StreamExt;
 
 use crate::{
-    language::OcrLanguage,
-    state::{AppState, JobProgress},
+    content::PageTarget,
+    engine::EngineContext,
+    events::JobEvent,
+    series::SeriesChapterStatus,
+    state::{AppState, JobProgress, JobRecord, JobStatus, PageStatus},
 };
 
-pub async fn run_chapter_job(
-    state: AppState,
-    base_url: String,
-    pages: Vec<String>,
-    user: Option<String>,
-    pass: Option<String>,
-    context: String,
-    add_space_on_merge: Option<bool>,
-    language: OcrLanguage,
-) {
+/// How many chapters the queue works on at once.
+fn max_active_jobs() -> usize {
+    if cfg!(target_os = "android") { 1 } else { 2 }
+}
+
+/// Runs forever, starting queued jobs from `ocr_jobs` whenever there is room.
+pub async fn run_queue(state: AppState) {
+    loop {
+        loop {
+            let running: Vec<String> = state
+                .active_chapter_jobs
+                .read()
+                .expect("lock poisoned")
+                .keys()
+                .cloned()
+                .collect();
+            if running.len() >= max_active_jobs() {
+                break;
+            }
+            let Some(job) = state.next_queued_job(&running) else {
+                break;
+            };
+
+            state.set_job_status(&job.job_id, JobStatus::Running);
+            {
+                state
+                    .active_chapter_jobs
+                    .write()
+                    .expect("lock poisoned")
+                    .insert(
+                        job.job_id.clone(),
+                        JobProgress {
+                            current: 0,
+                            total: 0,
+                        },
+                    );
+            }
+
+            let state = state.clone();
+            tokio::spawn(async move {
+                let job_id = job.job_id.clone();
+                run_chapter_job(state.clone(), job).await;
+                state
+                    .active_chapter_jobs
+                    .write()
+                    .expect("lock poisoned")
+                    .remove(&job_id);
+                state.job_notify.notify_one();
+            });
+        }
+
+        // Wake up on new work, or periodically as a safety net.
+        let _ = tokio::time::timeout(Duration::from_secs(30), state.job_notify.notified()).await;
+    }
+}
+
+fn is_still_running(state: &AppState, job_id: &str) -> bool {
+    state
+        .get_job(job_id)
+        .is_some_and(|job| job.status == JobStatus::Running)
+}
+
+pub async fn run_chapter_job(state: AppState, job: JobRecord) {
+    let job_id = job.job_id.clone();
+    let context = job.context.clone();
+
+    let pages = state.get_job_pages(&job_id);
     let total = pages.len();
-    let job_id = crate::logic::get_cache_key(&base_url, Some(language));
+    let already_finished = pages
+        .iter()
+        .filter(|page| page.status != PageStatus::Pending)
+        .count();
+    let pending: Vec<_> = pages
+        .into_iter()
+        .filter(|page| page.status == PageStatus::Pending)
+        .collect();
 
+    let initial_progress = JobProgress {
+        current: already_finished,
+        total,
+    };
     {
-        state
+        if let Some(prog) = state
             .active_chapter_jobs
             .write()
             .expect("lock poisoned")
-            .insert(job_id.clone(), JobProgress { current: 0, total });
+            .get_mut(&job_id)
+        {
+            *prog = initial_progress;
+        }
     }
+    state.emit(JobEvent::JobStarted {
+        job_id: job_id.clone(),
+        progress: initial_progress,
+    });
+
+    let engine = match state.resolve_engine(job.engine.as_deref()) {
+        Ok(engine) => engine,
+        Err(err) => {
+            tracing::warn!("[Job {job_id}] {err}");
+            for page in &pending {
+                state.set_job_page_status(&job_id, page.page_index, PageStatus::Failed, Some(&err));
+            }
+            finalize_job(&state, &job_id, total);
+            return;
+        }
+    };
 
     state.active_jobs.fetch_add(1, Ordering::Relaxed);
-    tracing::info!("[Job] Started for {} ({} pages)", context, total);
+    tracing::info!(
+        "[Job] Started for {} ({} of {} pages left)",
+        context,
+        pending.len(),
+        total
+    );
 
-    let completed_counter = Arc::new(AtomicUsize::new(0));
-    let processed_counter = Arc::new(AtomicUsize::new(0));
-    let stream = futures::stream::iter(pages.into_iter());
+    let completed_counter = Arc::new(AtomicUsize::new(already_finished));
+    let stream = futures::stream::iter(pending);
 
-    // Change from 6 to 2 or 3 for Android stability
-    let concurrency_limit = if cfg!(target_os = "android") { 2 } else { 6 };
+    // The scheduler bounds OCR across all jobs; this only keeps a job from fetching more
+    // pages than could be OCR'd at once.
+    let concurrency_limit = state.config().ocr_concurrency.max(1);
 
     stream
-        .for_each_concurrent(concurrency_limit, |url| {
+        .for_each_concurrent(concurrency_limit, |page| {
             let state = state.clone();
+            let engine = engine.clone();
             let job_id = job_id.clone();
-            let user = user.clone();
-            let pass = pass.clone();
+            let user = job.user.clone();
+            let pass = job.pass.clone();
             let context = context.clone();
+            let merge_config = job.merge_config.clone();
+            let ocr_priority = job.ocr_priority;
             let completed_counter = completed_counter.clone();
-            let processed_counter = processed_counter.clone();
 
+            let url = page.url;
             let page_id = url.split('/').next_back().unwrap_or("unknown").to_string();
 
             async move {
-                let cache_key = crate::logic::get_cache_key(&url, Some(language));
-                let exists = state.has_cache_entry(&cache_key);
+                // Paused or cancelled jobs leave their remaining pages pending.
+                if !is_still_running(&state, &job_id) {
+                    return;
+                }
+
+                let cache_key = crate::logic::get_merge_cache_key(&url, &merge_config);
+                let exists = state.has_cache_entry_from(&cache_key, Some(engine.id()));
                 if exists {
                     state.insert_chapter_cache(&job_id, &cache_key);
-                    processed_counter.fetch_add(1, Ordering::Relaxed);
+                    state.set_job_page_status(&job_id, page.page_index, PageStatus::Done, None);
                     tracing::info!("[Page {page_id}] Skip (Cached)");
                 } else {
-                    tracing::info!("[Page {page_id}] Starting fetch_and_process (Async)...");
+                    tracing::info!("[Page {page_id}] Starting ocr_url (Async)...");
 
                     // None defaults to Smart Detection for space merging
-                    match crate::logic::fetch_and_process(
-                        &url,
-                        user,
-                        pass,
-                        add_space_on_merge,
-                        language,
-                    )
-                    .await
+                    match state
+                        .ocr_url(
+                            &PageTarget {
+                                url: &url,
+                                cache_key: &cache_key,
+                                context: &context,
+                            },
+                            engine.as_ref(),
+                            &EngineContext { user, pass },
+                            &merge_config,
+                            ocr_priority,
+                        )
+                        .await
                     {
-                        Ok(res) => {
-                            state.insert_cache_entry(
-                                &cache_key,
-                                &crate::state::CacheEntry {
-                                    context: context.clone(),
-                                    data: res,
-                                },
-                            );
+                        Ok(_) => {
                             state.insert_chapter_cache(&job_id, &cache_key);
-                            processed_counter.fetch_add(1, Ordering::Relaxed);
+                            state.set_job_page_status(
+                                &job_id,
+                                page.page_index,
+                                PageStatus::Done,
+                                None,
+                            );
                         }
                         Err(err) => {
                             tracing::warn!("[Page {page_id}] Failed: {err:?}");
+                            let error = format!("{err:#}");
+                            state.set_job_page_status(
+                                &job_id,
+                                page.page_index,
+                                PageStatus::Failed,
+                                Some(&error),
+                            );
+                            state.emit(JobEvent::PageFailed {
+                                job_id: job_id.clone(),
+                                page_index: page.page_index,
+                                url: url.clone(),
+                                error,
+                            });
                         }
                     }
                 }
 
                 let current = completed_counter.fetch_add(1, Ordering::Relaxed) + 1;
-                let processed_count = processed_counter.load(Ordering::Relaxed);
+                let processed_count = state.count_job_pages(&job_id).done;
                 state.set_chapter_progress(&job_id, total, processed_count);
 
                 {
@@ -104,23 +226,66 @@ pub async fn run_chapter_job(
                         prog.current = current;
                     }
                 }
+                state.emit(JobEvent::Progress {
+                    job_id: job_id.clone(),
+                    progress: JobProgress { current, total },
+                });
             }
         })
         .await;
 
     tracing::info!("[Job {job_id}] Finalize...");
-    let processed_count = processed_counter.load(Ordering::Relaxed);
-    state.set_chapter_progress(&job_id, total, processed_count);
-
+    finalize_job(&state, &job_id, total);
     state.active_jobs.fetch_sub(1, Ordering::Relaxed);
 
-    {
-        state
-            .active_chapter_jobs
-            .write()
-            .expect("lock poisoned")
-            .remove(&job_id);
+    tracing::info!("[Job {job_id}] Finished for {}", context);
+}
+
+/// Decides what happens to a job after a run: finished jobs are dropped from the queue, jobs
+/// with failed pages are kept for retrying, and jobs that still have pending pages go back
+/// into the queue unless they were paused or cancelled in the meantime.
+fn finalize_job(state: &AppState, job_id: &str, total: usize) {
+    let counts = state.count_job_pages(job_id);
+    state.set_chapter_progress(job_id, total, counts.done);
+
+    let Some(job) = state.get_job(job_id) else {
+        return;
+    };
+    match job.status {
+        JobStatus::Running if counts.pending > 0 => {
+            state.set_job_status(job_id, JobStatus::Queued);
+            return;
+        }
+        JobStatus::Running if counts.failed > 0 => {
+            state.set_job_status(job_id, JobStatus::Failed);
+            state.finish_series_chapter(job_id, SeriesChapterStatus::Failed);
+        }
+        JobStatus::Running => {
+            state.delete_job(job_id);
+            state.finish_series_chapter(job_id, SeriesChapterStatus::Done);
+        }
+        JobStatus::Cancelled => {
+            state.delete_job(job_id);
+            state.finish_series_chapter(job_id, SeriesChapterStatus::Cancelled);
+            state.emit(JobEvent::JobStopped {
+                job_id: job_id.to_string(),
+                status: job.status,
+            });
+            return;
+        }
+        status => {
+            state.emit(JobEvent::JobStopped {
+                job_id: job_id.to_string(),
+                status,
+            });
+            return;
+        }
     }
 
-    tracing::info!("[Job {job_id}] Finished for {}", context);
+    state.emit(JobEvent::JobCompleted {
+        job_id: job_id.to_string(),
+        processed: counts.done,
+        failed: counts.failed,
+        total,
+    });
 }
@@ -1,4 +1,7 @@
-use serde::{Deserialize, Serialize};
+use serde::{
+    Deserialize, Serialize,
+    de::{IntoDeserializer, value::Error as ValueError},
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -45,6 +48,8 @@ pub enum OcrLanguage {
     Vietnamese,
     Welsh,
     Cantonese,
+    /// Let the engine detect the script. Merging then uses the generic horizontal rules.
+    Auto,
 }
 
 impl OcrLanguage {
@@ -92,9 +97,15 @@ impl OcrLanguage {
             OcrLanguage::Vietnamese => "vietnamese",
             OcrLanguage::Welsh => "welsh",
             OcrLanguage::Cantonese => "cantonese",
+            OcrLanguage::Auto => "auto",
         }
     }
 
+    /// Parses the name used by [`OcrLanguage::as_str`] and in requests.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::deserialize(IntoDeserializer::<ValueError>::into_deserializer(name)).ok()
+    }
+
     pub fn prefers_vertical(&self) -> bool {
         matches!(
             self,
@@ -1,9 +1,28 @@
+pub mod archive;
+pub mod cache;
+pub mod config;
+pub mod content;
+pub mod corrections;
+pub mod engine;
+pub mod events;
 pub mod handlers;
+pub mod inflight;
 pub mod jobs;
 pub mod language;
 pub mod logic;
 pub mod merge;
+pub mod point;
+pub mod prefetch;
+pub mod profiles;
+pub mod reading_order;
+pub mod region;
+pub mod remerge;
+pub mod scheduler;
+pub mod search;
+pub mod series;
+pub mod sources;
 pub mod state;
+pub mod throttle;
 
 use std::path::PathBuf;
 
@@ -16,14 +35,31 @@ use state::AppState;
 
 /// Creates the OCR Router.
 pub fn create_router(cache_dir: PathBuf) -> Router {
-    let state = AppState::new(cache_dir);
+    create_router_with_state(AppState::new(cache_dir))
+}
 
-    // Spawn the job worker if you want strict concurrency,
-    // or we just spawn tasks per request (handled in handlers).
+/// Creates the OCR Router around an existing state, for servers that also read the OCR
+/// cache directly (see [`AppState::ocr_result_at`]).
+pub fn create_router_with_state(state: AppState) -> Router {
+    // Single worker that drains the persistent job queue (and resumes it after a restart).
+    tokio::spawn(jobs::run_queue(state.clone()));
+    tokio::spawn(cache::run_eviction(state.clone()));
+    tokio::spawn(remerge::run_remerge(state.clone()));
+    tokio::spawn(series::resume_series_jobs(state.clone()));
+    let search_state = state.clone();
+    tokio::task::spawn_blocking(move || search_state.rebuild_search_index_if_stale());
 
     Router::new()
         .route("/", get(handlers::status_handler))
         .route("/ocr", get(handlers::ocr_handler))
+        .route("/ocr-region", post(handlers::ocr_region_handler))
+        .route("/ocr-upload", post(handlers::ocr_upload_handler))
+        .route("/ocr-local", get(handlers::ocr_local_handler))
+        .route("/engines", get(handlers::engines_handler))
+        .route(
+            "/config",
+            get(handlers::get_config_handler).post(handlers::set_config_handler),
+        )
         .route(
             "/is-chapter-preprocessed",
             get(handlers::is_chapter_preprocessed_get_handler)
@@ -34,9 +70,86 @@ pub fn create_router(cache_dir: PathBuf) -> Router {
             post(handlers::is_chapters_preprocessed_handler),
         )
         .route("/preprocess-chapter", post(handlers::preprocess_handler))
+        .route(
+            "/preprocess-series",
+            post(handlers::preprocess_series_handler),
+        )
+        .route("/series-jobs", get(handlers::list_series_jobs_handler))
+        .route(
+            "/series-jobs/cancel",
+            post(handlers::cancel_series_job_handler),
+        )
+        .route(
+            "/prefetch/series",
+            get(handlers::list_series_prefetch_handler).post(handlers::set_series_prefetch_handler),
+        )
+        .route("/jobs", get(handlers::list_jobs_handler))
+        .route("/events", get(handlers::events_handler))
+        .route("/jobs/pause", post(handlers::pause_job_handler))
+        .route("/jobs/resume", post(handlers::resume_job_handler))
+        .route("/jobs/cancel", post(handlers::cancel_job_handler))
+        .route("/jobs/priority", post(handlers::reprioritize_job_handler))
+        .route("/jobs/retry", post(handlers::retry_job_handler))
+        .route("/cache/stats", get(handlers::cache_stats_handler))
+        .route("/cache/evict", post(handlers::evict_cache_handler))
+        .route("/cache/pin", post(handlers::pin_chapter_handler))
+        .route("/cache/unpin", post(handlers::unpin_chapter_handler))
         .route("/purge-cache", post(handlers::purge_cache_handler))
+        .route(
+            "/purge-cache/chapter",
+            post(handlers::purge_chapter_handler),
+        )
+        .route("/purge-cache/series", post(handlers::purge_series_handler))
+        .route(
+            "/purge-cache/language",
+            post(handlers::purge_language_handler),
+        )
+        .route(
+            "/purge-cache/older-than",
+            post(handlers::purge_older_than_handler),
+        )
+        .route(
+            "/purge-cache/language-mismatch",
+            post(handlers::purge_language_mismatch_handler),
+        )
         .route("/export-cache", get(handlers::export_cache_handler))
         .route("/import-cache", post(handlers::import_cache_handler))
+        .route("/remerge", post(handlers::remerge_handler))
+        .route(
+            "/merge-profiles",
+            get(handlers::list_merge_profiles_handler).post(handlers::save_merge_profile_handler),
+        )
+        .route(
+            "/merge-profiles/delete",
+            post(handlers::delete_merge_profile_handler),
+        )
+        .route(
+            "/merge-profiles/assign",
+            post(handlers::assign_merge_profile_handler),
+        )
+        .route(
+            "/corrections",
+            get(handlers::get_corrections_handler).post(handlers::add_corrections_handler),
+        )
+        .route(
+            "/corrections/clear",
+            post(handlers::clear_corrections_handler),
+        )
+        .route(
+            "/corrections/export",
+            get(handlers::export_corrections_handler),
+        )
+        .route(
+            "/corrections/import",
+            post(handlers::import_corrections_handler),
+        )
+        .route("/search", get(handlers::search_handler))
+        .route("/export-archive", get(handlers::export_archive_handler))
+        .route(
+            "/import-archive",
+            // Archives are streamed, so they are not bound by the JSON import limit.
+            post(handlers::import_archive_handler).layer(DefaultBodyLimit::disable()),
+        )
         .layer(DefaultBodyLimit::max(50 * 1024 * 1024)) // 50MB limit for imports
         .with_state(state)
 }
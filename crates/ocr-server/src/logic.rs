@@ -1,92 +1,18 @@
 use std::{io::Cursor, time::Duration};
 
-use anyhow::anyhow;
-use chrome_lens_ocr::LensClient;
-use image::{DynamicImage, GenericImageView, ImageBuffer, ImageFormat, ImageReader};
+use anyhow::{Context, anyhow};
+use image::{DynamicImage, GrayImage, ImageBuffer, ImageFormat, ImageReader};
 use reqwest::header::ACCEPT;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    engine::{EngineContext, EngineLine, OcrEngine},
     language::OcrLanguage,
     merge::{self, MergeConfig},
+    reading_order,
+    throttle::FailureKind,
 };
 
-// --- REST Structs ---
-
-#[derive(Deserialize)]
-struct SettingsResponse {
-    settings: Option<ProxySettingsRaw>,
-}
-
-#[derive(Deserialize)]
-struct ProxySettingsRaw {
-    #[serde(rename = "socksProxyEnabled")]
-    socks_proxy_enabled: Option<bool>,
-    #[serde(rename = "socksProxyVersion")]
-    socks_proxy_version: Option<i32>,
-    #[serde(rename = "socksProxyHost")]
-    socks_proxy_host: Option<String>,
-    #[serde(rename = "socksProxyPort")]
-    socks_proxy_port: Option<String>,
-    #[serde(rename = "socksProxyUsername")]
-    socks_proxy_username: Option<String>,
-    #[serde(rename = "socksProxyPassword")]
-    socks_proxy_password: Option<String>,
-}
-
-#[derive(Clone, Debug)]
-struct ProxySettings {
-    socks_proxy_enabled: bool,
-    socks_proxy_version: i32,
-    socks_proxy_host: String,
-    socks_proxy_port: String,
-    socks_proxy_username: Option<String>,
-    socks_proxy_password: Option<String>,
-}
-
-async fn get_proxy_settings(
-    user: Option<String>,
-    pass: Option<String>,
-) -> anyhow::Result<Option<ProxySettings>> {
-    let client = reqwest::Client::new();
-    let settings_url = "http://127.0.0.1:4568/api/v1/settings";
-    let mut request = client.get(settings_url).header(ACCEPT, "application/json");
-    if let Some(username) = user {
-        request = request.basic_auth(username, pass);
-    }
-    let response = request.send().await?;
-    let status = response.status();
-    if !status.is_success() {
-        let body = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "[Failed to read body]".to_string());
-        return Err(anyhow!(
-            "REST request failed (Status: {status}). Body: {body}"
-        ));
-    }
-    let json_response: SettingsResponse = response
-        .json()
-        .await
-        .map_err(|err| anyhow!("Error decoding settings REST response: {err}"))?;
-    let Some(raw) = json_response.settings else {
-        return Ok(None);
-    };
-    let settings = ProxySettings {
-        socks_proxy_enabled: raw.socks_proxy_enabled.unwrap_or(false),
-        socks_proxy_version: raw.socks_proxy_version.unwrap_or(5),
-        socks_proxy_host: raw.socks_proxy_host.unwrap_or_default(),
-        socks_proxy_port: raw.socks_proxy_port.unwrap_or_default(),
-        socks_proxy_username: raw
-            .socks_proxy_username
-            .filter(|value| !value.is_empty()),
-        socks_proxy_password: raw
-            .socks_proxy_password
-            .filter(|value| !value.is_empty()),
-    };
-    Ok(Some(settings))
-}
-
 pub async fn resolve_total_pages_from_graphql(
     chapter_base_url: &str,
     user: Option<String>,
@@ -114,30 +40,50 @@ fn derive_api_base(chapter_base_url: &str) -> String {
     }
 }
 
+/// Whether two URLs point at the same Suwayomi server.
+pub fn same_server(url: &str, other: &str) -> bool {
+    derive_api_base(url) == derive_api_base(other)
+}
+
 pub async fn resolve_total_pages_from_rest(
     chapter_base_url: &str,
     user: Option<String>,
     pass: Option<String>,
 ) -> anyhow::Result<usize> {
-    let path = get_cache_key(chapter_base_url, None);
-    let parts: Vec<&str> = path.split('/').collect();
-    let manga_id_str = parts
-        .iter()
-        .find(|&part| *part == "manga")
-        .and_then(|_| parts.get(parts.iter().position(|&part| part == "manga")? + 1))
-        .ok_or_else(|| anyhow!("Failed to parse manga ID from URL: {chapter_base_url}"))?;
-    let chapter_index_str = parts
-        .iter()
-        .find(|&part| *part == "chapter")
-        .and_then(|_| parts.get(parts.iter().position(|&part| part == "chapter")? + 1))
-        .ok_or_else(|| anyhow!("Failed to parse chapter index from URL: {chapter_base_url}"))?;
+    Ok(resolve_chapter_pages(chapter_base_url, user, pass)
+        .await?
+        .len())
+}
 
-    let api_base = derive_api_base(chapter_base_url);
+#[derive(Deserialize)]
+struct RestChapter {
+    index: u32,
+}
+
+/// Indexes of all chapters of a series, in reading order. `server_url` is any URL on the
+/// Suwayomi server; only its scheme, host and port are used.
+pub async fn resolve_series_chapters(
+    server_url: &str,
+    manga_id: &str,
+    user: Option<String>,
+    pass: Option<String>,
+) -> anyhow::Result<Vec<u32>> {
     let url = format!(
-        "{}/api/v1/manga/{}/chapter/{}/pages",
-        api_base, manga_id_str, chapter_index_str
+        "{}/api/v1/manga/{manga_id}/chapters",
+        derive_api_base(server_url)
     );
+    let chapters: Vec<RestChapter> = get_rest_json(&url, user, pass).await?;
+    let mut indexes: Vec<u32> = chapters.into_iter().map(|chapter| chapter.index).collect();
+    indexes.sort_unstable();
+    indexes.dedup();
+    Ok(indexes)
+}
 
+async fn get_rest_json<T: serde::de::DeserializeOwned>(
+    url: &str,
+    user: Option<String>,
+    pass: Option<String>,
+) -> anyhow::Result<T> {
     let client = reqwest::Client::new();
     let mut request = client.get(url).header(ACCEPT, "application/json");
     if let Some(username) = user {
@@ -154,11 +100,69 @@ pub async fn resolve_total_pages_from_rest(
             "REST request failed (Status: {status}). Body: {body}"
         ));
     }
-    let list: RestPageList = response
+    response
         .json()
         .await
-        .map_err(|err| anyhow!("Error decoding REST response: {err}"))?;
-    Ok(list.pages.len())
+        .map_err(|err| anyhow!("Error decoding REST response: {err}"))
+}
+
+/// Suwayomi manga id and chapter index of a chapter base URL (`.../manga/<id>/chapter/<n>/...`).
+pub fn chapter_location(chapter_base_url: &str) -> Option<(&str, &str)> {
+    let mut parts = chapter_base_url.split('?').next()?.split('/');
+    parts.find(|part| *part == "manga")?;
+    let manga_id = parts.next().filter(|id| !id.is_empty())?;
+    parts.find(|part| *part == "chapter")?;
+    let chapter_index = parts.next().filter(|index| !index.is_empty())?;
+    Some((manga_id, chapter_index))
+}
+
+/// Base URL of another chapter of the same series.
+pub fn sibling_chapter_url(chapter_base_url: &str, chapter_index: u32) -> Option<String> {
+    let (manga_id, current) = chapter_location(chapter_base_url)?;
+    let from = format!("/manga/{manga_id}/chapter/{current}");
+    let to = format!("/manga/{manga_id}/chapter/{chapter_index}");
+    chapter_base_url
+        .contains(&from)
+        .then(|| chapter_base_url.replacen(&from, &to, 1))
+}
+
+/// Chapter base URL in the form the reader uses, on the server `server_url` points at.
+pub fn chapter_base_url(server_url: &str, manga_id: &str, chapter_index: u32) -> String {
+    format!(
+        "{}/api/v1/manga/{manga_id}/chapter/{chapter_index}/page/",
+        derive_api_base(server_url)
+    )
+}
+
+/// Asks Suwayomi for the page image URLs of a chapter, made absolute against the server the
+/// chapter URL points at.
+pub async fn resolve_chapter_pages(
+    chapter_base_url: &str,
+    user: Option<String>,
+    pass: Option<String>,
+) -> anyhow::Result<Vec<String>> {
+    let path = get_cache_key(chapter_base_url, None);
+    let (manga_id_str, chapter_index_str) = chapter_location(&path).ok_or_else(|| {
+        anyhow!("Failed to parse manga ID or chapter index from URL: {chapter_base_url}")
+    })?;
+
+    let api_base = derive_api_base(chapter_base_url);
+    let url = format!(
+        "{}/api/v1/manga/{}/chapter/{}/pages",
+        api_base, manga_id_str, chapter_index_str
+    );
+    let list: RestPageList = get_rest_json(&url, user, pass).await?;
+    Ok(list
+        .pages
+        .into_iter()
+        .map(|page| {
+            if page.starts_with("http") {
+                page
+            } else {
+                format!("{api_base}{page}")
+            }
+        })
+        .collect())
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -173,6 +177,43 @@ pub struct OcrResult {
 
     #[serde(rename = "forcedOrientation", skip_serializing_if = "Option::is_none")]
     pub forced_orientation: Option<String>,
+
+    /// Speech bubble the result belongs to, numbered in reading order.
+    #[serde(rename = "bubbleId", skip_serializing_if = "Option::is_none")]
+    pub bubble_id: Option<u32>,
+
+    /// Position of the result when the page is read in order.
+    #[serde(rename = "readingOrder", skip_serializing_if = "Option::is_none")]
+    pub reading_order: Option<u32>,
+
+    /// Furigana found beside the text, kept out of `text` itself.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ruby: Vec<RubyAnnotation>,
+
+    /// Word boxes from the engine, when it reported them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<WordBox>,
+}
+
+/// Where part of an [`OcrResult`]'s text sits on the page.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WordBox {
+    /// Char range of the parent text.
+    pub start: usize,
+    pub end: usize,
+    #[serde(rename = "tightBoundingBox")]
+    pub tight_bounding_box: BoundingBox,
+}
+
+/// A ruby reading over part of an [`OcrResult`]'s text.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RubyAnnotation {
+    pub text: String,
+    /// Char range of the parent text the reading belongs to.
+    pub start: usize,
+    pub end: usize,
+    #[serde(rename = "tightBoundingBox")]
+    pub tight_bounding_box: BoundingBox,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
@@ -185,6 +226,67 @@ pub struct BoundingBox {
     pub rotation: Option<f64>,
 }
 
+impl BoundingBox {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x && x <= self.x + self.width && y >= self.y && y <= self.y + self.height
+    }
+
+    /// Squared distance from `(x, y)` to the nearest point of the box.
+    fn distance_squared(&self, x: f64, y: f64) -> f64 {
+        let dx = (self.x - x).max(x - (self.x + self.width)).max(0.0);
+        let dy = (self.y - y).max(y - (self.y + self.height)).max(0.0);
+        dx * dx + dy * dy
+    }
+}
+
+impl OcrResult {
+    /// Char offset into `text` of the character under `(x, y)`, given in the same
+    /// coordinates as the boxes. Uses the nearest word box when the engine reported any,
+    /// otherwise spreads the text evenly over its lines.
+    pub fn char_offset_at(&self, x: f64, y: f64) -> Option<usize> {
+        if !self.tight_bounding_box.contains(x, y) {
+            return None;
+        }
+        let vertical = self.forced_orientation.as_deref() == Some("vertical");
+
+        if let Some(word) = self.words.iter().min_by(|a, b| {
+            a.tight_bounding_box
+                .distance_squared(x, y)
+                .total_cmp(&b.tight_bounding_box.distance_squared(x, y))
+        }) {
+            let span = word.end.saturating_sub(word.start);
+            return Some(word.start + span_index(&word.tight_bounding_box, span, x, y, vertical));
+        }
+
+        // Vertical lines are columns read right to left, horizontal ones rows top to bottom.
+        let lines: Vec<&str> = self.text.split('\n').collect();
+        let bbox = &self.tight_bounding_box;
+        let across = if vertical {
+            (bbox.x + bbox.width - x) / bbox.width
+        } else {
+            (y - bbox.y) / bbox.height
+        };
+        let line_index = ((across.clamp(0.0, 1.0) * lines.len() as f64) as usize)
+            .min(lines.len().saturating_sub(1));
+        let line_start: usize = lines[..line_index]
+            .iter()
+            .map(|line| line.chars().count() + 1)
+            .sum();
+        let line_length = lines[line_index].chars().count();
+        Some(line_start + span_index(bbox, line_length, x, y, vertical))
+    }
+}
+
+/// Index of the char under `(x, y)` when `length` chars fill `bbox` along the reading axis.
+fn span_index(bbox: &BoundingBox, length: usize, x: f64, y: f64, vertical: bool) -> usize {
+    let along = if vertical {
+        (y - bbox.y) / bbox.height
+    } else {
+        (x - bbox.x) / bbox.width
+    };
+    ((along.clamp(0.0, 1.0) * length as f64) as usize).min(length.saturating_sub(1))
+}
+
 /// Helper to strip the scheme/host/query from the URL for caching purposes.
 pub fn get_cache_key(url: &str, language: Option<OcrLanguage>) -> String {
     let raw = if let Ok(parsed) = reqwest::Url::parse(url) {
@@ -212,6 +314,64 @@ pub fn get_cache_key(url: &str, language: Option<OcrLanguage>) -> String {
     }
 }
 
+/// Marks the merge settings a cache entry was produced with, when they differ from the
+/// defaults.
+const MERGE_KEY_SUFFIX: &str = "#merge=";
+
+/// Cache key of a page merged with `merge_config`. Default settings keep the plain key from
+/// [`get_cache_key`], so existing caches stay valid. A space setting that matches the
+/// language's own default counts as default, so clients that always send it share keys with
+/// those that never do.
+pub fn get_merge_cache_key(url: &str, merge_config: &MergeConfig) -> String {
+    with_merge_suffix(
+        &get_cache_key(url, Some(merge_config.language)),
+        merge_config,
+    )
+}
+
+pub fn with_merge_suffix(base_key: &str, merge_config: &MergeConfig) -> String {
+    let defaults = MergeConfig::default();
+    if !merge_config.enabled {
+        return format!("{base_key}{MERGE_KEY_SUFFIX}off");
+    }
+    let add_space = merge_config
+        .add_space_on_merge
+        .filter(|&add_space| add_space == merge_config.language.prefers_no_space());
+    if merge_config.font_size_ratio == defaults.font_size_ratio && add_space.is_none() {
+        return base_key.to_string();
+    }
+    let mut key = format!(
+        "{base_key}{MERGE_KEY_SUFFIX}r{}",
+        merge_config.font_size_ratio
+    );
+    if let Some(add_space) = add_space {
+        key.push_str(if add_space { ",s1" } else { ",s0" });
+    }
+    key
+}
+
+pub fn strip_merge_suffix(cache_key: &str) -> &str {
+    cache_key
+        .split_once(MERGE_KEY_SUFFIX)
+        .map_or(cache_key, |(base, _)| base)
+}
+
+/// The language segment of a cache key made by [`get_cache_key`] (`lang/<language>/...`).
+pub fn cache_key_language(cache_key: &str) -> Option<&str> {
+    cache_key
+        .strip_prefix("lang/")
+        .and_then(|rest| rest.split('/').next())
+        .filter(|language| !language.is_empty())
+}
+
+/// The Suwayomi manga id a cache key belongs to (the segment after `/manga/`).
+pub fn cache_key_series(cache_key: &str) -> Option<&str> {
+    let path = cache_key.split('?').next().unwrap_or(cache_key);
+    let mut parts = path.split('/');
+    parts.find(|part| *part == "manga")?;
+    parts.next().filter(|id| !id.is_empty())
+}
+
 fn post_process_text(text: String, language: OcrLanguage) -> String {
     if language.prefers_no_space() {
         text.replace(char::is_whitespace, "")
@@ -278,27 +438,20 @@ fn decode_avif_custom(bytes: &[u8]) -> anyhow::Result<DynamicImage> {
     }
 }
 
-pub async fn fetch_and_process(
-    url: &str,
-    user: Option<String>,
-    pass: Option<String>,
-    add_space_on_merge: Option<bool>,
-    language: OcrLanguage,
-) -> anyhow::Result<Vec<OcrResult>> {
+/// Runs `attempt` up to three times, backing off a little longer after each failure. Failures
+/// that can't go away on their own (a broken image, an engine paused by its breaker) are not
+/// retried, and rate-limited attempts leave the waiting to the engine's shared backoff.
+pub async fn with_retries<T, Fut>(url: &str, mut attempt: impl FnMut() -> Fut) -> anyhow::Result<T>
+where
+    Fut: Future<Output = anyhow::Result<T>>,
+{
     let mut last_error = anyhow!("Unknown error");
 
     for attempt_number in 1..=3 {
-        match fetch_and_process_internal(
-            url,
-            user.clone(),
-            pass.clone(),
-            add_space_on_merge,
-            language,
-        )
-        .await
-        {
+        match attempt().await {
             Ok(result) => return Ok(result),
             Err(error) => {
+                let kind = FailureKind::of(&error);
                 last_error = error;
                 tracing::warn!(
                     "Attempt {} failed for {}: {:?}",
@@ -306,7 +459,11 @@ pub async fn fetch_and_process(
                     url,
                     last_error
                 );
-                tokio::time::sleep(Duration::from_secs(attempt_number)).await;
+                match kind {
+                    Some(kind) if !kind.is_retryable() => break,
+                    Some(FailureKind::RateLimited) => {}
+                    _ => tokio::time::sleep(Duration::from_secs(attempt_number)).await,
+                }
             }
         }
     }
@@ -315,7 +472,7 @@ pub async fn fetch_and_process(
 
 // --- Data Structure for Test Caching ---
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RawChunk {
     pub lines: Vec<OcrResult>,
     pub width: u32,
@@ -325,177 +482,290 @@ pub struct RawChunk {
     pub full_height: u32,
 }
 
-// --- Public Helper for Testing ---
-pub async fn get_raw_ocr_data(
-    image_bytes: &[u8],
-    user: Option<String>,
-    pass: Option<String>,
+/// OCR output for one page: the merged results together with the raw chunks and settings
+/// they were merged from, so the page can be re-merged later without calling the engine.
+pub struct OcrPage {
+    pub results: Vec<OcrResult>,
+    pub raw_chunks: Vec<RawChunk>,
+    pub merge_config: MergeConfig,
+    /// Hash of the image the page was recognized from, see [`crate::sources::content_hash`].
+    pub content_hash: String,
+    /// Id of the engine that recognized it.
+    pub engine: String,
+    /// Language the engine was asked to recognize.
+    pub ocr_language: OcrLanguage,
+}
+
+/// Converts a normalized engine line into an axis-aligned [`OcrResult`] in pixel
+/// coordinates of a `width` x `height` image.
+pub(crate) fn line_to_pixel_result(
+    line: EngineLine,
+    width: u32,
+    height: u32,
     language: OcrLanguage,
-) -> anyhow::Result<Vec<RawChunk>> {
+) -> Option<OcrResult> {
+    let clean_text = post_process_text(line.text, language);
+    if clean_text.trim().is_empty() {
+        return None;
+    }
+
+    // Words are located in the cleaned text in order; if one can't be found the offsets
+    // would be wrong, so the line goes without word boxes.
+    let mut words = Vec::new();
+    let mut cursor = 0;
+    for word in line.words {
+        let word_text = post_process_text(word.text, language);
+        let word_text = word_text.trim();
+        if word_text.is_empty() {
+            continue;
+        }
+        let Some(found) = clean_text[cursor..].find(word_text) else {
+            words.clear();
+            break;
+        };
+        let byte_start = cursor + found;
+        let start = clean_text[..byte_start].chars().count();
+        words.push(WordBox {
+            start,
+            end: start + word_text.chars().count(),
+            tight_bounding_box: to_pixel_box(
+                (word.center_x, word.center_y, word.width, word.height),
+                word.rotation,
+                width,
+                height,
+            ),
+        });
+        cursor = byte_start + word_text.len();
+    }
+
+    let rotation = line.rotation;
+    let tight_bounding_box = to_pixel_box(
+        (line.center_x, line.center_y, line.width, line.height),
+        rotation,
+        width,
+        height,
+    );
+    let aabb_w = tight_bounding_box.width;
+    let aabb_h = tight_bounding_box.height;
+
+    let is_vertical = if language.prefers_vertical() {
+        if rotation.abs() > 0.1 {
+            (rotation.abs() - std::f32::consts::FRAC_PI_2 as f64).abs() < 0.5
+        } else {
+            aabb_w <= aabb_h
+        }
+    } else {
+        false
+    };
+
+    Some(OcrResult {
+        text: clean_text,
+        is_merged: Some(false),
+        forced_orientation: Some(if is_vertical {
+            "vertical".into()
+        } else {
+            "horizontal".into()
+        }),
+        tight_bounding_box,
+        bubble_id: None,
+        reading_order: None,
+        ruby: Vec::new(),
+        words,
+    })
+}
+
+/// Axis-aligned pixel box of a normalized `(center_x, center_y, width, height)` box rotated
+/// by `rotation` radians, in a `width` x `height` image.
+fn to_pixel_box(
+    (center_x, center_y, box_width, box_height): (f64, f64, f64, f64),
+    rotation: f64,
+    width: u32,
+    height: u32,
+) -> BoundingBox {
+    let cx = center_x * width as f64;
+    let cy = center_y * height as f64;
+    let w = box_width * width as f64;
+    let h = box_height * height as f64;
+
+    let hw = w / 2.0;
+    let hh = h / 2.0;
+    let cos_a = rotation.cos();
+    let sin_a = rotation.sin();
+
+    let corners = [(-hw, -hh), (hw, -hh), (hw, hh), (-hw, hh)];
+
+    let mut min_x = f64::INFINITY;
+    let mut max_x = f64::NEG_INFINITY;
+    let mut min_y = f64::INFINITY;
+    let mut max_y = f64::NEG_INFINITY;
+
+    for (lx, ly) in corners {
+        let rx = lx * cos_a - ly * sin_a + cx;
+        let ry = lx * sin_a + ly * cos_a + cy;
+        min_x = min_x.min(rx);
+        max_x = max_x.max(rx);
+        min_y = min_y.min(ry);
+        max_y = max_y.max(ry);
+    }
+
+    BoundingBox {
+        x: min_x,
+        y: min_y,
+        width: max_x - min_x,
+        height: max_y - min_y,
+        rotation: None,
+    }
+}
+
+/// Tallest strip sent to the engine in one request.
+const CHUNK_HEIGHT_LIMIT: u32 = 3000;
+
+/// Blank rows needed before a strip may be cut there.
+const MIN_GUTTER_HEIGHT: u32 = 8;
+
+/// Rows a strip overlaps the next one by when no gutter was found, so a line cut at the
+/// boundary is seen whole by one of them.
+const CHUNK_OVERLAP: u32 = 300;
+
+/// Luma difference from the row's median still counted as background (JPEG noise, paper
+/// texture).
+const BLANK_ROW_TOLERANCE: u8 = 24;
+
+/// Marks the rows of `image` that are a single flat colour, allowing 1% stray pixels.
+fn blank_rows(image: &GrayImage) -> Vec<bool> {
+    let width = image.width() as usize;
+    let allowed_outliers = width / 100;
+    image
+        .rows()
+        .map(|row| {
+            let mut histogram = [0usize; 256];
+            for pixel in row {
+                histogram[pixel.0[0] as usize] += 1;
+            }
+            let mut seen = 0;
+            let median = histogram
+                .iter()
+                .position(|count| {
+                    seen += count;
+                    seen * 2 >= width
+                })
+                .unwrap_or(0);
+            let low = median.saturating_sub(BLANK_ROW_TOLERANCE as usize);
+            let high = (median + BLANK_ROW_TOLERANCE as usize).min(255);
+            let outliers: usize = histogram[..low].iter().sum::<usize>()
+                + histogram[high + 1..].iter().sum::<usize>();
+            outliers <= allowed_outliers
+        })
+        .collect()
+}
+
+/// The middle of the lowest gutter of at least [`MIN_GUTTER_HEIGHT`] blank rows in
+/// `from..to`. Only the part of a gutter inside the range counts.
+fn find_gutter(blank: &[bool], from: u32, to: u32) -> Option<u32> {
+    // One past the last row of the blank run being scanned.
+    let mut run_end = to;
+    for y in (from..to).rev() {
+        if !blank[y as usize] {
+            if run_end - (y + 1) >= MIN_GUTTER_HEIGHT {
+                return Some((y + 1 + run_end) / 2);
+            }
+            run_end = y;
+        }
+    }
+    (run_end - from >= MIN_GUTTER_HEIGHT).then(|| (from + run_end) / 2)
+}
+
+/// Splits a page into `(global_y, height)` strips of at most [`CHUNK_HEIGHT_LIMIT`] rows.
+/// Strips end in a blank gutter when there is one in the lower half of the allowed range;
+/// otherwise they are cut at the limit and overlap the next strip by [`CHUNK_OVERLAP`].
+fn plan_chunks(image: &DynamicImage) -> Vec<(u32, u32)> {
+    let full_height = image.height();
+    if full_height <= CHUNK_HEIGHT_LIMIT {
+        return vec![(0, full_height)];
+    }
+
+    let blank = blank_rows(&image.to_luma8());
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while full_height - start > CHUNK_HEIGHT_LIMIT {
+        let limit = start + CHUNK_HEIGHT_LIMIT;
+        match find_gutter(&blank, start + CHUNK_HEIGHT_LIMIT / 2, limit) {
+            Some(cut) => {
+                chunks.push((start, cut - start));
+                start = cut;
+            }
+            None => {
+                chunks.push((start, CHUNK_HEIGHT_LIMIT));
+                start = limit - CHUNK_OVERLAP;
+            }
+        }
+    }
+    chunks.push((start, full_height - start));
+    chunks
+}
+
+pub(crate) fn decode_image(image_bytes: &[u8]) -> anyhow::Result<DynamicImage> {
     let reader = ImageReader::new(Cursor::new(image_bytes))
         .with_guessed_format()
-        .map_err(|err| anyhow!("Failed with_guessed_format: {err:?}"))?;
+        .map_err(|err| anyhow!("Failed with_guessed_format: {err:?}"))
+        .context(FailureKind::Decode)?;
 
-    let decoded_image = if reader.format() == Some(ImageFormat::Avif) {
-        decode_avif_custom(image_bytes)?
+    if reader.format() == Some(ImageFormat::Avif) {
+        decode_avif_custom(image_bytes)
     } else {
         reader
             .decode()
-            .map_err(|err| anyhow!("Failed decode: {err:?}"))?
-    };
+            .map_err(|err| anyhow!("Failed decode: {err:?}"))
+    }
+    .context(FailureKind::Decode)
+}
+
+pub(crate) fn encode_png(image: &DynamicImage) -> anyhow::Result<Vec<u8>> {
+    let mut image_buffer = Cursor::new(Vec::new());
+    image
+        .write_to(&mut image_buffer, ImageFormat::Png)
+        .map_err(|err| anyhow!("Failed write_to: {err:?}"))?;
+    Ok(image_buffer.into_inner())
+}
+
+// --- Public Helper for Testing ---
+pub async fn get_raw_ocr_data(
+    image_bytes: &[u8],
+    engine: &dyn OcrEngine,
+    user: Option<String>,
+    pass: Option<String>,
+    language: OcrLanguage,
+) -> anyhow::Result<Vec<RawChunk>> {
+    let decoded_image = decode_image(image_bytes)?;
 
     let full_image_width = decoded_image.width();
     let full_image_height = decoded_image.height();
-    let chunk_height_limit = 3000;
 
     let mut raw_chunks = Vec::new();
 
-    // Fetch proxy settings
-    let proxy_settings = get_proxy_settings(user.clone(), pass.clone())
-        .await
-        .ok()
-        .flatten();
-
-    // Create LensClient with optional proxy
-    let lens_client = if let Some(ref proxy) = proxy_settings {
-        if proxy.socks_proxy_enabled && !proxy.socks_proxy_host.is_empty() {
-            // Build proxy URL with authentication if provided
-            let proxy_url = if let (Some(username), Some(password)) =
-                (&proxy.socks_proxy_username, &proxy.socks_proxy_password)
-            {
-                if !username.is_empty() && !password.is_empty() {
-                    format!(
-                        "socks{}://{}:{}@{}:{}",
-                        proxy.socks_proxy_version,
-                        username,
-                        password,
-                        proxy.socks_proxy_host,
-                        proxy.socks_proxy_port
-                    )
-                } else {
-                    format!(
-                        "socks{}://{}:{}",
-                        proxy.socks_proxy_version, proxy.socks_proxy_host, proxy.socks_proxy_port
-                    )
-                }
-            } else {
-                format!(
-                    "socks{}://{}:{}",
-                    proxy.socks_proxy_version, proxy.socks_proxy_host, proxy.socks_proxy_port
-                )
-            };
+    let ctx = EngineContext { user, pass };
 
-            tracing::info!(
-                "Using SOCKS{} proxy for Google Lens: {}:{}",
-                proxy.socks_proxy_version,
-                proxy.socks_proxy_host,
-                proxy.socks_proxy_port
-            );
-
-            LensClient::new_with_proxy(None, Some(&proxy_url))
-                .map_err(|e| anyhow!("Failed to create LensClient with proxy: {}", e))?
-        } else {
-            LensClient::new(None)
-        }
-    } else {
-        LensClient::new(None)
-    };
-
-    let mut current_y_position = 0;
-    while current_y_position < full_image_height {
-        let current_chunk_height =
-            std::cmp::min(chunk_height_limit, full_image_height - current_y_position);
+    for (current_y_position, current_chunk_height) in plan_chunks(&decoded_image) {
         if current_chunk_height == 0 {
-            break;
+            continue;
         }
 
-        let chunk_image = decoded_image
-            .view(
-                0,
-                current_y_position,
-                full_image_width,
-                current_chunk_height,
-            )
-            .to_image();
-        let mut image_buffer = Cursor::new(Vec::new());
-        chunk_image
-            .write_to(&mut image_buffer, ImageFormat::Png)
-            .map_err(|err| anyhow!("Failed write_to: {err:?}"))?;
-        let chunk_png_bytes = image_buffer.into_inner();
-
-        let lens_response = lens_client
-            .process_image_bytes(&chunk_png_bytes, Some("jp"))
-            .await
-            .map_err(|err| anyhow!("Failed process_image_bytes: {err:?}"))?;
-
-        let mut flat_ocr_lines = Vec::new();
-        for paragraph in lens_response.paragraphs {
-            for line in paragraph.lines {
-                if let Some(geometry) = line.geometry {
-                    let clean_text = post_process_text(line.text, language);
-                    if clean_text.trim().is_empty() {
-                        continue;
-                    }
-
-                    let rotation = geometry.rotation_z as f64;
-                    let cx = (geometry.center_x * full_image_width as f32) as f64;
-                    let cy = (geometry.center_y * current_chunk_height as f32) as f64;
-                    let w = (geometry.width * full_image_width as f32) as f64;
-                    let h = (geometry.height * current_chunk_height as f32) as f64;
-
-                    let hw = w / 2.0;
-                    let hh = h / 2.0;
-                    let cos_a = rotation.cos();
-                    let sin_a = rotation.sin();
-
-                    let corners = [(-hw, -hh), (hw, -hh), (hw, hh), (-hw, hh)];
-
-                    let mut min_x = f64::INFINITY;
-                    let mut max_x = f64::NEG_INFINITY;
-                    let mut min_y = f64::INFINITY;
-                    let mut max_y = f64::NEG_INFINITY;
-
-                    for (lx, ly) in corners {
-                        let rx = lx * cos_a - ly * sin_a + cx;
-                        let ry = lx * sin_a + ly * cos_a + cy;
-                        min_x = min_x.min(rx);
-                        max_x = max_x.max(rx);
-                        min_y = min_y.min(ry);
-                        max_y = max_y.max(ry);
-                    }
-
-                    let aabb_w = max_x - min_x;
-                    let aabb_h = max_y - min_y;
-
-                    let is_vertical = if language.prefers_vertical() {
-                        if rotation.abs() > 0.1 {
-                            (rotation.abs() - std::f32::consts::FRAC_PI_2 as f64).abs() < 0.5
-                        } else {
-                            aabb_w <= aabb_h
-                        }
-                    } else {
-                        false
-                    };
-
-                    flat_ocr_lines.push(OcrResult {
-                        text: clean_text,
-                        is_merged: Some(false),
-                        forced_orientation: Some(if is_vertical {
-                            "vertical".into()
-                        } else {
-                            "horizontal".into()
-                        }),
-                        tight_bounding_box: BoundingBox {
-                            x: min_x,
-                            y: min_y,
-                            width: aabb_w,
-                            height: aabb_h,
-                            rotation: None,
-                        },
-                    });
-                }
-            }
-        }
+        let chunk_image = decoded_image.crop_imm(
+            0,
+            current_y_position,
+            full_image_width,
+            current_chunk_height,
+        );
+        let chunk_png_bytes = encode_png(&chunk_image)?;
+
+        let engine_lines = engine.recognize(&chunk_png_bytes, language, &ctx).await?;
+
+        let flat_ocr_lines = engine_lines
+            .into_iter()
+            .filter_map(|line| {
+                line_to_pixel_result(line, full_image_width, current_chunk_height, language)
+            })
+            .collect();
 
         raw_chunks.push(RawChunk {
             lines: flat_ocr_lines,
@@ -505,20 +775,18 @@ pub async fn get_raw_ocr_data(
             full_width: full_image_width,
             full_height: full_image_height,
         });
-
-        current_y_position += chunk_height_limit;
     }
 
     Ok(raw_chunks)
 }
 
-async fn fetch_and_process_internal(
+/// Downloads a page image. Page URLs always point at the local Suwayomi server, whatever
+/// host the client saw them under.
+pub(crate) async fn fetch_image(
     url: &str,
-    user: Option<String>,
-    pass: Option<String>,
-    add_space_on_merge: Option<bool>,
-    language: OcrLanguage,
-) -> anyhow::Result<Vec<OcrResult>> {
+    user: Option<&str>,
+    pass: Option<&str>,
+) -> anyhow::Result<Vec<u8>> {
     // 0. Force URL to Localhost
     let target_url = match reqwest::Url::parse(url) {
         Ok(mut parsed) => {
@@ -533,45 +801,339 @@ async fn fetch_and_process_internal(
     // 1. Fetch
     let client = reqwest::Client::new();
     let mut request = client.get(&target_url);
-    if let Some(username) = &user {
-        request = request.basic_auth(username, pass.as_ref());
+    if let Some(username) = user {
+        request = request.basic_auth(username, pass);
     }
-    let response = request
-        .send()
-        .await?
-        .error_for_status()
-        .map_err(|err| anyhow!("Failed error_for_status (URL: {target_url}): {err:?}"))?;
-    let image_bytes = response.bytes().await?.to_vec();
+    async {
+        let response = request
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(|err| anyhow!("Failed error_for_status (URL: {target_url}): {err:?}"))?;
+        anyhow::Ok(response.bytes().await?.to_vec())
+    }
+    .await
+    .context(FailureKind::Fetch)
+}
+
+/// OCRs an image that is already in memory, whatever it came from.
+pub async fn process_image(
+    image_bytes: &[u8],
+    engine: &dyn OcrEngine,
+    user: Option<String>,
+    pass: Option<String>,
+    merge_config: &MergeConfig,
+) -> anyhow::Result<OcrPage> {
+    // Decode & OCR (Wrapped) - user/pass are handed to the engine for server settings
+    let ocr_language = merge_config.language;
+    let raw_chunks = get_raw_ocr_data(image_bytes, engine, user, pass, ocr_language).await?;
+
+    // Merge & Normalize
+    let results = merge_chunks(&raw_chunks, merge_config);
+
+    Ok(OcrPage {
+        results,
+        raw_chunks,
+        merge_config: merge_config.clone(),
+        content_hash: crate::sources::content_hash(image_bytes),
+        engine: engine.id().to_string(),
+        ocr_language,
+    })
+}
 
-    // 2. Decode & OCR (Wrapped) - now passes user/pass for proxy settings
-    let raw_chunks = get_raw_ocr_data(&image_bytes, user, pass, language).await?;
+/// Merges the lines of the whole page at once, puts them in reading order and maps them to
+/// page-normalized coordinates. Lines seen twice where strips (or separately OCR'd regions)
+/// overlap are kept once. Runs on fresh OCR output as well as on raw chunks loaded back from
+/// the cache.
+pub fn merge_chunks(raw_chunks: &[RawChunk], merge_config: &MergeConfig) -> Vec<OcrResult> {
+    let Some(first) = raw_chunks.first() else {
+        return Vec::new();
+    };
+    let full_width = first.full_width;
+    let full_height = first.full_height;
+    // The noise filters in auto_merge scale with the area they look at, and are tuned for a
+    // single strip rather than a whole webtoon page.
+    let reference_height = raw_chunks
+        .iter()
+        .map(|chunk| chunk.height)
+        .max()
+        .unwrap_or(full_height);
 
-    // 3. Merge & Normalize
-    let mut final_results = Vec::new();
-    let mut merge_config = MergeConfig::default();
-    merge_config.add_space_on_merge = add_space_on_merge;
-    merge_config.language = language;
+    let mut page_lines: Vec<(usize, OcrResult)> = Vec::new();
+    for (index, chunk) in raw_chunks.iter().enumerate() {
+        for line in &chunk.lines {
+            // Chunk Pixels -> Global Pixels
+            let mut line = line.clone();
+            line.tight_bounding_box.y += chunk.global_y as f64;
+            for word in &mut line.words {
+                word.tight_bounding_box.y += chunk.global_y as f64;
+            }
+            page_lines.push((index, line));
+        }
+    }
+    let lines = dedupe_overlapping_lines(raw_chunks, page_lines);
+
+    let mut results = merge::auto_merge(lines, full_width, reference_height, merge_config);
+    reading_order::assign_reading_order(&mut results, merge_config.language);
+    results
+        .into_iter()
+        .map(|mut result| {
+            // Global Pixels -> Global Normalized
+            let boxes = std::iter::once(&mut result.tight_bounding_box)
+                .chain(
+                    result
+                        .ruby
+                        .iter_mut()
+                        .map(|ruby| &mut ruby.tight_bounding_box),
+                )
+                .chain(
+                    result
+                        .words
+                        .iter_mut()
+                        .map(|word| &mut word.tight_bounding_box),
+                );
+            for bbox in boxes {
+                bbox.x /= full_width as f64;
+                bbox.width /= full_width as f64;
+                bbox.y /= full_height as f64;
+                bbox.height /= full_height as f64;
+            }
+            result
+        })
+        .collect()
+}
+
+/// Drops lines that another overlapping strip also recognized, keeping the larger box since
+/// the smaller one is usually cut off at a strip edge.
+fn dedupe_overlapping_lines(
+    raw_chunks: &[RawChunk],
+    lines: Vec<(usize, OcrResult)>,
+) -> Vec<OcrResult> {
+    let chunks_overlap = |a: usize, b: usize| {
+        let (a, b) = (&raw_chunks[a], &raw_chunks[b]);
+        a.global_y < b.global_y + b.height && b.global_y < a.global_y + a.height
+    };
+    let area = |line: &OcrResult| line.tight_bounding_box.width * line.tight_bounding_box.height;
+
+    let mut keep = vec![true; lines.len()];
+    for i in 0..lines.len() {
+        for j in i + 1..lines.len() {
+            let ((chunk_i, line_i), (chunk_j, line_j)) = (&lines[i], &lines[j]);
+            if !keep[i] || !keep[j] || chunk_i == chunk_j || !chunks_overlap(*chunk_i, *chunk_j) {
+                continue;
+            }
+            let (a, b) = (&line_i.tight_bounding_box, &line_j.tight_bounding_box);
+            let overlap_w = (a.x + a.width).min(b.x + b.width) - a.x.max(b.x);
+            let overlap_h = (a.y + a.height).min(b.y + b.height) - a.y.max(b.y);
+            if overlap_w <= 0.0 || overlap_h <= 0.0 {
+                continue;
+            }
+            let smaller = area(line_i).min(area(line_j));
+            if smaller <= 0.0 || overlap_w * overlap_h < smaller * 0.5 {
+                continue;
+            }
+            if area(line_i) >= area(line_j) {
+                keep[j] = false;
+            } else {
+                keep[i] = false;
+            }
+        }
+    }
 
-    for chunk in raw_chunks {
-        let merged_lines = merge::auto_merge(chunk.lines, chunk.width, chunk.height, &merge_config);
+    lines
+        .into_iter()
+        .zip(keep)
+        .filter_map(|((_, line), keep)| keep.then_some(line))
+        .collect()
+}
 
-        for mut result in merged_lines {
-            // Adjust Coordinates: Chunk Pixels -> Global Pixels -> Global Normalized
-            let chunk_pixel_x = result.tight_bounding_box.x;
-            let chunk_pixel_y = result.tight_bounding_box.y;
-            let chunk_pixel_width = result.tight_bounding_box.width;
-            let chunk_pixel_height = result.tight_bounding_box.height;
+#[cfg(test)]
+mod tests {
+    use image::Luma;
 
-            let global_pixel_y = chunk_pixel_y + (chunk.global_y as f64);
+    use super::*;
 
-            result.tight_bounding_box.x = chunk_pixel_x / chunk.full_width as f64;
-            result.tight_bounding_box.width = chunk_pixel_width / chunk.full_width as f64;
-            result.tight_bounding_box.y = global_pixel_y / chunk.full_height as f64;
-            result.tight_bounding_box.height = chunk_pixel_height / chunk.full_height as f64;
+    fn line(text: &str, x: f64, y: f64, width: f64, height: f64) -> OcrResult {
+        OcrResult {
+            text: text.to_string(),
+            tight_bounding_box: BoundingBox {
+                x,
+                y,
+                width,
+                height,
+                rotation: None,
+            },
+            is_merged: None,
+            forced_orientation: None,
+            bubble_id: None,
+            reading_order: None,
+            ruby: Vec::new(),
+            words: Vec::new(),
+        }
+    }
 
-            final_results.push(result);
+    fn chunk(global_y: u32, height: u32) -> RawChunk {
+        RawChunk {
+            lines: Vec::new(),
+            width: 1000,
+            height,
+            global_y,
+            full_width: 1000,
+            full_height: 6000,
         }
     }
 
-    Ok(final_results)
+    /// A `height` rows tall page with text-like content on every row except `blank`.
+    fn page(height: u32, blank: std::ops::Range<u32>) -> DynamicImage {
+        let image = GrayImage::from_fn(200, height, |x, y| {
+            if blank.contains(&y) || x % 2 == 0 {
+                Luma([255])
+            } else {
+                Luma([0])
+            }
+        });
+        DynamicImage::ImageLuma8(image)
+    }
+
+    #[test]
+    fn find_gutter_picks_the_lowest_run_long_enough() {
+        let mut blank = vec![false; 100];
+        blank[20..40].fill(true);
+        blank[70..75].fill(true);
+        // Cut in the middle of the whole run, not of its first qualifying rows.
+        assert_eq!(find_gutter(&blank, 0, 100), Some(30));
+        assert_eq!(find_gutter(&blank, 50, 100), None);
+        // A run crossing the edge of the range is cut in the middle of the part inside it.
+        assert_eq!(find_gutter(&blank, 24, 100), Some(32));
+        blank[90..100].fill(true);
+        assert_eq!(find_gutter(&blank, 0, 100), Some(95));
+    }
+
+    #[test]
+    fn short_pages_are_a_single_chunk() {
+        assert_eq!(
+            plan_chunks(&page(CHUNK_HEIGHT_LIMIT, 0..0)),
+            vec![(0, 3000)]
+        );
+    }
+
+    #[test]
+    fn chunks_end_in_a_gutter_or_overlap_without_one() {
+        let chunks = plan_chunks(&page(7000, 2500..2520));
+        // The first strip is cut in the gutter; the second finds none in its lower half, so it
+        // is cut at the limit and the third starts CHUNK_OVERLAP rows back.
+        assert_eq!(chunks, vec![(0, 2510), (2510, 3000), (5210, 1790)]);
+    }
+
+    #[test]
+    fn dedupe_keeps_the_larger_copy_from_overlapping_strips() {
+        let raw_chunks = [chunk(0, 3000), chunk(2700, 3000), chunk(5700, 300)];
+        let lines = vec![
+            // Cut off at the bottom of the first strip, whole in the second.
+            (0, line("cut", 100.0, 2900.0, 200.0, 100.0)),
+            (1, line("whole", 100.0, 2900.0, 200.0, 150.0)),
+            // Overlapping lines of the same strip are distinct text.
+            (1, line("a", 500.0, 3500.0, 100.0, 50.0)),
+            (1, line("b", 500.0, 3510.0, 100.0, 50.0)),
+            // These strips only touch, so lines overlapping across them are not the same line.
+            (1, line("c", 500.0, 5680.0, 100.0, 40.0)),
+            (2, line("d", 500.0, 5690.0, 100.0, 40.0)),
+        ];
+        let texts: Vec<String> = dedupe_overlapping_lines(&raw_chunks, lines)
+            .into_iter()
+            .map(|line| line.text)
+            .collect();
+        assert_eq!(texts, ["whole", "a", "b", "c", "d"]);
+    }
+
+    fn assert_box(actual: BoundingBox, (x, y, width, height): (f64, f64, f64, f64)) {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(
+            close(actual.x, x)
+                && close(actual.y, y)
+                && close(actual.width, width)
+                && close(actual.height, height),
+            "{actual:?} != {:?}",
+            (x, y, width, height)
+        );
+    }
+
+    #[test]
+    fn char_offset_counts_earlier_lines_and_their_breaks() {
+        let result = line("abcd\nefgh", 0.0, 0.0, 100.0, 20.0);
+        assert_eq!(result.char_offset_at(10.0, 5.0), Some(0));
+        assert_eq!(result.char_offset_at(60.0, 5.0), Some(2));
+        assert_eq!(result.char_offset_at(90.0, 15.0), Some(8));
+        // The far edges still belong to the last line and char.
+        assert_eq!(result.char_offset_at(100.0, 20.0), Some(8));
+        assert_eq!(result.char_offset_at(100.1, 10.0), None);
+    }
+
+    #[test]
+    fn char_offset_reads_vertical_columns_right_to_left() {
+        let mut result = line("ab\ncd", 0.0, 0.0, 20.0, 100.0);
+        result.forced_orientation = Some("vertical".into());
+        assert_eq!(result.char_offset_at(15.0, 90.0), Some(1));
+        assert_eq!(result.char_offset_at(5.0, 0.0), Some(3));
+    }
+
+    #[test]
+    fn char_offset_uses_the_nearest_word_box() {
+        let mut result = line("abc def", 0.0, 0.0, 90.0, 20.0);
+        let word = |start, x| WordBox {
+            start,
+            end: start + 3,
+            tight_bounding_box: line("", x, 0.0, 30.0, 20.0).tight_bounding_box,
+        };
+        result.words = vec![word(0, 0.0), word(4, 60.0)];
+        assert_eq!(result.char_offset_at(70.0, 5.0), Some(5));
+        // Between the words the closer one wins.
+        assert_eq!(result.char_offset_at(40.0, 5.0), Some(2));
+    }
+
+    #[test]
+    fn span_index_clamps_to_the_box() {
+        let bbox = line("", 10.0, 0.0, 40.0, 10.0).tight_bounding_box;
+        assert_eq!(span_index(&bbox, 4, 10.0, 5.0, false), 0);
+        assert_eq!(span_index(&bbox, 4, 29.9, 5.0, false), 1);
+        assert_eq!(span_index(&bbox, 4, 50.0, 5.0, false), 3);
+        assert_eq!(span_index(&bbox, 4, 0.0, 5.0, false), 0);
+        assert_eq!(span_index(&bbox, 0, 30.0, 5.0, false), 0);
+    }
+
+    #[test]
+    fn pixel_box_scales_and_covers_the_rotated_box() {
+        assert_box(
+            to_pixel_box((0.5, 0.5, 0.2, 0.1), 0.0, 1000, 2000),
+            (400.0, 900.0, 200.0, 200.0),
+        );
+        assert_box(
+            to_pixel_box(
+                (0.5, 0.5, 0.2, 0.05),
+                std::f64::consts::FRAC_PI_2,
+                1000,
+                1000,
+            ),
+            (475.0, 400.0, 50.0, 200.0),
+        );
+    }
+
+    #[test]
+    fn merge_key_ignores_the_languages_own_space_setting() {
+        let url = "http://host/api/v1/manga/1/chapter/2/page/3";
+        let mut merge_config = MergeConfig {
+            language: OcrLanguage::English,
+            ..MergeConfig::default()
+        };
+        let plain = get_merge_cache_key(url, &merge_config);
+
+        merge_config.add_space_on_merge = Some(true);
+        assert_eq!(get_merge_cache_key(url, &merge_config), plain);
+
+        merge_config.add_space_on_merge = Some(false);
+        assert_eq!(
+            get_merge_cache_key(url, &merge_config),
+            format!("{plain}#merge=r3,s0")
+        );
+    }
 }
@@ -2,10 +2,11 @@ use std::cmp::Ordering;
 
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     language::OcrLanguage,
-    logic::{BoundingBox, OcrResult},
+    logic::{BoundingBox, OcrResult, RubyAnnotation, WordBox},
 };
 
 lazy_static! {
@@ -15,7 +16,16 @@ lazy_static! {
     static ref KATAKANA_REGEX: Regex = Regex::new(r"[\p{Katakana}]").unwrap();
 }
 
-#[derive(Clone)]
+/// Version of the [`auto_merge`] output. Bump it whenever a change alters how lines are
+/// grouped, so results cached with an older algorithm can be told apart.
+///
+/// 2: pages are merged as a whole instead of per chunk.
+/// 3: results carry bubble ids and a reading order.
+/// 4: furigana is attached to its line as ruby instead of being dropped.
+pub const MERGE_VERSION: u32 = 4;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
 pub struct MergeConfig {
     pub enabled: bool,
     pub font_size_ratio: f64,
@@ -23,6 +33,15 @@ pub struct MergeConfig {
     pub language: OcrLanguage,
 }
 
+impl MergeConfig {
+    /// Whether merged lines are joined with a space: the explicit setting, or else what the
+    /// language calls for.
+    pub fn adds_space(&self) -> bool {
+        self.add_space_on_merge
+            .unwrap_or(!self.language.prefers_no_space())
+    }
+}
+
 impl Default for MergeConfig {
     fn default() -> Self {
         Self {
@@ -34,6 +53,29 @@ impl Default for MergeConfig {
     }
 }
 
+/// Merge settings chosen per request or saved as a named profile. Unset fields keep the
+/// value of whatever they are applied to.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct MergeProfile {
+    pub enabled: Option<bool>,
+    pub font_size_ratio: Option<f64>,
+    pub add_space_on_merge: Option<bool>,
+}
+
+impl MergeProfile {
+    pub fn apply(&self, config: &mut MergeConfig) {
+        if let Some(enabled) = self.enabled {
+            config.enabled = enabled;
+        }
+        if let Some(font_size_ratio) = self.font_size_ratio {
+            config.font_size_ratio = font_size_ratio;
+        }
+        if let Some(add_space_on_merge) = self.add_space_on_merge {
+            config.add_space_on_merge = Some(add_space_on_merge);
+        }
+    }
+}
+
 // --- Geometry Helpers ---
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -182,7 +224,9 @@ fn filter_bad_boxes(
         }
     }
 
-    // 3. Furigana Check (Japanese only)
+    // 3. Furigana Check (Japanese only): ruby lines leave the body text and are attached to
+    //    the line they annotate instead. Stores (parent, is_vertical) per ruby line.
+    let mut ruby_parent: Vec<Option<(usize, bool)>> = vec![None; n];
     if config.language.is_japanese() {
         for i in 0..n {
             if !keep[i] {
@@ -241,11 +285,25 @@ fn filter_bad_boxes(
 
                 if is_vertical_furigana || is_horizontal_furigana {
                     keep[j] = false;
+                    ruby_parent[j] = Some((i, is_vertical_furigana));
                 }
             }
         }
     }
 
+    let mut lines = lines;
+    for (j, parent) in ruby_parent.into_iter().enumerate() {
+        if let Some((i, is_vertical)) = parent
+            && keep[i]
+        {
+            let annotation = ruby_annotation(&lines[i], &lines[j], is_vertical);
+            lines[i].ruby.push(annotation);
+        }
+    }
+    for line in &mut lines {
+        line.ruby.sort_by_key(|ruby| ruby.start);
+    }
+
     lines
         .into_iter()
         .enumerate()
@@ -254,6 +312,39 @@ fn filter_bad_boxes(
         .collect()
 }
 
+/// Maps a ruby line onto the chars of `main` it sits beside, by where it falls along the
+/// main line's reading axis.
+fn ruby_annotation(main: &OcrResult, ruby: &OcrResult, is_vertical: bool) -> RubyAnnotation {
+    let (main_start, main_length, ruby_start, ruby_length) = if is_vertical {
+        (
+            main.tight_bounding_box.y,
+            main.tight_bounding_box.height,
+            ruby.tight_bounding_box.y,
+            ruby.tight_bounding_box.height,
+        )
+    } else {
+        (
+            main.tight_bounding_box.x,
+            main.tight_bounding_box.width,
+            ruby.tight_bounding_box.x,
+            ruby.tight_bounding_box.width,
+        )
+    };
+    let char_count = main.text.chars().count();
+    let to_fraction = |pos: f64| ((pos - main_start) / main_length.max(1.0)).clamp(0.0, 1.0);
+    let start = ((to_fraction(ruby_start) * char_count as f64).floor() as usize)
+        .min(char_count.saturating_sub(1));
+    let end = ((to_fraction(ruby_start + ruby_length) * char_count as f64).ceil() as usize)
+        .clamp(start + 1, char_count.max(1));
+
+    RubyAnnotation {
+        text: ruby.text.clone(),
+        start,
+        end,
+        tight_bounding_box: ruby.tight_bounding_box.clone(),
+    }
+}
+
 // --- Dynamic Merging Logic ---
 
 struct ProcessedLine {
@@ -479,39 +570,44 @@ pub fn auto_merge(lines: Vec<OcrResult>, w: u32, h: u32, config: &MergeConfig) -
             }
         });
 
-        let use_space_separator = if let Some(forced) = config.add_space_on_merge {
-            forced
-        } else {
-            !config.language.prefers_no_space()
-        };
+        let use_space_separator = config.adds_space();
 
         let mut text_content = String::new();
-        for (i, line) in group_lines.iter().enumerate() {
-            if i == 0 {
-                text_content.push_str(&line.text);
-                continue;
-            }
-            let prev = &group_lines[i - 1];
-            let curr = line;
-            let is_new_line = if is_vertical {
-                let p_x2 = prev.tight_bounding_box.x + prev.tight_bounding_box.width;
-                let c_x1 = curr.tight_bounding_box.x;
-                (p_x2 - c_x1).abs() > 0.0
-            } else {
-                let p_y2 = prev.tight_bounding_box.y + prev.tight_bounding_box.height;
-                let c_y1 = curr.tight_bounding_box.y;
-                (c_y1 - p_y2).max(0.0) > 0.0
-            };
-
-            if is_new_line {
-                text_content.push('\n');
-                text_content.push_str(&curr.text);
-            } else {
-                if use_space_separator {
+        let mut ruby = Vec::new();
+        let mut words = Vec::new();
+        for (i, curr) in group_lines.iter().enumerate() {
+            if i > 0 {
+                let prev = &group_lines[i - 1];
+                let is_new_line = if is_vertical {
+                    let p_x2 = prev.tight_bounding_box.x + prev.tight_bounding_box.width;
+                    let c_x1 = curr.tight_bounding_box.x;
+                    (p_x2 - c_x1).abs() > 0.0
+                } else {
+                    let p_y2 = prev.tight_bounding_box.y + prev.tight_bounding_box.height;
+                    let c_y1 = curr.tight_bounding_box.y;
+                    (c_y1 - p_y2).max(0.0) > 0.0
+                };
+
+                if is_new_line {
+                    text_content.push('\n');
+                } else if use_space_separator {
                     text_content.push(' ');
                 }
-                text_content.push_str(&curr.text);
             }
+
+            // Ruby and word offsets move with the line's text.
+            let offset = text_content.chars().count();
+            text_content.push_str(&curr.text);
+            ruby.extend(curr.ruby.iter().map(|annotation| RubyAnnotation {
+                start: annotation.start + offset,
+                end: annotation.end + offset,
+                ..annotation.clone()
+            }));
+            words.extend(curr.words.iter().map(|word| WordBox {
+                start: word.start + offset,
+                end: word.end + offset,
+                ..word.clone()
+            }));
         }
 
         let mut points = Vec::new();
@@ -535,6 +631,10 @@ pub fn auto_merge(lines: Vec<OcrResult>, w: u32, h: u32, config: &MergeConfig) -
             } else {
                 "horizontal".into()
             }),
+            bubble_id: None,
+            reading_order: None,
+            ruby,
+            words,
         });
     }
     results
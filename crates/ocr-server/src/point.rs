@@ -0,0 +1,58 @@
+//! Finding the OCR result under a point of an already processed page.
+
+use crate::{
+    language::OcrLanguage,
+    logic::{self, OcrResult},
+    merge::MergeProfile,
+    state::AppState,
+};
+
+/// The result under a point and where in its text the point falls.
+#[derive(Debug)]
+pub struct OcrHit {
+    pub cache_key: String,
+    pub result: OcrResult,
+    /// Char offset into `result.text`.
+    pub char_offset: usize,
+}
+
+impl AppState {
+    /// Looks up the cached page for `url` and returns the result under the page-normalized
+    /// point `(x, y)`. Where boxes overlap the smallest one wins. `None` when the page hasn't
+    /// been OCR'd with these settings or there is no text at the point.
+    ///
+    /// `merge_profile` and `overrides` are resolved like the merge parameters of `/ocr`, so
+    /// the lookup finds the page the reader was shown.
+    pub fn ocr_result_at(
+        &self,
+        url: &str,
+        language: OcrLanguage,
+        merge_profile: Option<&str>,
+        overrides: &MergeProfile,
+        x: f64,
+        y: f64,
+    ) -> Option<OcrHit> {
+        let merge_config = self
+            .resolve_merge_config(url, language, merge_profile, overrides)
+            .ok()?;
+        let cache_key = logic::get_merge_cache_key(url, &merge_config);
+        let entry = self.get_cache_entry(&cache_key)?;
+        let results = self.corrected_results(&cache_key, entry.data);
+
+        let area =
+            |result: &OcrResult| result.tight_bounding_box.width * result.tight_bounding_box.height;
+        let (result, char_offset) = results
+            .into_iter()
+            .filter_map(|result| {
+                let offset = result.char_offset_at(x, y)?;
+                Some((result, offset))
+            })
+            .min_by(|(a, _), (b, _)| area(a).total_cmp(&area(b)))?;
+
+        Some(OcrHit {
+            cache_key,
+            result,
+            char_offset,
+        })
+    }
+}
@@ -0,0 +1,231 @@
+//! OCR of the chapters after the one being read, before the reader gets there.
+//!
+//! Reader requests that name their chapter tell us what is being read. The chapters after it
+//! in the series' chapter list from Suwayomi are queued as background jobs, so they are ready
+//! by the time the reader turns the page. How many chapters are fetched ahead can be set per
+//! series, and a series can opt out entirely.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rusqlite::{OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{
+    logic,
+    scheduler::OcrPriority,
+    state::{AppState, JobRecord, JobStatus, now_unix},
+};
+
+/// How long after prefetching for a chapter another read of it is ignored, so every page of
+/// the chapter doesn't ask Suwayomi about the next ones again.
+const PREFETCH_RECHECK: Duration = Duration::from_secs(10 * 60);
+
+/// Queue priority of prefetched chapters beyond the next one, below jobs queued by hand.
+const PREFETCH_QUEUE_PRIORITY: i64 = -1;
+
+/// Chapters already prefetched for, and when.
+#[derive(Clone, Default)]
+pub struct PrefetchClaims {
+    chapters: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl PrefetchClaims {
+    /// Returns `false` if `chapter_key` was claimed recently.
+    fn claim(&self, chapter_key: &str) -> bool {
+        let now = Instant::now();
+        let mut chapters = self.chapters.lock().expect("lock poisoned");
+        chapters.retain(|_, claimed_at| now - *claimed_at < PREFETCH_RECHECK);
+        if chapters.contains_key(chapter_key) {
+            return false;
+        }
+        chapters.insert(chapter_key.to_string(), now);
+        true
+    }
+}
+
+/// Prefetch settings of one series, overriding the global ones.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SeriesPrefetch {
+    /// `false` opts the series out of prefetching.
+    pub enabled: bool,
+    /// Chapters to fetch ahead; the global setting applies when unset.
+    pub chapters: Option<usize>,
+}
+
+impl AppState {
+    pub fn series_prefetch(&self, series: &str) -> Option<SeriesPrefetch> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for series_prefetch");
+            return None;
+        };
+        conn.query_row(
+            "SELECT enabled, chapters FROM series_prefetch WHERE series = ?",
+            params![series],
+            |row| {
+                Ok(SeriesPrefetch {
+                    enabled: row.get::<_, i64>(0)? != 0,
+                    chapters: row.get::<_, Option<i64>>(1)?.map(|value| value as usize),
+                })
+            },
+        )
+        .optional()
+        .unwrap_or(None)
+    }
+
+    /// Every series with its own prefetch settings.
+    pub fn all_series_prefetch(&self) -> BTreeMap<String, SeriesPrefetch> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for all_series_prefetch");
+            return BTreeMap::new();
+        };
+        let Ok(mut stmt) = conn.prepare("SELECT series, enabled, chapters FROM series_prefetch")
+        else {
+            return BTreeMap::new();
+        };
+        stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                SeriesPrefetch {
+                    enabled: row.get::<_, i64>(1)? != 0,
+                    chapters: row.get::<_, Option<i64>>(2)?.map(|value| value as usize),
+                },
+            ))
+        })
+        .map(|rows| rows.flatten().collect())
+        .unwrap_or_default()
+    }
+
+    /// Stores the series' own prefetch settings, or goes back to the global ones with `None`.
+    pub fn set_series_prefetch(&self, series: &str, settings: Option<&SeriesPrefetch>) {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for set_series_prefetch");
+            return;
+        };
+        let _ = match settings {
+            Some(settings) => conn.execute(
+                "INSERT INTO series_prefetch (series, enabled, chapters) VALUES (?, ?, ?)
+                 ON CONFLICT(series) DO UPDATE SET
+                    enabled = excluded.enabled,
+                    chapters = excluded.chapters",
+                params![
+                    series,
+                    settings.enabled,
+                    settings.chapters.map(|value| value as i64)
+                ],
+            ),
+            None => conn.execute(
+                "DELETE FROM series_prefetch WHERE series = ?",
+                params![series],
+            ),
+        };
+    }
+
+    /// Queues the chapters after `reading` for OCR. `reading` describes the chapter being read
+    /// the way a job for it would; the queued jobs copy its language, engine, merge settings
+    /// and credentials. The next chapter is OCR'd ahead of other preprocessing, the ones after
+    /// it as bulk work.
+    pub async fn prefetch_after(&self, reading: JobRecord) {
+        let Some((series, current)) = logic::chapter_location(&reading.base_url) else {
+            return;
+        };
+        let Ok(current) = current.parse::<u32>() else {
+            return;
+        };
+        let series_settings = self.series_prefetch(series);
+        if series_settings
+            .as_ref()
+            .is_some_and(|settings| !settings.enabled)
+        {
+            return;
+        }
+        let chapters = series_settings
+            .and_then(|settings| settings.chapters)
+            .unwrap_or(self.config().prefetch_chapters);
+        if chapters == 0 || !self.prefetch_claims.claim(&reading.job_id) {
+            return;
+        }
+
+        // Chapter indexes can have gaps, so the next ones come from the series' chapter list.
+        let following: Vec<u32> = match logic::resolve_series_chapters(
+            &reading.base_url,
+            series,
+            reading.user.clone(),
+            reading.pass.clone(),
+        )
+        .await
+        {
+            Ok(indexes) => indexes
+                .into_iter()
+                .filter(|&index| index > current)
+                .take(chapters)
+                .collect(),
+            Err(err) => {
+                warn!(
+                    "Failed to list the chapters after {}: {err:#}",
+                    reading.base_url
+                );
+                return;
+            }
+        };
+
+        for (ahead, index) in following.into_iter().enumerate() {
+            let Some(base_url) = logic::sibling_chapter_url(&reading.base_url, index) else {
+                return;
+            };
+            let job_id = logic::get_merge_cache_key(&base_url, &reading.merge_config);
+            if self.get_job(&job_id).is_some() {
+                continue;
+            }
+
+            let pages = match logic::resolve_chapter_pages(
+                &base_url,
+                reading.user.clone(),
+                reading.pass.clone(),
+            )
+            .await
+            {
+                Ok(pages) if !pages.is_empty() => pages,
+                Ok(_) => continue,
+                Err(err) => {
+                    debug!("Failed to list the pages of {base_url}: {err:#}");
+                    continue;
+                }
+            };
+            let engine = self.engine_id(reading.engine.as_deref());
+            if pages.iter().all(|page| {
+                self.has_cache_entry_from(
+                    &logic::get_merge_cache_key(page, &reading.merge_config),
+                    Some(&engine),
+                )
+            }) {
+                continue;
+            }
+
+            info!("Prefetching chapter {base_url} ({} pages)", pages.len());
+            let now = now_unix();
+            let (priority, ocr_priority) = if ahead == 0 {
+                (0, OcrPriority::NextChapter)
+            } else {
+                (PREFETCH_QUEUE_PRIORITY, OcrPriority::Bulk)
+            };
+            self.enqueue_job(
+                &JobRecord {
+                    job_id,
+                    base_url,
+                    status: JobStatus::Queued,
+                    priority,
+                    ocr_priority,
+                    created_at: now,
+                    updated_at: now,
+                    ..reading.clone()
+                },
+                &pages,
+            );
+        }
+    }
+}
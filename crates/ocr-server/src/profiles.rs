@@ -0,0 +1,166 @@
+//! Named merge profiles and their assignment to series.
+
+use std::collections::BTreeMap;
+
+use rusqlite::{OptionalExtension, params};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{
+    language::OcrLanguage,
+    logic,
+    merge::{MergeConfig, MergeProfile},
+    state::{AppState, now_unix},
+};
+
+#[derive(Serialize, Debug)]
+pub struct MergeProfiles {
+    pub profiles: BTreeMap<String, MergeProfile>,
+    /// Series (Suwayomi manga id) to profile name.
+    pub series: BTreeMap<String, String>,
+}
+
+impl AppState {
+    pub fn merge_profiles(&self) -> MergeProfiles {
+        let mut out = MergeProfiles {
+            profiles: BTreeMap::new(),
+            series: BTreeMap::new(),
+        };
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for merge_profiles");
+            return out;
+        };
+
+        if let Ok(mut stmt) = conn.prepare("SELECT name, profile FROM merge_profiles")
+            && let Ok(rows) = stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+        {
+            for (name, profile) in rows.flatten() {
+                if let Ok(profile) = serde_json::from_str(&profile) {
+                    out.profiles.insert(name, profile);
+                }
+            }
+        }
+        if let Ok(mut stmt) = conn.prepare("SELECT series, profile_name FROM series_merge_profiles")
+            && let Ok(rows) = stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+        {
+            out.series.extend(rows.flatten());
+        }
+        out
+    }
+
+    pub fn get_merge_profile(&self, name: &str) -> Option<MergeProfile> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for get_merge_profile");
+            return None;
+        };
+        conn.query_row(
+            "SELECT profile FROM merge_profiles WHERE name = ?",
+            params![name],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+        .unwrap_or(None)
+        .and_then(|json| serde_json::from_str(&json).ok())
+    }
+
+    pub fn save_merge_profile(&self, name: &str, profile: &MergeProfile) {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for save_merge_profile");
+            return;
+        };
+        let json = serde_json::to_string(profile).unwrap_or_default();
+        let _ = conn.execute(
+            "INSERT INTO merge_profiles (name, profile, updated_at) VALUES (?, ?, ?)
+             ON CONFLICT(name) DO UPDATE SET
+                profile = excluded.profile,
+                updated_at = excluded.updated_at",
+            params![name, json, now_unix()],
+        );
+    }
+
+    /// Deletes a profile; series that used it go back to the defaults.
+    pub fn delete_merge_profile(&self, name: &str) -> bool {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for delete_merge_profile");
+            return false;
+        };
+        let _ = conn.execute(
+            "DELETE FROM series_merge_profiles WHERE profile_name = ?",
+            params![name],
+        );
+        conn.execute("DELETE FROM merge_profiles WHERE name = ?", params![name])
+            .map(|changed| changed > 0)
+            .unwrap_or(false)
+    }
+
+    /// Uses `profile_name` for every page of `series`, or clears the assignment with `None`.
+    pub fn assign_series_profile(&self, series: &str, profile_name: Option<&str>) {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for assign_series_profile");
+            return;
+        };
+        let _ = match profile_name {
+            Some(profile_name) => conn.execute(
+                "INSERT INTO series_merge_profiles (series, profile_name) VALUES (?, ?)
+                 ON CONFLICT(series) DO UPDATE SET profile_name = excluded.profile_name",
+                params![series, profile_name],
+            ),
+            None => conn.execute(
+                "DELETE FROM series_merge_profiles WHERE series = ?",
+                params![series],
+            ),
+        };
+    }
+
+    fn series_merge_profile(&self, series: &str) -> Option<MergeProfile> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for series_merge_profile");
+            return None;
+        };
+        let name = conn
+            .query_row(
+                "SELECT profile_name FROM series_merge_profiles WHERE series = ?",
+                params![series],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .unwrap_or(None)?;
+        drop(conn);
+        self.get_merge_profile(&name)
+    }
+
+    /// Merge settings for a page or chapter URL: the defaults, then the series profile, then
+    /// the named `profile`, then the request's own `overrides`.
+    pub fn resolve_merge_config(
+        &self,
+        url: &str,
+        language: OcrLanguage,
+        profile: Option<&str>,
+        overrides: &MergeProfile,
+    ) -> Result<MergeConfig, String> {
+        let mut config = MergeConfig {
+            language,
+            ..MergeConfig::default()
+        };
+
+        let path = logic::get_cache_key(url, None);
+        if let Some(series) = logic::cache_key_series(&path)
+            && let Some(series_profile) = self.series_merge_profile(series)
+        {
+            series_profile.apply(&mut config);
+        }
+        if let Some(name) = profile {
+            let named = self
+                .get_merge_profile(name)
+                .ok_or_else(|| format!("Unknown merge profile: {name}"))?;
+            named.apply(&mut config);
+        }
+        overrides.apply(&mut config);
+
+        Ok(config)
+    }
+}
@@ -0,0 +1,304 @@
+//! Speech-bubble grouping and reading order for the merged results of a page.
+//!
+//! Bubbles are results whose boxes sit within about a line height of each other. Panels are
+//! approximated by tiers: bubbles that overlap vertically are read side by side, tiers top
+//! to bottom. Pages in a vertical script read right to left, everything else left to right.
+
+use std::cmp::Ordering;
+
+use crate::{
+    language::OcrLanguage,
+    logic::{BoundingBox, OcrResult},
+};
+
+/// Gap allowed between two results of the same bubble, relative to their font size.
+const BUBBLE_GAP_RATIO: f64 = 0.6;
+
+/// Share of the shorter bubble's height two bubbles must overlap by to share a tier.
+const TIER_OVERLAP_RATIO: f64 = 0.5;
+
+#[derive(Clone, Copy)]
+struct Extent {
+    left: f64,
+    top: f64,
+    right: f64,
+    bottom: f64,
+}
+
+impl Extent {
+    fn of(bbox: &BoundingBox) -> Self {
+        Self {
+            left: bbox.x,
+            top: bbox.y,
+            right: bbox.x + bbox.width,
+            bottom: bbox.y + bbox.height,
+        }
+    }
+
+    fn grow(self, margin: f64) -> Self {
+        Self {
+            left: self.left - margin,
+            top: self.top - margin,
+            right: self.right + margin,
+            bottom: self.bottom + margin,
+        }
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    fn intersects(&self, other: &Self) -> bool {
+        self.left < other.right
+            && other.left < self.right
+            && self.top < other.bottom
+            && other.top < self.bottom
+    }
+
+    fn height(&self) -> f64 {
+        self.bottom - self.top
+    }
+
+    fn center_x(&self) -> f64 {
+        (self.left + self.right) / 2.0
+    }
+}
+
+struct Bubble {
+    extent: Extent,
+    /// Indices into the page's results, in reading order once sorted.
+    members: Vec<usize>,
+}
+
+fn find(parent: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    parent[i] = root;
+    root
+}
+
+/// Groups `results` into bubbles, sorts them into reading order and fills in `bubble_id`
+/// and `reading_order`. Boxes must share one coordinate space with square pixels.
+pub fn assign_reading_order(results: &mut Vec<OcrResult>, language: OcrLanguage) {
+    let right_to_left = language.prefers_vertical();
+    let extents: Vec<Extent> = results
+        .iter()
+        .map(|result| Extent::of(&result.tight_bounding_box))
+        .collect();
+
+    // 1. Bubbles: union results whose boxes, grown by a fraction of the font size, touch.
+    let mut parent: Vec<usize> = (0..results.len()).collect();
+    for i in 0..results.len() {
+        let bbox_i = &results[i].tight_bounding_box;
+        let grown_i = extents[i].grow(bbox_i.width.min(bbox_i.height) * BUBBLE_GAP_RATIO);
+        for j in i + 1..results.len() {
+            let bbox_j = &results[j].tight_bounding_box;
+            let grown_j = extents[j].grow(bbox_j.width.min(bbox_j.height) * BUBBLE_GAP_RATIO);
+            if grown_i.intersects(&grown_j) {
+                let (root_i, root_j) = (find(&mut parent, i), find(&mut parent, j));
+                parent[root_j] = root_i;
+            }
+        }
+    }
+
+    let mut bubbles: Vec<Bubble> = Vec::new();
+    let mut bubble_of_root = vec![usize::MAX; results.len()];
+    for (i, &extent) in extents.iter().enumerate() {
+        let root = find(&mut parent, i);
+        if bubble_of_root[root] == usize::MAX {
+            bubble_of_root[root] = bubbles.len();
+            bubbles.push(Bubble {
+                extent,
+                members: Vec::new(),
+            });
+        }
+        let bubble = &mut bubbles[bubble_of_root[root]];
+        bubble.extent = bubble.extent.union(extent);
+        bubble.members.push(i);
+    }
+
+    // 2. Lines inside a bubble: columns right to left for vertical text, rows top to bottom
+    //    otherwise. The bubble's orientation is the one most of its lines have, so lines of
+    //    both kinds are ordered by the same rule.
+    for bubble in &mut bubbles {
+        let vertical_lines = bubble
+            .members
+            .iter()
+            .filter(|&&i| results[i].forced_orientation.as_deref() == Some("vertical"))
+            .count();
+        let vertical = vertical_lines * 2 > bubble.members.len();
+        bubble.members.sort_by(|&a, &b| {
+            let (ea, eb) = (&extents[a], &extents[b]);
+            if vertical {
+                let columns = if right_to_left {
+                    eb.right.partial_cmp(&ea.right)
+                } else {
+                    ea.left.partial_cmp(&eb.left)
+                };
+                columns
+                    .unwrap_or(Ordering::Equal)
+                    .then(ea.top.partial_cmp(&eb.top).unwrap_or(Ordering::Equal))
+            } else {
+                ea.top
+                    .partial_cmp(&eb.top)
+                    .unwrap_or(Ordering::Equal)
+                    .then(ea.left.partial_cmp(&eb.left).unwrap_or(Ordering::Equal))
+            }
+        });
+    }
+
+    // 3. Tiers: bubbles sharing enough vertical extent are read across, tiers top to bottom.
+    bubbles.sort_by(|a, b| {
+        a.extent
+            .top
+            .partial_cmp(&b.extent.top)
+            .unwrap_or(Ordering::Equal)
+    });
+    let mut tiers: Vec<(Extent, Vec<Bubble>)> = Vec::new();
+    for bubble in bubbles {
+        let extent = bubble.extent;
+        let joins_last = tiers.last().is_some_and(|(tier, _)| {
+            let overlap = tier.bottom.min(extent.bottom) - tier.top.max(extent.top);
+            overlap >= tier.height().min(extent.height()) * TIER_OVERLAP_RATIO
+        });
+        match tiers.last_mut() {
+            Some((tier, tier_bubbles)) if joins_last => {
+                *tier = tier.union(extent);
+                tier_bubbles.push(bubble);
+            }
+            _ => tiers.push((extent, vec![bubble])),
+        }
+    }
+
+    // 4. Bubble ids count up in reading order across the whole page.
+    let mut order = Vec::with_capacity(results.len());
+    let mut bubble_id = 0;
+    for (_, mut tier_bubbles) in tiers {
+        tier_bubbles.sort_by(|a, b| {
+            let across = a.extent.center_x().partial_cmp(&b.extent.center_x());
+            let across = if right_to_left {
+                across.map(Ordering::reverse)
+            } else {
+                across
+            };
+            across.unwrap_or(Ordering::Equal)
+        });
+        for bubble in tier_bubbles {
+            order.extend(bubble.members.into_iter().map(|index| (index, bubble_id)));
+            bubble_id += 1;
+        }
+    }
+
+    let mut slots: Vec<Option<OcrResult>> = results.drain(..).map(Some).collect();
+    for (reading_order, (index, bubble_id)) in order.into_iter().enumerate() {
+        if let Some(mut result) = slots[index].take() {
+            result.bubble_id = Some(bubble_id);
+            result.reading_order = Some(reading_order as u32);
+            results.push(result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(text: &str, x: f64, y: f64, width: f64, height: f64) -> OcrResult {
+        OcrResult {
+            text: text.to_string(),
+            tight_bounding_box: BoundingBox {
+                x,
+                y,
+                width,
+                height,
+                rotation: None,
+            },
+            is_merged: None,
+            forced_orientation: Some(
+                if height > width {
+                    "vertical"
+                } else {
+                    "horizontal"
+                }
+                .into(),
+            ),
+            bubble_id: None,
+            reading_order: None,
+            ruby: Vec::new(),
+            words: Vec::new(),
+        }
+    }
+
+    fn order(results: &[OcrResult]) -> Vec<(&str, u32, u32)> {
+        results
+            .iter()
+            .map(|result| {
+                (
+                    result.text.as_str(),
+                    result.bubble_id.unwrap(),
+                    result.reading_order.unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vertical_pages_read_columns_and_bubbles_right_to_left() {
+        let mut results = vec![
+            result("left bubble", 200.0, 120.0, 30.0, 200.0),
+            result("second column", 760.0, 100.0, 30.0, 200.0),
+            result("next tier", 500.0, 1000.0, 30.0, 200.0),
+            result("first column", 800.0, 100.0, 30.0, 200.0),
+        ];
+        assign_reading_order(&mut results, OcrLanguage::Japanese);
+        assert_eq!(
+            order(&results),
+            [
+                ("first column", 0, 0),
+                ("second column", 0, 1),
+                ("left bubble", 1, 2),
+                ("next tier", 2, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn horizontal_pages_read_rows_and_bubbles_left_to_right() {
+        let mut results = vec![
+            result("below", 100.0, 800.0, 300.0, 30.0),
+            result("beside", 700.0, 110.0, 200.0, 30.0),
+            result("world", 100.0, 140.0, 300.0, 30.0),
+            result("hello", 100.0, 100.0, 300.0, 30.0),
+        ];
+        assign_reading_order(&mut results, OcrLanguage::English);
+        assert_eq!(
+            order(&results),
+            [
+                ("hello", 0, 0),
+                ("world", 0, 1),
+                ("beside", 1, 2),
+                ("below", 2, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn mixed_bubbles_read_the_same_whatever_the_input_order() {
+        let column = result("column", 180.0, 120.0, 50.0, 300.0);
+        let caption = result("caption", 150.0, 440.0, 100.0, 40.0);
+        for mut results in [
+            vec![column.clone(), caption.clone()],
+            vec![caption.clone(), column.clone()],
+        ] {
+            assign_reading_order(&mut results, OcrLanguage::Japanese);
+            assert_eq!(order(&results), [("column", 0, 0), ("caption", 0, 1)]);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! OCR of a single region of a page, for small text, sound effects and signs that full-page
+//! OCR missed.
+//!
+//! The region is cropped out, upscaled and sent to the engine on its own. Its lines come back
+//! as a [`RawChunk`] in page pixels, so they can be merged on their own or stored next to the
+//! page's other chunks and survive a re-merge.
+
+use anyhow::{Context, bail};
+use image::imageops::FilterType;
+use rusqlite::{OptionalExtension, params};
+
+use crate::{
+    engine::{EngineContext, OcrEngine},
+    language::OcrLanguage,
+    logic::{self, BoundingBox, OcrResult, RawChunk},
+    merge::{MERGE_VERSION, MergeConfig},
+    search,
+    state::{AppState, now_unix},
+};
+
+/// Shorter side, in pixels, an automatically upscaled region is brought up to.
+const REGION_TARGET_SIDE: f64 = 800.0;
+
+/// Largest upscale factor, whether automatic or requested.
+const MAX_REGION_UPSCALE: f64 = 4.0;
+
+/// Runs OCR on the page-normalized `region` of `image_bytes`. `upscale` is the factor the crop
+/// is enlarged by before recognition; without one, small regions are enlarged until their
+/// shorter side reaches [`REGION_TARGET_SIDE`].
+pub async fn recognize_region(
+    image_bytes: &[u8],
+    region: &BoundingBox,
+    upscale: Option<f64>,
+    engine: &dyn OcrEngine,
+    ctx: &EngineContext,
+    language: OcrLanguage,
+) -> anyhow::Result<RawChunk> {
+    let image = logic::decode_image(image_bytes)?;
+    let (full_width, full_height) = (image.width(), image.height());
+
+    let left = (region.x.clamp(0.0, 1.0) * full_width as f64).floor() as u32;
+    let top = (region.y.clamp(0.0, 1.0) * full_height as f64).floor() as u32;
+    let right = ((region.x + region.width).clamp(0.0, 1.0) * full_width as f64).ceil() as u32;
+    let bottom = ((region.y + region.height).clamp(0.0, 1.0) * full_height as f64).ceil() as u32;
+    if right <= left || bottom <= top {
+        bail!("Region is empty or outside the page");
+    }
+    let (crop_width, crop_height) = (right - left, bottom - top);
+
+    let factor = upscale
+        .unwrap_or_else(|| REGION_TARGET_SIDE / crop_width.min(crop_height) as f64)
+        .clamp(1.0, MAX_REGION_UPSCALE);
+    let mut crop = image.crop_imm(left, top, crop_width, crop_height);
+    if factor > 1.0 {
+        crop = crop.resize_exact(
+            (crop_width as f64 * factor).round() as u32,
+            (crop_height as f64 * factor).round() as u32,
+            FilterType::Lanczos3,
+        );
+    }
+
+    let engine_lines = engine
+        .recognize(&logic::encode_png(&crop)?, language, ctx)
+        .await?;
+
+    // Upscaled crop pixels -> page pixels, with y relative to the top of the region like the
+    // lines of any other chunk.
+    let to_page = |bbox: &mut BoundingBox| {
+        bbox.x = left as f64 + bbox.x / factor;
+        bbox.y /= factor;
+        bbox.width /= factor;
+        bbox.height /= factor;
+    };
+    let lines = engine_lines
+        .into_iter()
+        .filter_map(|line| {
+            let mut result =
+                logic::line_to_pixel_result(line, crop.width(), crop.height(), language)?;
+            to_page(&mut result.tight_bounding_box);
+            for word in &mut result.words {
+                to_page(&mut word.tight_bounding_box);
+            }
+            Some(result)
+        })
+        .collect();
+
+    Ok(RawChunk {
+        lines,
+        width: full_width,
+        height: crop_height,
+        global_y: top,
+        full_width,
+        full_height,
+    })
+}
+
+/// The region's own results, merged and in page-normalized coordinates.
+pub fn region_results(chunk: RawChunk, merge_config: &MergeConfig) -> Vec<OcrResult> {
+    logic::merge_chunks(&[chunk], merge_config)
+}
+
+impl AppState {
+    /// Adds a region chunk to a cached page and re-merges it, so the region's lines join the
+    /// page's results. Returns `false` when the page isn't cached or was cached without raw
+    /// chunks.
+    pub fn append_region(&self, cache_key: &str, chunk: RawChunk) -> anyhow::Result<bool> {
+        let conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection for append_region")?;
+        let stored = conn
+            .query_row(
+                "SELECT raw_chunks, merge_config FROM ocr_cache WHERE cache_key = ?",
+                params![cache_key],
+                |row| {
+                    Ok((
+                        row.get::<_, Option<Vec<u8>>>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((Some(raw_blob), merge_config)) = stored else {
+            return Ok(false);
+        };
+
+        let mut raw_chunks: Vec<RawChunk> = serde_json::from_slice(&raw_blob)?;
+        if let Some(first) = raw_chunks.first()
+            && (first.full_width, first.full_height) != (chunk.full_width, chunk.full_height)
+        {
+            bail!(
+                "Region image is {}x{} but the cached page is {}x{}",
+                chunk.full_width,
+                chunk.full_height,
+                first.full_width,
+                first.full_height
+            );
+        }
+        raw_chunks.push(chunk);
+        let merge_config: MergeConfig = match merge_config {
+            Some(json) => serde_json::from_str(&json)?,
+            None => MergeConfig::default(),
+        };
+
+        let results = logic::merge_chunks(&raw_chunks, &merge_config);
+        conn.execute(
+            "UPDATE ocr_cache
+             SET data = ?, raw_chunks = ?, merge_version = ?, last_processed_at = ?
+             WHERE cache_key = ?",
+            params![
+                serde_json::to_vec(&results)?,
+                serde_json::to_vec(&raw_chunks)?,
+                MERGE_VERSION,
+                now_unix(),
+                cache_key
+            ],
+        )?;
+        if let Err(err) = search::index_page(&conn, cache_key, &results) {
+            search::index_failed(&conn, cache_key, &err);
+        }
+        Ok(true)
+    }
+}
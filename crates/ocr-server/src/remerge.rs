@@ -0,0 +1,179 @@
+//! Re-running [`crate::merge::auto_merge`] over cached raw chunks, so merge improvements and setting
+//! changes apply to already processed pages without calling the OCR engine again.
+
+use anyhow::Context;
+use rusqlite::{OptionalExtension, params};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    archive::ArchiveFilter,
+    logic::{self, RawChunk},
+    merge::{MERGE_VERSION, MergeConfig, MergeProfile},
+    search,
+    state::AppState,
+};
+
+/// Pages re-merged per blocking task by the startup pass.
+const REMERGE_BATCH_SIZE: usize = 100;
+
+#[derive(Serialize, Default, Debug)]
+pub struct RemergeReport {
+    pub remerged: usize,
+    /// Pages cached before raw chunks were stored; they need a fresh OCR run instead.
+    pub missing_raw: usize,
+}
+
+impl AppState {
+    /// Pages with raw chunks that were merged by an older [`MERGE_VERSION`].
+    fn stale_merge_keys(&self, limit: usize) -> Vec<String> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for stale_merge_keys");
+            return Vec::new();
+        };
+        let Ok(mut stmt) = conn.prepare(
+            "SELECT cache_key FROM ocr_cache
+             WHERE raw_chunks IS NOT NULL AND merge_version < ?
+             LIMIT ?",
+        ) else {
+            return Vec::new();
+        };
+        stmt.query_map(params![MERGE_VERSION, limit as i64], |row| row.get(0))
+            .map(|rows| rows.flatten().collect())
+            .unwrap_or_default()
+    }
+
+    fn mark_merge_current(&self, cache_key: &str) {
+        let Ok(conn) = self.pool.get() else {
+            return;
+        };
+        let _ = conn.execute(
+            "UPDATE ocr_cache SET merge_version = ? WHERE cache_key = ?",
+            params![MERGE_VERSION, cache_key],
+        );
+    }
+
+    /// Re-merges one page from its stored raw chunks. Returns `false` when the page has none.
+    ///
+    /// Cache keys record the merge settings, so when `overrides` change them the result is
+    /// stored under the matching key and the original entry is left alone.
+    pub fn remerge_page(&self, cache_key: &str, overrides: &MergeProfile) -> anyhow::Result<bool> {
+        let conn = self
+            .pool
+            .get()
+            .context("Failed to get DB connection for remerge_page")?;
+        let stored = conn
+            .query_row(
+                "SELECT raw_chunks, merge_config FROM ocr_cache WHERE cache_key = ?",
+                params![cache_key],
+                |row| {
+                    Ok((
+                        row.get::<_, Option<Vec<u8>>>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((Some(raw_blob), merge_config)) = stored else {
+            return Ok(false);
+        };
+
+        let raw_chunks: Vec<RawChunk> = serde_json::from_slice(&raw_blob)?;
+        let mut merge_config: MergeConfig = match merge_config {
+            Some(json) => serde_json::from_str(&json)?,
+            None => MergeConfig::default(),
+        };
+        overrides.apply(&mut merge_config);
+
+        let results = logic::merge_chunks(&raw_chunks, &merge_config);
+        let data_blob = serde_json::to_vec(&results)?;
+        let merge_json = serde_json::to_string(&merge_config)?;
+        let target_key =
+            logic::with_merge_suffix(logic::strip_merge_suffix(cache_key), &merge_config);
+        if target_key == cache_key {
+            conn.execute(
+                "UPDATE ocr_cache SET data = ?, merge_version = ?, merge_config = ?
+                 WHERE cache_key = ?",
+                params![data_blob, MERGE_VERSION, merge_json, cache_key],
+            )?;
+        } else {
+            conn.execute(
+                "INSERT OR REPLACE INTO ocr_cache
+                    (cache_key, context, data, created_at, last_processed_at, last_accessed_at,
+                     access_count, raw_chunks, merge_version, merge_config, ocr_language,
+                     content_hash, hash_checked_at, engine)
+                 SELECT ?, context, ?, created_at, last_processed_at, last_accessed_at,
+                     0, raw_chunks, ?, ?, ocr_language, content_hash, hash_checked_at, engine
+                 FROM ocr_cache WHERE cache_key = ?",
+                params![target_key, data_blob, MERGE_VERSION, merge_json, cache_key],
+            )?;
+        }
+        if let Err(err) = search::index_page(&conn, &target_key, &results) {
+            search::index_failed(&conn, &target_key, &err);
+        }
+        Ok(true)
+    }
+
+    /// Re-merges every cached page matching `filter` with `overrides` applied.
+    pub fn remerge_cache(&self, filter: &ArchiveFilter, overrides: &MergeProfile) -> RemergeReport {
+        let mut report = RemergeReport::default();
+        let cache_keys: Vec<String> = {
+            let Ok(conn) = self.pool.get() else {
+                warn!("Failed to get DB connection for remerge_cache");
+                return report;
+            };
+            let Ok(mut stmt) = conn.prepare("SELECT cache_key FROM ocr_cache") else {
+                return report;
+            };
+            stmt.query_map([], |row| row.get::<_, String>(0))
+                .map(|rows| rows.flatten().filter(|key| filter.matches(key)).collect())
+                .unwrap_or_default()
+        };
+
+        for cache_key in cache_keys {
+            match self.remerge_page(&cache_key, overrides) {
+                Ok(true) => report.remerged += 1,
+                Ok(false) => report.missing_raw += 1,
+                Err(err) => warn!("Failed to re-merge {cache_key}: {err:#}"),
+            }
+        }
+        report
+    }
+}
+
+/// Startup pass that brings pages merged by an older algorithm up to [`MERGE_VERSION`].
+pub async fn run_remerge(state: AppState) {
+    let mut total = 0;
+    loop {
+        let worker_state = state.clone();
+        let batch = tokio::task::spawn_blocking(move || {
+            let keys = worker_state.stale_merge_keys(REMERGE_BATCH_SIZE);
+            let mut remerged = 0;
+            for cache_key in &keys {
+                match worker_state.remerge_page(cache_key, &MergeProfile::default()) {
+                    Ok(true) => remerged += 1,
+                    Ok(false) => {}
+                    Err(err) => {
+                        warn!("Failed to re-merge {cache_key}: {err:#}");
+                        // Leave the page as is rather than retrying it forever.
+                        worker_state.mark_merge_current(cache_key);
+                    }
+                }
+            }
+            (keys.len(), remerged)
+        })
+        .await;
+
+        match batch {
+            Ok((0, _)) => break,
+            Ok((_, remerged)) => total += remerged,
+            Err(err) => {
+                warn!("Re-merge task failed: {err}");
+                break;
+            }
+        }
+    }
+    if total > 0 {
+        info!("Re-merged {total} cached pages with merge version {MERGE_VERSION}");
+    }
+}
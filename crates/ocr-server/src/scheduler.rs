@@ -0,0 +1,231 @@
+//! One concurrency budget for every OCR run in the server.
+//!
+//! Readers, next-chapter preprocessing and bulk jobs all queue here for a slot before their
+//! image goes to the engine. Free slots go to the most urgent waiter first, so the page on
+//! screen never sits behind a backlog of preprocessing. The budget comes from
+//! [`crate::config::OcrConfig::ocr_concurrency`] and can be changed while running.
+
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU8, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+/// Urgency of an OCR run, most urgent first.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OcrPriority {
+    /// A page someone is looking at right now.
+    Interactive,
+    /// The chapter after the one being read.
+    NextChapter,
+    /// Everything else preprocessing queued up.
+    #[default]
+    Bulk,
+}
+
+impl OcrPriority {
+    const ALL: [Self; 3] = [Self::Interactive, Self::NextChapter, Self::Bulk];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OcrPriority::Interactive => "interactive",
+            OcrPriority::NextChapter => "next_chapter",
+            OcrPriority::Bulk => "bulk",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "interactive" => OcrPriority::Interactive,
+            "next_chapter" => OcrPriority::NextChapter,
+            _ => OcrPriority::Bulk,
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        Self::ALL[usize::from(value).min(Self::ALL.len() - 1)]
+    }
+}
+
+/// Priority of a waiting run. It can be raised while the run waits, e.g. when a reader asks
+/// for a page a bulk job is already queued for.
+#[derive(Clone, Debug)]
+pub struct SharedPriority(Arc<AtomicU8>);
+
+impl SharedPriority {
+    pub fn new(priority: OcrPriority) -> Self {
+        Self(Arc::new(AtomicU8::new(priority as u8)))
+    }
+
+    pub fn get(&self) -> OcrPriority {
+        OcrPriority::from_u8(self.0.load(Ordering::Relaxed))
+    }
+
+    /// Makes the run at least as urgent as `priority`.
+    pub fn raise(&self, priority: OcrPriority) {
+        self.0.fetch_min(priority as u8, Ordering::Relaxed);
+    }
+}
+
+struct Waiter {
+    seq: u64,
+    priority: SharedPriority,
+    wake: oneshot::Sender<()>,
+}
+
+struct Slots {
+    limit: usize,
+    running: usize,
+    next_seq: u64,
+    waiting: Vec<Waiter>,
+}
+
+impl Slots {
+    /// Hands free slots to the most urgent waiters, oldest first within a class.
+    fn dispatch(&mut self) {
+        while self.running < self.limit {
+            let Some(index) = self
+                .waiting
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, waiter)| (waiter.priority.get(), waiter.seq))
+                .map(|(index, _)| index)
+            else {
+                break;
+            };
+            let waiter = self.waiting.swap_remove(index);
+            if waiter.wake.send(()).is_ok() {
+                self.running += 1;
+            }
+        }
+    }
+}
+
+/// Snapshot of the scheduler for the status endpoint.
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct SchedulerStats {
+    pub limit: usize,
+    pub running: usize,
+    pub waiting_interactive: usize,
+    pub waiting_next_chapter: usize,
+    pub waiting_bulk: usize,
+}
+
+#[derive(Clone)]
+pub struct Scheduler {
+    slots: Arc<Mutex<Slots>>,
+}
+
+impl Scheduler {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            slots: Arc::new(Mutex::new(Slots {
+                limit: limit.max(1),
+                running: 0,
+                next_seq: 0,
+                waiting: Vec::new(),
+            })),
+        }
+    }
+
+    /// Changes the budget. Runs already holding a slot finish; a raised limit wakes waiters
+    /// right away.
+    pub fn set_limit(&self, limit: usize) {
+        let mut slots = self.slots.lock().expect("lock poisoned");
+        slots.limit = limit.max(1);
+        slots.dispatch();
+    }
+
+    pub fn stats(&self) -> SchedulerStats {
+        let slots = self.slots.lock().expect("lock poisoned");
+        let waiting = |priority| {
+            slots
+                .waiting
+                .iter()
+                .filter(|waiter| waiter.priority.get() == priority)
+                .count()
+        };
+        SchedulerStats {
+            limit: slots.limit,
+            running: slots.running,
+            waiting_interactive: waiting(OcrPriority::Interactive),
+            waiting_next_chapter: waiting(OcrPriority::NextChapter),
+            waiting_bulk: waiting(OcrPriority::Bulk),
+        }
+    }
+
+    /// Waits for a slot. The slot is held until the returned permit is dropped.
+    pub async fn acquire(&self, priority: &SharedPriority) -> Permit {
+        let (wake, woken) = oneshot::channel();
+        let seq = {
+            let mut slots = self.slots.lock().expect("lock poisoned");
+            if slots.running < slots.limit && slots.waiting.is_empty() {
+                slots.running += 1;
+                return Permit {
+                    scheduler: self.clone(),
+                };
+            }
+            let seq = slots.next_seq;
+            slots.next_seq += 1;
+            slots.waiting.push(Waiter {
+                seq,
+                priority: priority.clone(),
+                wake,
+            });
+            seq
+        };
+
+        let mut queued = Queued {
+            scheduler: self.clone(),
+            seq: Some(seq),
+        };
+        // The sender is only ever dropped after sending.
+        let _ = woken.await;
+        queued.seq = None;
+        Permit {
+            scheduler: self.clone(),
+        }
+    }
+
+    fn release(&self) {
+        let mut slots = self.slots.lock().expect("lock poisoned");
+        slots.running = slots.running.saturating_sub(1);
+        slots.dispatch();
+    }
+}
+
+/// A held slot.
+pub struct Permit {
+    scheduler: Scheduler,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+/// Cleans up after a caller that stopped waiting: leaves the queue, or gives back the slot
+/// it was handed in the meantime.
+struct Queued {
+    scheduler: Scheduler,
+    seq: Option<u64>,
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        let Some(seq) = self.seq else {
+            return;
+        };
+        let mut slots = self.scheduler.slots.lock().expect("lock poisoned");
+        let before = slots.waiting.len();
+        slots.waiting.retain(|waiter| waiter.seq != seq);
+        if slots.waiting.len() == before {
+            slots.running = slots.running.saturating_sub(1);
+            slots.dispatch();
+        }
+    }
+}
@@ -0,0 +1,482 @@
+//! Full-text search across the OCR cache.
+//!
+//! Every cached page's lines are kept in `ocr_text_lines`, with their words in the FTS5 table
+//! `ocr_text_fts`. SQLite's tokenizer splits on spaces and punctuation, which finds nothing in
+//! Japanese or Chinese text, so CJK runs are broken into overlapping character bigrams before
+//! they are indexed. Queries go through the same transform, so a CJK query matches wherever its
+//! bigrams appear in a row. Other scripts are left to SQLite's `unicode61` tokenizer.
+//!
+//! The index follows the cache: every write of a page's results re-indexes the page, and a
+//! trigger drops a page's lines when it leaves `ocr_cache`. Pages are indexed as they are
+//! served, with their corrections applied, and are re-indexed when those change. An update that
+//! fails marks the index stale, so it is rebuilt on the next start.
+
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    corrections::{self, apply_corrections},
+    language::OcrLanguage,
+    logic::{self, BoundingBox, OcrResult},
+    state::AppState,
+};
+
+/// Bumped when tokenization changes, so the index is rebuilt on the next start.
+const SEARCH_INDEX_VERSION: &str = "2";
+
+/// Pages indexed per transaction while rebuilding.
+const REBUILD_BATCH_SIZE: usize = 200;
+
+pub const DEFAULT_SEARCH_LIMIT: usize = 50;
+pub const MAX_SEARCH_LIMIT: usize = 500;
+
+/// Narrows a search to part of the cache. Unset fields match everything.
+#[derive(Default, Clone, Debug)]
+pub struct SearchFilter {
+    /// Suwayomi manga id.
+    pub series: Option<String>,
+    /// Chapter index within the series.
+    pub chapter: Option<String>,
+    pub language: Option<OcrLanguage>,
+}
+
+/// One cached line matching a search.
+#[derive(Serialize, Clone, Debug)]
+pub struct SearchHit {
+    pub cache_key: String,
+    /// Position of the line in the page's results.
+    pub line: usize,
+    pub text: String,
+    pub bounding_box: BoundingBox,
+    pub series: Option<String>,
+    pub chapter: Option<String>,
+    pub language: String,
+}
+
+/// Han, kana and Hangul letters. Punctuation and combining marks are left out, since SQLite's
+/// tokenizer treats them as separators and they would split a bigram.
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{1100}'..='\u{11FF}'
+            | '\u{3005}'..='\u{3007}'
+            | '\u{3041}'..='\u{3096}'
+            | '\u{309D}'..='\u{309F}'
+            | '\u{30A1}'..='\u{30FA}'
+            | '\u{30FC}'..='\u{30FF}'
+            | '\u{3131}'..='\u{318E}'
+            | '\u{31F0}'..='\u{31FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7A3}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{FF66}'..='\u{FF9F}'
+            | '\u{20000}'..='\u{2FFFF}'
+    )
+}
+
+/// Rewrites `text` with every CJK run replaced by its bigrams, space separated.
+///
+/// Indexed text ends each run with its last character on its own, so single-character runs and
+/// a run followed by other text can be found. A query leaves that out at the end of its text,
+/// where the indexed run may go on; a single character there is matched as a prefix instead.
+/// Returns the terms and whether they end in such a prefix.
+fn cjk_terms(text: &str, query: bool) -> (String, bool) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut terms = String::with_capacity(text.len() * 2);
+    let mut prefix = false;
+    let mut i = 0;
+    while i < chars.len() {
+        if !is_cjk(chars[i]) {
+            terms.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && is_cjk(chars[i]) {
+            i += 1;
+        }
+        let run = &chars[start..i];
+        let at_end = i == chars.len();
+        terms.push(' ');
+        for pair in run.windows(2) {
+            terms.extend(pair);
+            terms.push(' ');
+        }
+        if !query || run.len() == 1 || !at_end {
+            terms.push(run[run.len() - 1]);
+            terms.push(' ');
+        }
+        prefix = query && at_end && run.len() == 1;
+    }
+    (terms, prefix)
+}
+
+/// Terms stored in the index for a line of a page in `language`.
+fn index_terms(text: &str, language: OcrLanguage) -> String {
+    if !language.prefers_no_space() {
+        return cjk_terms(text, false).0;
+    }
+    // Spaces between CJK characters in these languages are OCR noise and would split words
+    // apart. Latin words mixed in keep theirs.
+    let mut joined = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() && joined.chars().next_back().is_some_and(is_cjk) {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.peek().is_some_and(|&next| is_cjk(next)) {
+                continue;
+            }
+        }
+        joined.push(c);
+    }
+    cjk_terms(&joined, false).0
+}
+
+/// FTS5 query for what the user typed: each space-separated word becomes a phrase, and a line
+/// has to contain all of them. `None` if nothing in it can be searched for.
+fn match_query(query: &str) -> Option<String> {
+    let phrases: Vec<String> = query
+        .split_whitespace()
+        .filter_map(|word| {
+            let (terms, prefix) = cjk_terms(word, true);
+            if !terms.chars().any(char::is_alphanumeric) {
+                return None;
+            }
+            let phrase = format!("\"{}\"", terms.trim().replace('"', "\"\""));
+            Some(if prefix { phrase + "*" } else { phrase })
+        })
+        .collect();
+    (!phrases.is_empty()).then(|| phrases.join(" "))
+}
+
+fn page_language(cache_key: &str) -> OcrLanguage {
+    logic::cache_key_language(cache_key)
+        .and_then(OcrLanguage::from_name)
+        .unwrap_or_default()
+}
+
+/// Replaces the indexed lines of `cache_key` with `results`, after the page's corrections.
+pub(crate) fn index_page(
+    conn: &Connection,
+    cache_key: &str,
+    results: &[OcrResult],
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM ocr_text_lines WHERE cache_key = ?",
+        params![cache_key],
+    )?;
+    let mut results = results.to_vec();
+    if let Some(page) = corrections::stored_corrections(conn, cache_key) {
+        apply_corrections(&mut results, &page.edits);
+    }
+    let language = page_language(cache_key);
+    let series = logic::cache_key_series(cache_key);
+    let chapter = logic::chapter_location(cache_key).map(|(_, chapter)| chapter);
+    let mut insert_line = conn.prepare_cached(
+        "INSERT INTO ocr_text_lines
+            (cache_key, line, series, chapter, language, text, bounding_box)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )?;
+    let mut insert_terms =
+        conn.prepare_cached("INSERT INTO ocr_text_fts (rowid, terms) VALUES (?, ?)")?;
+    for (line, result) in results.iter().enumerate() {
+        let terms = index_terms(&result.text, language);
+        if !terms.chars().any(char::is_alphanumeric) {
+            continue;
+        }
+        let bounding_box = serde_json::to_string(&result.tight_bounding_box).unwrap_or_default();
+        let id = insert_line.insert(params![
+            cache_key,
+            line as i64,
+            series,
+            chapter,
+            language.as_str(),
+            result.text,
+            bounding_box
+        ])?;
+        insert_terms.execute(params![id, terms])?;
+    }
+    Ok(())
+}
+
+/// Re-indexes `cache_key` from the results stored in `ocr_cache`.
+pub(crate) fn reindex_page(conn: &Connection, cache_key: &str) -> rusqlite::Result<()> {
+    let data_blob = conn
+        .query_row(
+            "SELECT data FROM ocr_cache WHERE cache_key = ?",
+            params![cache_key],
+            |row| row.get::<_, Vec<u8>>(0),
+        )
+        .optional()?;
+    let results: Vec<OcrResult> = data_blob
+        .and_then(|blob| serde_json::from_slice(&blob).ok())
+        .unwrap_or_default();
+    index_page(conn, cache_key, &results)
+}
+
+/// Re-indexes every cached version of the page whose corrections are stored under
+/// `corrections_key`, one per merge setting.
+pub(crate) fn reindex_corrected(conn: &Connection, corrections_key: &str) -> rusqlite::Result<()> {
+    // Merge-suffixed keys sort between `<key>#merge=` and `<key>#merge>`.
+    let cache_keys: Vec<String> = conn
+        .prepare_cached(
+            "SELECT cache_key FROM ocr_cache
+             WHERE cache_key = ?1 OR (cache_key >= ?2 AND cache_key < ?3)",
+        )?
+        .query_map(
+            params![
+                corrections_key,
+                format!("{corrections_key}#merge="),
+                format!("{corrections_key}#merge>")
+            ],
+            |row| row.get(0),
+        )?
+        .collect::<rusqlite::Result<_>>()?;
+    for cache_key in cache_keys {
+        reindex_page(conn, &cache_key)?;
+    }
+    Ok(())
+}
+
+/// Logs an index update of `key` that failed and marks the index stale, so the next start
+/// rebuilds it.
+pub(crate) fn index_failed(conn: &Connection, key: &str, err: &rusqlite::Error) {
+    warn!("Failed to update the search index for {key}: {err}");
+    let _ = conn.execute(
+        "DELETE FROM metadata WHERE key = 'search_index_version'",
+        [],
+    );
+}
+
+impl AppState {
+    /// Cached lines matching `query`, best matches first.
+    pub fn search_text(
+        &self,
+        query: &str,
+        filter: &SearchFilter,
+        limit: usize,
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let Some(fts_query) = match_query(query) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT l.cache_key, l.line, l.text, l.bounding_box, l.series, l.chapter, l.language
+             FROM ocr_text_fts f
+             JOIN ocr_text_lines l ON l.id = f.rowid
+             WHERE ocr_text_fts MATCH ?1
+               AND (?2 IS NULL OR l.series = ?2)
+               AND (?3 IS NULL OR l.chapter = ?3)
+               AND (?4 IS NULL OR l.language = ?4)
+             ORDER BY f.rank
+             LIMIT ?5",
+        )?;
+        let hits = stmt
+            .query_map(
+                params![
+                    fts_query,
+                    filter.series,
+                    filter.chapter,
+                    filter.language.map(|language| language.as_str()),
+                    limit.min(MAX_SEARCH_LIMIT) as i64
+                ],
+                |row| {
+                    let bounding_box: String = row.get(3)?;
+                    Ok(SearchHit {
+                        cache_key: row.get(0)?,
+                        line: row.get::<_, i64>(1)? as usize,
+                        text: row.get(2)?,
+                        bounding_box: serde_json::from_str(&bounding_box).unwrap_or_default(),
+                        series: row.get(4)?,
+                        chapter: row.get(5)?,
+                        language: row.get(6)?,
+                    })
+                },
+            )?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(hits)
+    }
+
+    /// Indexes the whole cache when the index predates [`SEARCH_INDEX_VERSION`], e.g. on the
+    /// first start after search was added.
+    pub fn rebuild_search_index_if_stale(&self) {
+        let Ok(mut conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for rebuild_search_index_if_stale");
+            return;
+        };
+        let version = conn
+            .query_row(
+                "SELECT value FROM metadata WHERE key = 'search_index_version'",
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .unwrap_or(None);
+        if version.as_deref() == Some(SEARCH_INDEX_VERSION) {
+            return;
+        }
+        let cache_keys: Vec<String> = match conn.prepare("SELECT cache_key FROM ocr_cache") {
+            Ok(mut stmt) => stmt
+                .query_map([], |row| row.get(0))
+                .map(|rows| rows.flatten().collect())
+                .unwrap_or_default(),
+            Err(err) => {
+                warn!("Failed to list cached pages for the search index: {err}");
+                return;
+            }
+        };
+
+        info!("Building the search index for {} pages", cache_keys.len());
+        for batch in cache_keys.chunks(REBUILD_BATCH_SIZE) {
+            let indexed = conn.transaction().and_then(|tx| {
+                for cache_key in batch {
+                    reindex_page(&tx, cache_key)?;
+                }
+                tx.commit()
+            });
+            if let Err(err) = indexed {
+                warn!("Failed to build the search index: {err}");
+                return;
+            }
+        }
+        let _ = conn.execute(
+            "INSERT OR REPLACE INTO metadata (key, value) VALUES ('search_index_version', ?)",
+            params![SEARCH_INDEX_VERSION],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corrections::{Correction, CorrectionOp};
+
+    fn line(text: &str) -> OcrResult {
+        OcrResult {
+            text: text.to_string(),
+            tight_bounding_box: BoundingBox::default(),
+            is_merged: None,
+            forced_orientation: None,
+            bubble_id: None,
+            reading_order: None,
+            ruby: Vec::new(),
+            words: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn cjk_runs_become_bigrams() {
+        assert_eq!(
+            cjk_terms("日本語", false),
+            (" 日本 本語 語 ".to_string(), false)
+        );
+        assert_eq!(
+            cjk_terms("abc日本def", false),
+            ("abc 日本 本 def".to_string(), false)
+        );
+        assert_eq!(cjk_terms("hello", false), ("hello".to_string(), false));
+    }
+
+    #[test]
+    fn query_run_at_the_end_stays_open() {
+        // The indexed run may go on past the query, so its last character is not a term.
+        assert_eq!(
+            cjk_terms("日本語", true),
+            (" 日本 本語 ".to_string(), false)
+        );
+        assert_eq!(cjk_terms("日本語で", true).0, " 日本 本語 語で ");
+        assert_eq!(cjk_terms("日", true), (" 日 ".to_string(), true));
+        assert_eq!(cjk_terms("日x", true), (" 日 x".to_string(), false));
+    }
+
+    #[test]
+    fn match_query_needs_every_word() {
+        assert_eq!(match_query("日本語").as_deref(), Some("\"日本 本語\""));
+        assert_eq!(match_query("日").as_deref(), Some("\"日\"*"));
+        assert_eq!(
+            match_query("hello  世界").as_deref(),
+            Some("\"hello\" \"世界\"")
+        );
+        assert_eq!(match_query("say\"hi").as_deref(), Some("\"say\"\"hi\""));
+    }
+
+    #[test]
+    fn match_query_ignores_punctuation_only_words() {
+        assert_eq!(match_query(""), None);
+        assert_eq!(match_query("「」 …"), None);
+        assert_eq!(match_query("「」 猫").as_deref(), Some("\"猫\"*"));
+    }
+
+    #[test]
+    fn index_drops_ocr_spaces_between_cjk() {
+        assert_eq!(
+            index_terms("日本 語 OCR", OcrLanguage::Japanese),
+            " 日本 本語 語  OCR"
+        );
+    }
+
+    #[test]
+    fn pages_are_indexed_with_their_corrections() {
+        let conn = Connection::open_in_memory().expect("in-memory database");
+        conn.execute_batch(
+            "CREATE TABLE ocr_corrections (
+                cache_key TEXT PRIMARY KEY,
+                edits TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+             );
+             CREATE VIRTUAL TABLE ocr_text_fts USING fts5(terms);
+             CREATE TABLE ocr_text_lines (
+                id INTEGER PRIMARY KEY,
+                cache_key TEXT NOT NULL,
+                line INTEGER NOT NULL,
+                series TEXT,
+                chapter TEXT,
+                language TEXT NOT NULL,
+                text TEXT NOT NULL,
+                bounding_box TEXT NOT NULL
+             );",
+        )
+        .expect("schema");
+        let edits = vec![
+            Correction {
+                index: 0,
+                original: Some("ノイズ".to_string()),
+                op: CorrectionOp::Delete,
+            },
+            Correction {
+                index: 0,
+                original: Some("猫が好ぎ".to_string()),
+                op: CorrectionOp::EditText {
+                    text: "猫が好き".to_string(),
+                },
+            },
+        ];
+        conn.execute(
+            "INSERT INTO ocr_corrections (cache_key, edits, updated_at) VALUES (?, ?, 0)",
+            params!["page", serde_json::to_string(&edits).expect("edits")],
+        )
+        .expect("corrections");
+
+        index_page(&conn, "page#merge=off", &[line("ノイズ"), line("猫が好ぎ")]).expect("index");
+
+        let hits = |query: &str| -> Vec<(i64, String)> {
+            conn.prepare(
+                "SELECT l.line, l.text FROM ocr_text_fts f
+                 JOIN ocr_text_lines l ON l.id = f.rowid
+                 WHERE ocr_text_fts MATCH ?",
+            )
+            .expect("query")
+            .query_map(params![match_query(query)], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .expect("rows")
+            .collect::<rusqlite::Result<_>>()
+            .expect("hits")
+        };
+        assert_eq!(hits("好き"), vec![(0, "猫が好き".to_string())]);
+        assert!(hits("好ぎ").is_empty());
+        assert!(hits("ノイズ").is_empty());
+    }
+}
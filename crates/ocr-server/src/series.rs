@@ -0,0 +1,610 @@
+//! Preprocessing of a whole series, or a range of its chapters, as one job.
+//!
+//! The chapter list comes from Suwayomi when the job is created. A worker then resolves the
+//! pages of one chapter at a time and queues it as an ordinary chapter job, so the chapters
+//! share the job queue and the OCR scheduler with everything else. Each chapter's outcome is
+//! recorded against the series, which gives the series its progress and status.
+
+use rusqlite::{OptionalExtension, params};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    engine::EngineContext,
+    language::OcrLanguage,
+    logic,
+    merge::MergeConfig,
+    scheduler::OcrPriority,
+    state::{AppState, JobRecord, JobStatus, now_unix},
+};
+
+/// A series preprocess job as stored in `ocr_series_jobs`. The id is the series URL's cache
+/// key, so the same series with other settings is a separate job. The credentials live in
+/// [`AppState::series_credentials`], not in the row.
+#[derive(Serialize, Clone, Debug)]
+pub struct SeriesJob {
+    pub series_id: String,
+    pub manga_id: String,
+    pub context: String,
+    pub language: OcrLanguage,
+    pub engine: Option<String>,
+    pub merge_config: MergeConfig,
+    #[serde(skip)]
+    pub user: Option<String>,
+    #[serde(skip)]
+    pub pass: Option<String>,
+    /// Queue priority the chapter jobs get.
+    pub priority: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeriesChapterStatus {
+    /// Pages not looked up yet.
+    Pending,
+    /// Pages being looked up right now.
+    Resolving,
+    /// Handed to the job queue.
+    Queued,
+    Done,
+    Failed,
+    /// Nothing to do: no pages, or every page was already cached.
+    Skipped,
+    Cancelled,
+}
+
+impl SeriesChapterStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SeriesChapterStatus::Pending => "pending",
+            SeriesChapterStatus::Resolving => "resolving",
+            SeriesChapterStatus::Queued => "queued",
+            SeriesChapterStatus::Done => "done",
+            SeriesChapterStatus::Failed => "failed",
+            SeriesChapterStatus::Skipped => "skipped",
+            SeriesChapterStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "resolving" => SeriesChapterStatus::Resolving,
+            "queued" => SeriesChapterStatus::Queued,
+            "done" => SeriesChapterStatus::Done,
+            "failed" => SeriesChapterStatus::Failed,
+            "skipped" => SeriesChapterStatus::Skipped,
+            "cancelled" => SeriesChapterStatus::Cancelled,
+            _ => SeriesChapterStatus::Pending,
+        }
+    }
+
+    fn is_finished(self) -> bool {
+        !matches!(
+            self,
+            SeriesChapterStatus::Pending
+                | SeriesChapterStatus::Resolving
+                | SeriesChapterStatus::Queued
+        )
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SeriesChapter {
+    pub chapter_index: u32,
+    pub base_url: String,
+    pub job_id: Option<String>,
+    pub status: SeriesChapterStatus,
+    /// Page count, once known.
+    pub pages: Option<usize>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeriesStatus {
+    /// Started with Suwayomi credentials that were lost in a restart; waits for a request
+    /// for the same server to bring them.
+    #[serde(rename = "needs_credentials")]
+    NeedsCredentials,
+    /// Some chapters still wait for their pages to be looked up.
+    Resolving,
+    /// Every chapter is known; some are still being OCR'd.
+    Running,
+    Done,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default)]
+pub struct SeriesChapterCounts {
+    pub total: usize,
+    pub pending: usize,
+    pub queued: usize,
+    pub done: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub cancelled: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SeriesJobSummary {
+    #[serde(flatten)]
+    pub job: SeriesJob,
+    pub status: SeriesStatus,
+    pub chapters: SeriesChapterCounts,
+    pub pages_done: usize,
+    /// Pages of the chapters looked up so far.
+    pub pages_total: usize,
+    /// Share of the whole series that is finished, from `0.0` to `1.0`. Chapters being OCR'd
+    /// count in proportion to their finished pages.
+    pub progress: f64,
+}
+
+fn row_to_series_job(row: &rusqlite::Row<'_>) -> rusqlite::Result<SeriesJob> {
+    let language: String = row.get(3)?;
+    let merge_config: String = row.get(5)?;
+    Ok(SeriesJob {
+        series_id: row.get(0)?,
+        manga_id: row.get(1)?,
+        context: row.get(2)?,
+        language: serde_json::from_value(serde_json::Value::String(language)).unwrap_or_default(),
+        engine: row.get(4)?,
+        merge_config: serde_json::from_str(&merge_config).unwrap_or_default(),
+        user: None,
+        pass: None,
+        priority: row.get(6)?,
+        created_at: row.get(7)?,
+        updated_at: row.get(8)?,
+    })
+}
+
+const SERIES_JOB_COLUMNS: &str = "series_id, manga_id, context, language, engine, merge_config,
+     priority, created_at, updated_at";
+
+impl AppState {
+    fn with_series_credentials(&self, mut job: SeriesJob) -> SeriesJob {
+        let credentials = self.series_credentials.get(&job.series_id);
+        job.user = credentials.user;
+        job.pass = credentials.pass;
+        job
+    }
+
+    /// Stores a series job with its chapters, all pending, replacing any previous job for the
+    /// same series.
+    pub fn create_series_job(&self, job: &SeriesJob, chapters: &[(u32, String)]) -> bool {
+        let Ok(mut conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for create_series_job");
+            return false;
+        };
+        let tx = match conn.transaction() {
+            Ok(tx) => tx,
+            Err(err) => {
+                warn!("Failed to start series job transaction: {err}");
+                return false;
+            }
+        };
+        let _ = tx.execute(
+            "DELETE FROM ocr_series_chapters WHERE series_id = ?",
+            params![job.series_id],
+        );
+        let _ = tx.execute(
+            "INSERT OR REPLACE INTO ocr_series_jobs
+                (series_id, manga_id, context, language, engine, merge_config,
+                 priority, needs_credentials, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                job.series_id,
+                job.manga_id,
+                job.context,
+                job.language.as_str(),
+                job.engine,
+                serde_json::to_string(&job.merge_config).unwrap_or_default(),
+                job.priority,
+                job.user.is_some() || job.pass.is_some(),
+                job.created_at,
+                job.updated_at
+            ],
+        );
+        for (chapter_index, base_url) in chapters {
+            let _ = tx.execute(
+                "INSERT INTO ocr_series_chapters (series_id, chapter_index, base_url, status)
+                 VALUES (?, ?, ?, ?)",
+                params![
+                    job.series_id,
+                    chapter_index,
+                    base_url,
+                    SeriesChapterStatus::Pending.as_str()
+                ],
+            );
+        }
+        if let Err(err) = tx.commit() {
+            warn!("Failed to commit series job {}: {err}", job.series_id);
+            return false;
+        }
+        self.series_credentials.set(
+            &job.series_id,
+            EngineContext {
+                user: job.user.clone(),
+                pass: job.pass.clone(),
+            },
+        );
+        true
+    }
+
+    pub fn get_series_job(&self, series_id: &str) -> Option<SeriesJob> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for get_series_job");
+            return None;
+        };
+        conn.query_row(
+            &format!("SELECT {SERIES_JOB_COLUMNS} FROM ocr_series_jobs WHERE series_id = ?"),
+            params![series_id],
+            row_to_series_job,
+        )
+        .optional()
+        .unwrap_or(None)
+        .map(|job| self.with_series_credentials(job))
+    }
+
+    /// Whether the series job was started with credentials this server no longer has.
+    fn series_waits_for_credentials(&self, series_id: &str) -> bool {
+        if self.series_credentials.contains(series_id) {
+            return false;
+        }
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for series_waits_for_credentials");
+            return false;
+        };
+        conn.query_row(
+            "SELECT needs_credentials FROM ocr_series_jobs WHERE series_id = ?",
+            params![series_id],
+            |row| row.get::<_, bool>(0),
+        )
+        .unwrap_or(false)
+    }
+
+    /// Starts the series jobs on the server `url` points at that wait for credentials, with
+    /// `credentials`.
+    pub(crate) fn resume_series_with_credentials(&self, url: &str, credentials: &EngineContext) {
+        for series in self.list_series_jobs() {
+            if !self.series_waits_for_credentials(&series.series_id) {
+                continue;
+            }
+            let on_server = self
+                .series_chapters(&series.series_id)
+                .first()
+                .is_some_and(|chapter| logic::same_server(&chapter.base_url, url));
+            if on_server
+                && self
+                    .series_credentials
+                    .set_if_missing(&series.series_id, credentials)
+            {
+                info!(
+                    "Resuming series job {} with the credentials of a new request",
+                    series.series_id
+                );
+                tokio::spawn(run_series_job(self.clone(), series.series_id));
+            }
+        }
+    }
+
+    pub fn list_series_jobs(&self) -> Vec<SeriesJob> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for list_series_jobs");
+            return Vec::new();
+        };
+        let Ok(mut stmt) = conn.prepare(&format!(
+            "SELECT {SERIES_JOB_COLUMNS} FROM ocr_series_jobs ORDER BY created_at ASC"
+        )) else {
+            return Vec::new();
+        };
+        stmt.query_map([], row_to_series_job)
+            .map(|rows| {
+                rows.flatten()
+                    .map(|job| self.with_series_credentials(job))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn series_chapters(&self, series_id: &str) -> Vec<SeriesChapter> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for series_chapters");
+            return Vec::new();
+        };
+        let Ok(mut stmt) = conn.prepare(
+            "SELECT chapter_index, base_url, job_id, status, pages, error
+             FROM ocr_series_chapters WHERE series_id = ? ORDER BY chapter_index",
+        ) else {
+            return Vec::new();
+        };
+        stmt.query_map(params![series_id], |row| {
+            let status: String = row.get(3)?;
+            Ok(SeriesChapter {
+                chapter_index: row.get(0)?,
+                base_url: row.get(1)?,
+                job_id: row.get(2)?,
+                status: SeriesChapterStatus::parse(&status),
+                pages: row.get::<_, Option<i64>>(4)?.map(|pages| pages as usize),
+                error: row.get(5)?,
+            })
+        })
+        .map(|rows| rows.flatten().collect())
+        .unwrap_or_default()
+    }
+
+    fn set_series_chapter(&self, series_id: &str, chapter: &SeriesChapter) {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for set_series_chapter");
+            return;
+        };
+        let _ = conn.execute(
+            "UPDATE ocr_series_chapters SET job_id = ?, status = ?, pages = ?, error = ?
+             WHERE series_id = ? AND chapter_index = ?",
+            params![
+                chapter.job_id,
+                chapter.status.as_str(),
+                chapter.pages.map(|pages| pages as i64),
+                chapter.error,
+                series_id,
+                chapter.chapter_index
+            ],
+        );
+    }
+
+    /// Takes the next pending chapter of a series for page lookup. `None` once there are none
+    /// left or the series job is gone.
+    fn claim_series_chapter(&self, series_id: &str) -> Option<SeriesChapter> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for claim_series_chapter");
+            return None;
+        };
+        loop {
+            let (chapter_index, base_url): (u32, String) = conn
+                .query_row(
+                    "SELECT chapter_index, base_url FROM ocr_series_chapters
+                     WHERE series_id = ? AND status = 'pending'
+                     ORDER BY chapter_index LIMIT 1",
+                    params![series_id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()
+                .ok()??;
+            let claimed = conn
+                .execute(
+                    "UPDATE ocr_series_chapters SET status = 'resolving'
+                     WHERE series_id = ? AND chapter_index = ? AND status = 'pending'",
+                    params![series_id, chapter_index],
+                )
+                .unwrap_or(0);
+            if claimed > 0 {
+                return Some(SeriesChapter {
+                    chapter_index,
+                    base_url,
+                    job_id: None,
+                    status: SeriesChapterStatus::Resolving,
+                    pages: None,
+                    error: None,
+                });
+            }
+        }
+    }
+
+    /// Records the outcome of a chapter job against the series it belongs to, if any.
+    pub fn finish_series_chapter(&self, job_id: &str, status: SeriesChapterStatus) {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for finish_series_chapter");
+            return;
+        };
+        let _ = conn.execute(
+            "UPDATE ocr_series_chapters SET status = ?
+             WHERE job_id = ? AND status IN ('queued', 'failed')",
+            params![status.as_str(), job_id],
+        );
+    }
+
+    pub fn series_summary(&self, job: SeriesJob) -> SeriesJobSummary {
+        let mut chapters = SeriesChapterCounts::default();
+        let (mut pages_done, mut pages_total) = (0, 0);
+        let mut finished = 0.0;
+        for chapter in self.series_chapters(&job.series_id) {
+            chapters.total += 1;
+            let pages = chapter.pages.unwrap_or_default();
+            pages_total += pages;
+            match chapter.status {
+                SeriesChapterStatus::Pending | SeriesChapterStatus::Resolving => {
+                    chapters.pending += 1;
+                }
+                SeriesChapterStatus::Queued => {
+                    chapters.queued += 1;
+                    let done = chapter
+                        .job_id
+                        .as_deref()
+                        .map(|job_id| self.count_job_pages(job_id).done)
+                        .unwrap_or_default()
+                        .min(pages);
+                    pages_done += done;
+                    if pages > 0 {
+                        finished += done as f64 / pages as f64;
+                    }
+                }
+                SeriesChapterStatus::Done => chapters.done += 1,
+                SeriesChapterStatus::Failed => chapters.failed += 1,
+                SeriesChapterStatus::Skipped => chapters.skipped += 1,
+                SeriesChapterStatus::Cancelled => chapters.cancelled += 1,
+            }
+            if matches!(
+                chapter.status,
+                SeriesChapterStatus::Done | SeriesChapterStatus::Skipped
+            ) {
+                pages_done += pages;
+            }
+            if chapter.status.is_finished() {
+                finished += 1.0;
+            }
+        }
+
+        let status = if chapters.pending + chapters.queued > 0
+            && self.series_waits_for_credentials(&job.series_id)
+        {
+            SeriesStatus::NeedsCredentials
+        } else if chapters.pending > 0 {
+            SeriesStatus::Resolving
+        } else if chapters.queued > 0 {
+            SeriesStatus::Running
+        } else {
+            SeriesStatus::Done
+        };
+        let progress = if chapters.total == 0 {
+            1.0
+        } else {
+            finished / chapters.total as f64
+        };
+        SeriesJobSummary {
+            job,
+            status,
+            chapters,
+            pages_done,
+            pages_total,
+            progress,
+        }
+    }
+
+    /// Cancels the chapters still waiting or being OCR'd and forgets the series job.
+    pub fn cancel_series_job(&self, series_id: &str) -> bool {
+        if self.get_series_job(series_id).is_none() {
+            return false;
+        }
+        for chapter in self.series_chapters(series_id) {
+            if chapter.status == SeriesChapterStatus::Queued
+                && let Some(job_id) = chapter.job_id.as_deref()
+            {
+                self.cancel_job(job_id);
+            }
+        }
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for cancel_series_job");
+            return false;
+        };
+        let _ = conn.execute(
+            "DELETE FROM ocr_series_chapters WHERE series_id = ?",
+            params![series_id],
+        );
+        let _ = conn.execute(
+            "DELETE FROM ocr_series_jobs WHERE series_id = ?",
+            params![series_id],
+        );
+        self.series_credentials.remove(series_id);
+        info!("Cancelled series job {series_id}");
+        true
+    }
+}
+
+/// Looks up the pages of a series job's chapters one at a time and queues each as a chapter
+/// job. Stops early when the series job is cancelled.
+pub async fn run_series_job(state: AppState, series_id: String) {
+    while let Some(mut chapter) = state.claim_series_chapter(&series_id) {
+        let Some(series) = state.get_series_job(&series_id) else {
+            return;
+        };
+        let job_id = logic::get_merge_cache_key(&chapter.base_url, &series.merge_config);
+
+        // A chapter already in the queue, e.g. prefetched, is followed rather than replaced.
+        // One that was paused, failed or cancelled would never finish, so it is queued again.
+        if state
+            .get_job(&job_id)
+            .is_some_and(|job| matches!(job.status, JobStatus::Queued | JobStatus::Running))
+        {
+            chapter.job_id = Some(job_id);
+            chapter.status = SeriesChapterStatus::Queued;
+            state.set_series_chapter(&series_id, &chapter);
+            continue;
+        }
+
+        let pages = match logic::with_retries(&chapter.base_url, || {
+            logic::resolve_chapter_pages(
+                &chapter.base_url,
+                series.user.clone(),
+                series.pass.clone(),
+            )
+        })
+        .await
+        {
+            Ok(pages) => pages,
+            Err(err) => {
+                warn!(
+                    "[Series {series_id}] Failed to look up chapter {}: {err:#}",
+                    chapter.chapter_index
+                );
+                chapter.status = SeriesChapterStatus::Failed;
+                chapter.error = Some(format!("{err:#}"));
+                state.set_series_chapter(&series_id, &chapter);
+                continue;
+            }
+        };
+        chapter.pages = Some(pages.len());
+
+        let engine = state.engine_id(series.engine.as_deref());
+        let all_cached = pages.iter().all(|page| {
+            state.has_cache_entry_from(
+                &logic::get_merge_cache_key(page, &series.merge_config),
+                Some(&engine),
+            )
+        });
+        if all_cached {
+            chapter.status = SeriesChapterStatus::Skipped;
+            state.set_series_chapter(&series_id, &chapter);
+            continue;
+        }
+
+        if state.get_series_job(&series_id).is_none() {
+            return;
+        }
+        // Recorded before queueing so a job that finishes at once still finds its chapter.
+        chapter.job_id = Some(job_id.clone());
+        chapter.status = SeriesChapterStatus::Queued;
+        state.set_series_chapter(&series_id, &chapter);
+        let now = now_unix();
+        state.enqueue_job(
+            &JobRecord {
+                job_id,
+                base_url: chapter.base_url.clone(),
+                context: series.context.clone(),
+                language: series.language,
+                engine: series.engine.clone(),
+                merge_config: series.merge_config.clone(),
+                user: series.user.clone(),
+                pass: series.pass.clone(),
+                status: JobStatus::Queued,
+                priority: series.priority,
+                ocr_priority: OcrPriority::Bulk,
+                created_at: now,
+                updated_at: now,
+            },
+            &pages,
+        );
+    }
+}
+
+/// Picks up series jobs that still had chapters to look up when the server stopped. Chapters
+/// whose pages could not be looked up get another try. Jobs started with credentials wait
+/// until a request brings them again.
+pub async fn resume_series_jobs(state: AppState) {
+    if let Ok(conn) = state.pool.get() {
+        let _ = conn.execute(
+            "UPDATE ocr_series_chapters SET status = 'pending', error = NULL
+             WHERE status = 'resolving' OR (status = 'failed' AND job_id IS NULL)",
+            [],
+        );
+    }
+    for series in state.list_series_jobs() {
+        if state.series_waits_for_credentials(&series.series_id) {
+            info!(
+                "Series job {} waits for its Suwayomi credentials",
+                series.series_id
+            );
+            continue;
+        }
+        tokio::spawn(run_series_job(state.clone(), series.series_id));
+    }
+}
@@ -0,0 +1,46 @@
+//! Page images that don't come from Suwayomi: uploads and files under the configured library
+//! roots.
+//!
+//! Such images have no stable URL, so they are cached under a key derived from a hash of their
+//! content. The same screenshot uploaded twice, or a scan read from two paths, is only OCR'd
+//! once.
+
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::state::AppState;
+
+/// Prefix of the pseudo-URL content-addressed pages are cached under.
+pub const CONTENT_KEY_PREFIX: &str = "content/";
+
+/// Hex SHA-256 of an image.
+pub fn content_hash(image_bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(image_bytes))
+}
+
+/// Pseudo-URL standing in for a page URL when caching an image by its content.
+pub fn content_url(hash: &str) -> String {
+    format!("{CONTENT_KEY_PREFIX}{hash}")
+}
+
+impl AppState {
+    /// Resolves `path` and checks that it is a file inside one of the library roots from the
+    /// config file. Symlinks and `..` are resolved first, so neither can lead outside a root.
+    /// Every refusal gives the same error, so requests can't probe what exists outside the
+    /// roots.
+    pub fn resolve_library_path(&self, path: &str) -> Result<PathBuf, String> {
+        let roots = &self.file_config.library_roots;
+        if roots.is_empty() {
+            return Err("No library roots are configured for local files".to_string());
+        }
+        let allowed = Path::new(path).canonicalize().ok().filter(|resolved| {
+            resolved.is_file()
+                && roots.iter().any(|root| {
+                    root.canonicalize()
+                        .is_ok_and(|root| resolved.starts_with(root))
+                })
+        });
+        allowed.ok_or_else(|| format!("{path} was not found or is not allowed"))
+    }
+}
@@ -1,17 +1,32 @@
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
-    sync::{atomic::AtomicUsize, Arc, RwLock},
+    sync::{Arc, RwLock, atomic::AtomicUsize},
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, OptionalExtension};
+use rusqlite::{OptionalExtension, params};
 use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, Semaphore, broadcast};
 use tracing::{info, warn};
 
-use crate::logic::OcrResult;
+use crate::{
+    config::{FileConfig, OcrConfig},
+    content,
+    engine::{EngineContext, EngineRegistry, LENS_ENGINE_ID, OcrEngine},
+    events::{self, JobEvent},
+    inflight::InFlight,
+    language::OcrLanguage,
+    logic::{self, OcrPage, OcrResult},
+    merge::{MERGE_VERSION, MergeConfig},
+    prefetch::PrefetchClaims,
+    scheduler::{OcrPriority, Scheduler},
+    search,
+    series::SeriesChapterStatus,
+    throttle::EngineGuards,
+};
 
 #[derive(Clone, Copy, Serialize, Debug)]
 pub struct JobProgress {
@@ -26,6 +41,73 @@ pub struct AppState {
     pub active_jobs: Arc<AtomicUsize>,
     pub requests_processed: Arc<AtomicUsize>,
     pub active_chapter_jobs: Arc<RwLock<HashMap<String, JobProgress>>>,
+    pub engines: EngineRegistry,
+    pub config: Arc<RwLock<OcrConfig>>,
+    /// Settings from the config file, fixed for the life of the server.
+    pub file_config: Arc<FileConfig>,
+    pub job_notify: Arc<Notify>,
+    pub events: broadcast::Sender<JobEvent>,
+    /// Pages being OCR'd right now, shared by readers and preprocess jobs.
+    pub in_flight: InFlight,
+    /// Shared budget of concurrent OCR runs.
+    pub scheduler: Scheduler,
+    /// Rate limits and circuit breakers of the OCR engines.
+    pub engine_guards: EngineGuards,
+    /// Chapters the following ones were recently prefetched for.
+    pub prefetch_claims: PrefetchClaims,
+    /// Suwayomi credentials of queued jobs, by job id.
+    pub job_credentials: Credentials,
+    /// Suwayomi credentials of series jobs, by series id.
+    pub series_credentials: Credentials,
+    /// Slots for re-checking the images behind cache hits.
+    pub revalidations: Arc<Semaphore>,
+}
+
+/// Suwayomi credentials of background work, kept in memory only so they never reach the
+/// database. Work that had credentials waits after a restart until a request brings them
+/// again (see [`AppState::supply_credentials`]).
+#[derive(Clone, Default)]
+pub struct Credentials {
+    by_id: Arc<RwLock<HashMap<String, EngineContext>>>,
+}
+
+impl Credentials {
+    pub fn get(&self, id: &str) -> EngineContext {
+        self.by_id
+            .read()
+            .expect("lock poisoned")
+            .get(id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn set(&self, id: &str, credentials: EngineContext) {
+        let mut by_id = self.by_id.write().expect("lock poisoned");
+        if credentials.user.is_none() && credentials.pass.is_none() {
+            by_id.remove(id);
+        } else {
+            by_id.insert(id.to_string(), credentials);
+        }
+    }
+
+    /// Stores `credentials` for `id` unless it already has some. Returns whether they were
+    /// stored.
+    pub fn set_if_missing(&self, id: &str, credentials: &EngineContext) -> bool {
+        let mut by_id = self.by_id.write().expect("lock poisoned");
+        if by_id.contains_key(id) {
+            return false;
+        }
+        by_id.insert(id.to_string(), credentials.clone());
+        true
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.by_id.read().expect("lock poisoned").contains_key(id)
+    }
+
+    pub fn remove(&self, id: &str) {
+        self.by_id.write().expect("lock poisoned").remove(id);
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -95,7 +177,89 @@ impl AppState {
              );
 
              CREATE INDEX IF NOT EXISTS idx_chapter_pages_accessed
-                ON chapter_pages(last_accessed_at);",
+                ON chapter_pages(last_accessed_at);
+
+             CREATE TABLE IF NOT EXISTS ocr_jobs (
+                job_id TEXT PRIMARY KEY,
+                base_url TEXT NOT NULL,
+                context TEXT NOT NULL,
+                language TEXT NOT NULL,
+                engine TEXT,
+                add_space_on_merge INTEGER,
+                status TEXT NOT NULL,
+                priority INTEGER NOT NULL DEFAULT 0,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+             );
+
+             CREATE INDEX IF NOT EXISTS idx_ocr_jobs_queue
+                ON ocr_jobs(status, priority, created_at);
+
+             CREATE TABLE IF NOT EXISTS ocr_job_pages (
+                job_id TEXT NOT NULL,
+                page_index INTEGER NOT NULL,
+                url TEXT NOT NULL,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                error TEXT,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (job_id, page_index)
+             );
+
+             CREATE TABLE IF NOT EXISTS pinned_chapters (
+                chapter_key TEXT PRIMARY KEY,
+                created_at INTEGER NOT NULL
+             );
+
+             CREATE TABLE IF NOT EXISTS merge_profiles (
+                name TEXT PRIMARY KEY,
+                profile TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+             );
+
+             CREATE TABLE IF NOT EXISTS series_merge_profiles (
+                series TEXT PRIMARY KEY,
+                profile_name TEXT NOT NULL
+             );
+
+             CREATE TABLE IF NOT EXISTS ocr_corrections (
+                cache_key TEXT PRIMARY KEY,
+                edits TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+             );
+
+             CREATE TABLE IF NOT EXISTS ocr_series_jobs (
+                series_id TEXT PRIMARY KEY,
+                manga_id TEXT NOT NULL,
+                context TEXT NOT NULL,
+                language TEXT NOT NULL,
+                engine TEXT,
+                merge_config TEXT NOT NULL,
+                priority INTEGER NOT NULL DEFAULT 0,
+                needs_credentials INTEGER NOT NULL DEFAULT 0,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+             );
+
+             CREATE TABLE IF NOT EXISTS ocr_series_chapters (
+                series_id TEXT NOT NULL,
+                chapter_index INTEGER NOT NULL,
+                base_url TEXT NOT NULL,
+                job_id TEXT,
+                status TEXT NOT NULL,
+                pages INTEGER,
+                error TEXT,
+                PRIMARY KEY (series_id, chapter_index)
+             );
+
+             CREATE INDEX IF NOT EXISTS idx_ocr_series_chapters_job
+                ON ocr_series_chapters(job_id);
+
+             CREATE TABLE IF NOT EXISTS series_prefetch (
+                series TEXT PRIMARY KEY,
+                enabled INTEGER NOT NULL,
+                chapters INTEGER
+             );",
         )
         .expect("Failed to initialize OCR cache database");
 
@@ -103,17 +267,143 @@ impl AppState {
             "ALTER TABLE chapter_pages ADD COLUMN processed_count INTEGER NOT NULL DEFAULT 0",
             [],
         );
+        // Raw engine output kept next to the merged result so pages can be re-merged.
+        let _ = conn.execute("ALTER TABLE ocr_cache ADD COLUMN raw_chunks BLOB", []);
+        let _ = conn.execute(
+            "ALTER TABLE ocr_cache ADD COLUMN merge_version INTEGER NOT NULL DEFAULT 0",
+            [],
+        );
+        let _ = conn.execute("ALTER TABLE ocr_cache ADD COLUMN merge_config TEXT", []);
+        let _ = conn.execute("ALTER TABLE ocr_jobs ADD COLUMN merge_config TEXT", []);
+        // Language the engine was asked for; NULL for pages cached before it was honored.
+        let _ = conn.execute("ALTER TABLE ocr_cache ADD COLUMN ocr_language TEXT", []);
+        // Hash of the page image, for reusing results across URLs and noticing changed images.
+        let _ = conn.execute("ALTER TABLE ocr_cache ADD COLUMN content_hash TEXT", []);
+        let _ = conn.execute(
+            "ALTER TABLE ocr_cache ADD COLUMN hash_checked_at INTEGER",
+            [],
+        );
+        // Engine that recognized the page; NULL for pages cached before it was recorded, which
+        // all came from Google Lens.
+        let _ = conn.execute("ALTER TABLE ocr_cache ADD COLUMN engine TEXT", []);
+        // Scheduler class of a job's pages; NULL means bulk.
+        let _ = conn.execute("ALTER TABLE ocr_jobs ADD COLUMN ocr_priority TEXT", []);
+        // Whether the job was queued with Suwayomi credentials, which are never stored.
+        let _ = conn.execute(
+            "ALTER TABLE ocr_jobs ADD COLUMN needs_credentials INTEGER NOT NULL DEFAULT 0",
+            [],
+        );
+        let _ = conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_ocr_cache_content_hash ON ocr_cache(content_hash)",
+            [],
+        );
+        // Full-text index of the cached lines (see `search`). The virtual table comes first so
+        // nothing else is created when SQLite lacks FTS5.
+        if let Err(err) = conn.execute_batch(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS ocr_text_fts USING fts5(terms);
+
+             CREATE TABLE IF NOT EXISTS ocr_text_lines (
+                id INTEGER PRIMARY KEY,
+                cache_key TEXT NOT NULL,
+                line INTEGER NOT NULL,
+                series TEXT,
+                chapter TEXT,
+                language TEXT NOT NULL,
+                text TEXT NOT NULL,
+                bounding_box TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS idx_ocr_text_lines_key ON ocr_text_lines(cache_key);
+
+             CREATE TRIGGER IF NOT EXISTS ocr_text_lines_deleted
+             AFTER DELETE ON ocr_text_lines BEGIN
+                DELETE FROM ocr_text_fts WHERE rowid = old.id;
+             END;
+             CREATE TRIGGER IF NOT EXISTS ocr_cache_deleted_unindex
+             AFTER DELETE ON ocr_cache BEGIN
+                DELETE FROM ocr_text_lines WHERE cache_key = old.cache_key;
+             END;",
+        ) {
+            warn!("Full-text search is unavailable: {err}");
+        }
 
         migrate_legacy_cache(&mut conn, &cache_dir);
 
+        // Jobs that were running when the server stopped go back into the queue. Those that
+        // were queued with credentials wait for a request to bring them again.
+        let _ = conn.execute(
+            "UPDATE ocr_jobs SET status = 'needs_credentials'
+             WHERE status IN ('queued', 'running') AND needs_credentials = 1",
+            [],
+        );
+        let _ = conn.execute(
+            "UPDATE ocr_jobs SET status = 'queued' WHERE status = 'running'",
+            [],
+        );
+
+        let config = load_config(&conn);
+        let file_config = FileConfig::load(&cache_dir);
+        let engines = EngineRegistry::new(&cache_dir);
+
         Self {
             pool,
             cache_dir,
             active_jobs: Arc::new(AtomicUsize::new(0)),
             requests_processed: Arc::new(AtomicUsize::new(0)),
             active_chapter_jobs: Arc::new(RwLock::new(HashMap::new())),
+            engines,
+            scheduler: Scheduler::new(config.ocr_concurrency),
+            engine_guards: EngineGuards::new(config.engine_requests_per_minute),
+            prefetch_claims: PrefetchClaims::default(),
+            job_credentials: Credentials::default(),
+            series_credentials: Credentials::default(),
+            revalidations: Arc::new(Semaphore::new(content::MAX_REVALIDATIONS)),
+            config: Arc::new(RwLock::new(config)),
+            file_config: Arc::new(file_config),
+            job_notify: Arc::new(Notify::new()),
+            events: events::channel(),
+            in_flight: InFlight::default(),
+        }
+    }
+}
+
+impl AppState {
+    pub fn config(&self) -> OcrConfig {
+        self.config.read().expect("lock poisoned").clone()
+    }
+
+    pub fn set_config(&self, config: OcrConfig) {
+        if let Ok(conn) = self.pool.get() {
+            let value = serde_json::to_string(&config).unwrap_or_default();
+            let _ = conn.execute(
+                "INSERT OR REPLACE INTO metadata (key, value) VALUES ('config', ?)",
+                params![value],
+            );
+        } else {
+            warn!("Failed to get DB connection for set_config");
+        }
+        self.scheduler.set_limit(config.ocr_concurrency);
+        self.engine_guards
+            .set_rate(config.engine_requests_per_minute);
+        *self.config.write().expect("lock poisoned") = config;
+    }
+
+    /// Id of the engine named by a request, or of the configured default.
+    pub fn engine_id(&self, requested: Option<&str>) -> String {
+        match requested {
+            Some(id) if !id.is_empty() => id.to_string(),
+            _ => self.config().default_engine,
         }
     }
+
+    /// Looks up the engine named by a request, falling back to the configured default. The
+    /// engine comes wrapped in its shared rate limiter and circuit breaker.
+    pub fn resolve_engine(&self, requested: Option<&str>) -> Result<Arc<dyn OcrEngine>, String> {
+        let id = self.engine_id(requested);
+        self.engines
+            .get(&id)
+            .map(|engine| self.engine_guards.wrap(engine))
+            .ok_or_else(|| format!("Unknown OCR engine: {id}"))
+    }
 }
 
 impl AppState {
@@ -130,13 +420,20 @@ impl AppState {
     }
 
     pub fn has_cache_entry(&self, cache_key: &str) -> bool {
+        self.has_cache_entry_from(cache_key, None)
+    }
+
+    /// Whether `cache_key` is cached from `engine`; any engine will do with `None`.
+    pub fn has_cache_entry_from(&self, cache_key: &str, engine: Option<&str>) -> bool {
         let Ok(conn) = self.pool.get() else {
             warn!("Failed to get DB connection for has_cache_entry");
             return false;
         };
         conn.query_row(
-            "SELECT 1 FROM ocr_cache WHERE cache_key = ? LIMIT 1",
-            params![cache_key],
+            "SELECT 1 FROM ocr_cache
+             WHERE cache_key = ?1 AND (?2 IS NULL OR COALESCE(engine, ?3) = ?2)
+             LIMIT 1",
+            params![cache_key, engine, LENS_ENGINE_ID],
             |_| Ok(()),
         )
         .optional()
@@ -144,19 +441,32 @@ impl AppState {
         .unwrap_or(false)
     }
 
-    pub fn has_cache_entry_prefix(&self, prefix: &str) -> bool {
+    /// Whether the page at `url` is cached under a URL with a `sourceId` query appended,
+    /// merged with `merge_config`.
+    pub fn has_source_cache_entry(&self, url: &str, merge_config: &MergeConfig) -> bool {
         let Ok(conn) = self.pool.get() else {
-            warn!("Failed to get DB connection for has_cache_entry_prefix");
+            warn!("Failed to get DB connection for has_source_cache_entry");
             return false;
         };
-        let like_pattern = format!("{}%", prefix);
-        conn.query_row(
-            "SELECT 1 FROM ocr_cache WHERE cache_key LIKE ? LIMIT 1",
-            params![like_pattern],
-            |_| Ok(()),
+        let base_key = logic::get_cache_key(url, Some(merge_config.language));
+        let Ok(mut stmt) = conn.prepare(
+            "SELECT cache_key FROM ocr_cache WHERE cache_key LIKE ?1 OR cache_key LIKE ?2",
+        ) else {
+            return false;
+        };
+        stmt.query_map(
+            params![
+                format!("{base_key}?sourceId=%"),
+                format!("{base_key}&sourceId=%")
+            ],
+            |row| row.get::<_, String>(0),
         )
-        .optional()
-        .map(|v| v.is_some())
+        .map(|rows| {
+            rows.flatten().any(|cache_key| {
+                logic::with_merge_suffix(logic::strip_merge_suffix(&cache_key), merge_config)
+                    == cache_key
+            })
+        })
         .unwrap_or(false)
     }
 
@@ -187,6 +497,16 @@ impl AppState {
     }
 
     pub fn get_cache_entry(&self, cache_key: &str) -> Option<CacheEntry> {
+        self.get_cache_entry_from(cache_key, None)
+    }
+
+    /// The entry of `cache_key` if it was recognized by `engine`, so switching engines doesn't
+    /// serve the previous engine's text. Any engine will do with `None`.
+    pub fn get_cache_entry_from(
+        &self,
+        cache_key: &str,
+        engine: Option<&str>,
+    ) -> Option<CacheEntry> {
         let Ok(conn) = self.pool.get() else {
             warn!("Failed to get DB connection for get_cache_entry");
             return None;
@@ -194,8 +514,9 @@ impl AppState {
 
         let entry = conn
             .query_row(
-                "SELECT context, data FROM ocr_cache WHERE cache_key = ?",
-                params![cache_key],
+                "SELECT context, data FROM ocr_cache
+                 WHERE cache_key = ?1 AND (?2 IS NULL OR COALESCE(engine, ?3) = ?2)",
+                params![cache_key, engine, LENS_ENGINE_ID],
                 |row| {
                     let context: String = row.get(0)?;
                     let data_blob: Vec<u8> = row.get(1)?;
@@ -246,6 +567,60 @@ impl AppState {
                 1i64
             ],
         );
+        if let Err(err) = search::index_page(&conn, cache_key, &entry.data) {
+            search::index_failed(&conn, cache_key, &err);
+        }
+    }
+
+    /// Caches a freshly processed page along with its raw chunks and merge settings.
+    pub fn insert_ocr_page(&self, cache_key: &str, context: &str, page: &OcrPage) {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for insert_ocr_page");
+            return;
+        };
+        let now = now_unix();
+        let data_blob = serde_json::to_vec(&page.results).unwrap_or_default();
+        let raw_blob = serde_json::to_vec(&page.raw_chunks).unwrap_or_default();
+        let merge_config = serde_json::to_string(&page.merge_config).unwrap_or_default();
+        let _ = conn.execute(
+            "INSERT INTO ocr_cache
+                (cache_key, context, data, created_at, last_processed_at, last_accessed_at, access_count,
+                 raw_chunks, merge_version, merge_config, ocr_language, content_hash, hash_checked_at,
+                engine)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(cache_key) DO UPDATE SET
+                context = excluded.context,
+                data = excluded.data,
+                last_processed_at = excluded.last_processed_at,
+                last_accessed_at = excluded.last_accessed_at,
+                access_count = ocr_cache.access_count + 1,
+                raw_chunks = excluded.raw_chunks,
+                merge_version = excluded.merge_version,
+                merge_config = excluded.merge_config,
+                ocr_language = excluded.ocr_language,
+                content_hash = excluded.content_hash,
+                hash_checked_at = excluded.hash_checked_at,
+                engine = excluded.engine",
+            params![
+                cache_key,
+                context,
+                data_blob,
+                now,
+                now,
+                now,
+                1i64,
+                raw_blob,
+                MERGE_VERSION,
+                merge_config,
+                page.ocr_language.as_str(),
+                page.content_hash,
+                now,
+                page.engine
+            ],
+        );
+        if let Err(err) = search::index_page(&conn, cache_key, &page.results) {
+            search::index_failed(&conn, cache_key, &err);
+        }
     }
 
     pub fn clear_cache(&self) {
@@ -253,6 +628,8 @@ impl AppState {
             warn!("Failed to get DB connection for clear_cache");
             return;
         };
+        let _ = conn.execute("DELETE FROM ocr_text_fts", []);
+        let _ = conn.execute("DELETE FROM ocr_text_lines", []);
         let _ = conn.execute("DELETE FROM ocr_cache", []);
         let _ = conn.execute("DELETE FROM chapter_cache", []);
         let _ = conn.execute("DELETE FROM chapter_pages", []);
@@ -312,6 +689,9 @@ impl AppState {
             ) {
                 if changes > 0 {
                     added += 1;
+                    if let Err(err) = search::index_page(&tx, &key, &entry.data) {
+                        search::index_failed(&tx, &key, &err);
+                    }
                 }
             }
         }
@@ -413,7 +793,535 @@ impl AppState {
     }
 }
 
-fn now_unix() -> i64 {
+// --- Persistent Job Queue ---
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Paused,
+    Failed,
+    Cancelled,
+    /// Queued with Suwayomi credentials that were lost in a restart. Picked up again when a
+    /// request for the same server brings them.
+    #[serde(rename = "needs_credentials")]
+    NeedsCredentials,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Paused => "paused",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+            JobStatus::NeedsCredentials => "needs_credentials",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "running" => JobStatus::Running,
+            "paused" => JobStatus::Paused,
+            "failed" => JobStatus::Failed,
+            "cancelled" => JobStatus::Cancelled,
+            "needs_credentials" => JobStatus::NeedsCredentials,
+            _ => JobStatus::Queued,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageStatus {
+    Pending,
+    Done,
+    Failed,
+}
+
+impl PageStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PageStatus::Pending => "pending",
+            PageStatus::Done => "done",
+            PageStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "done" => PageStatus::Done,
+            "failed" => PageStatus::Failed,
+            _ => PageStatus::Pending,
+        }
+    }
+}
+
+/// A chapter preprocess job as stored in `ocr_jobs`. The job id is the chapter key. The
+/// credentials live in [`AppState::job_credentials`], not in the row.
+#[derive(Serialize, Clone, Debug)]
+pub struct JobRecord {
+    pub job_id: String,
+    pub base_url: String,
+    pub context: String,
+    pub language: OcrLanguage,
+    pub engine: Option<String>,
+    pub merge_config: MergeConfig,
+    #[serde(skip)]
+    pub user: Option<String>,
+    #[serde(skip)]
+    pub pass: Option<String>,
+    pub status: JobStatus,
+    pub priority: i64,
+    /// Scheduler class the job's pages are OCR'd with.
+    pub ocr_priority: OcrPriority,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct JobPage {
+    pub page_index: usize,
+    pub url: String,
+    pub status: PageStatus,
+    pub attempts: u32,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default)]
+pub struct JobPageCounts {
+    pub pending: usize,
+    pub done: usize,
+    pub failed: usize,
+}
+
+impl JobPageCounts {
+    pub fn total(&self) -> usize {
+        self.pending + self.done + self.failed
+    }
+}
+
+fn parse_language(value: &str) -> OcrLanguage {
+    serde_json::from_value(serde_json::Value::String(value.to_string())).unwrap_or_default()
+}
+
+fn row_to_job(row: &rusqlite::Row<'_>) -> rusqlite::Result<JobRecord> {
+    let language: String = row.get(3)?;
+    let add_space: Option<i64> = row.get(5)?;
+    let status: String = row.get(6)?;
+    let language = parse_language(&language);
+    // Jobs queued before merge profiles existed only recorded the spacing flag.
+    let merge_config = row
+        .get::<_, Option<String>>(10)?
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_else(|| MergeConfig {
+            add_space_on_merge: add_space.map(|value| value != 0),
+            language,
+            ..MergeConfig::default()
+        });
+    Ok(JobRecord {
+        job_id: row.get(0)?,
+        base_url: row.get(1)?,
+        context: row.get(2)?,
+        language,
+        engine: row.get(4)?,
+        merge_config,
+        user: None,
+        pass: None,
+        status: JobStatus::parse(&status),
+        priority: row.get(7)?,
+        ocr_priority: row
+            .get::<_, Option<String>>(11)?
+            .map(|value| OcrPriority::parse(&value))
+            .unwrap_or_default(),
+        created_at: row.get(8)?,
+        updated_at: row.get(9)?,
+    })
+}
+
+const JOB_COLUMNS: &str = "job_id, base_url, context, language, engine, add_space_on_merge,
+     status, priority, created_at, updated_at, merge_config, ocr_priority";
+
+impl AppState {
+    fn with_credentials(&self, mut job: JobRecord) -> JobRecord {
+        let credentials = self.job_credentials.get(&job.job_id);
+        job.user = credentials.user;
+        job.pass = credentials.pass;
+        job
+    }
+
+    /// Stores a job and its page list, replacing any previous job for the same chapter, and
+    /// wakes the queue worker.
+    pub fn enqueue_job(&self, job: &JobRecord, pages: &[String]) -> bool {
+        let Ok(mut conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for enqueue_job");
+            return false;
+        };
+        let tx = match conn.transaction() {
+            Ok(tx) => tx,
+            Err(err) => {
+                warn!("Failed to start enqueue transaction: {err}");
+                return false;
+            }
+        };
+        let _ = tx.execute(
+            "DELETE FROM ocr_job_pages WHERE job_id = ?",
+            params![job.job_id],
+        );
+        let _ = tx.execute(
+            "INSERT OR REPLACE INTO ocr_jobs
+                (job_id, base_url, context, language, engine, add_space_on_merge,
+                 status, priority, created_at, updated_at, merge_config, ocr_priority,
+                 needs_credentials)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                job.job_id,
+                job.base_url,
+                job.context,
+                job.language.as_str(),
+                job.engine,
+                job.merge_config.add_space_on_merge.map(i64::from),
+                job.status.as_str(),
+                job.priority,
+                job.created_at,
+                job.updated_at,
+                serde_json::to_string(&job.merge_config).unwrap_or_default(),
+                job.ocr_priority.as_str(),
+                job.user.is_some() || job.pass.is_some()
+            ],
+        );
+        for (index, url) in pages.iter().enumerate() {
+            let _ = tx.execute(
+                "INSERT INTO ocr_job_pages (job_id, page_index, url, status, attempts, updated_at)
+                 VALUES (?, ?, ?, ?, 0, ?)",
+                params![
+                    job.job_id,
+                    index as i64,
+                    url,
+                    PageStatus::Pending.as_str(),
+                    job.updated_at
+                ],
+            );
+        }
+        if let Err(err) = tx.commit() {
+            warn!("Failed to commit job {}: {err}", job.job_id);
+            return false;
+        }
+        self.job_credentials.set(
+            &job.job_id,
+            EngineContext {
+                user: job.user.clone(),
+                pass: job.pass.clone(),
+            },
+        );
+        self.job_notify.notify_one();
+        true
+    }
+
+    pub fn get_job(&self, job_id: &str) -> Option<JobRecord> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for get_job");
+            return None;
+        };
+        conn.query_row(
+            &format!("SELECT {JOB_COLUMNS} FROM ocr_jobs WHERE job_id = ?"),
+            params![job_id],
+            row_to_job,
+        )
+        .optional()
+        .unwrap_or(None)
+        .map(|job| self.with_credentials(job))
+    }
+
+    /// All stored jobs, highest priority first, then in the order they were queued.
+    pub fn list_jobs(&self) -> Vec<JobRecord> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for list_jobs");
+            return Vec::new();
+        };
+        let mut stmt = match conn.prepare(&format!(
+            "SELECT {JOB_COLUMNS} FROM ocr_jobs ORDER BY priority DESC, created_at ASC"
+        )) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                warn!("Failed to prepare list_jobs: {err}");
+                return Vec::new();
+            }
+        };
+        stmt.query_map([], row_to_job)
+            .map(|rows| {
+                rows.flatten()
+                    .map(|job| self.with_credentials(job))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Hands the Suwayomi credentials of a request for `url` to the jobs and series jobs on
+    /// the same server that lost theirs in a restart, and starts them again.
+    pub fn supply_credentials(&self, url: &str, user: Option<&str>, pass: Option<&str>) {
+        if user.is_none() && pass.is_none() {
+            return;
+        }
+        let credentials = EngineContext {
+            user: user.map(str::to_string),
+            pass: pass.map(str::to_string),
+        };
+        let waiting: Vec<(String, String)> = {
+            let Ok(conn) = self.pool.get() else {
+                warn!("Failed to get DB connection for supply_credentials");
+                return;
+            };
+            let Ok(mut stmt) = conn.prepare(
+                "SELECT job_id, base_url FROM ocr_jobs WHERE status = 'needs_credentials'",
+            ) else {
+                return;
+            };
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map(|rows| rows.flatten().collect())
+                .unwrap_or_default()
+        };
+        for (job_id, base_url) in waiting {
+            if !logic::same_server(&base_url, url) {
+                continue;
+            }
+            self.job_credentials.set(&job_id, credentials.clone());
+            let Ok(conn) = self.pool.get() else {
+                warn!("Failed to get DB connection for supply_credentials");
+                return;
+            };
+            let resumed = conn
+                .execute(
+                    "UPDATE ocr_jobs SET status = 'queued', updated_at = ?
+                     WHERE job_id = ? AND status = 'needs_credentials'",
+                    params![now_unix(), job_id],
+                )
+                .unwrap_or(0);
+            if resumed > 0 {
+                info!("Resuming job {job_id} with the credentials of a new request");
+                self.job_notify.notify_one();
+            }
+        }
+        self.resume_series_with_credentials(url, &credentials);
+    }
+
+    pub fn set_job_status(&self, job_id: &str, status: JobStatus) -> bool {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for set_job_status");
+            return false;
+        };
+        let changed = conn
+            .execute(
+                "UPDATE ocr_jobs SET status = ?, updated_at = ? WHERE job_id = ?",
+                params![status.as_str(), now_unix(), job_id],
+            )
+            .unwrap_or(0);
+        if status == JobStatus::Queued {
+            self.job_notify.notify_one();
+        }
+        changed > 0
+    }
+
+    pub fn set_job_priority(&self, job_id: &str, priority: i64) -> bool {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for set_job_priority");
+            return false;
+        };
+        conn.execute(
+            "UPDATE ocr_jobs SET priority = ?, updated_at = ? WHERE job_id = ?",
+            params![priority, now_unix(), job_id],
+        )
+        .map(|changed| changed > 0)
+        .unwrap_or(false)
+    }
+
+    pub fn delete_job(&self, job_id: &str) {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for delete_job");
+            return;
+        };
+        let _ = conn.execute(
+            "DELETE FROM ocr_job_pages WHERE job_id = ?",
+            params![job_id],
+        );
+        let _ = conn.execute("DELETE FROM ocr_jobs WHERE job_id = ?", params![job_id]);
+        self.job_credentials.remove(job_id);
+    }
+
+    /// Stops a job. A running job is dropped by its worker once the pages in flight are done;
+    /// any other job is deleted right away.
+    pub fn cancel_job(&self, job_id: &str) {
+        let is_running = self
+            .active_chapter_jobs
+            .read()
+            .expect("lock poisoned")
+            .contains_key(job_id);
+        if is_running {
+            self.set_job_status(job_id, JobStatus::Cancelled);
+        } else {
+            self.delete_job(job_id);
+            self.finish_series_chapter(job_id, SeriesChapterStatus::Cancelled);
+        }
+    }
+
+    /// Picks the next queued job that is not in `running`. Next-chapter jobs go ahead of
+    /// bulk ones regardless of their queue priority.
+    pub fn next_queued_job(&self, running: &[String]) -> Option<JobRecord> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for next_queued_job");
+            return None;
+        };
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {JOB_COLUMNS} FROM ocr_jobs WHERE status = 'queued'
+                 ORDER BY ocr_priority = 'next_chapter' DESC, priority DESC, created_at ASC"
+            ))
+            .ok()?;
+        let rows = stmt.query_map([], row_to_job).ok()?;
+        rows.flatten()
+            .find(|job| !running.contains(&job.job_id))
+            .map(|job| self.with_credentials(job))
+    }
+
+    pub fn get_job_pages(&self, job_id: &str) -> Vec<JobPage> {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for get_job_pages");
+            return Vec::new();
+        };
+        let mut stmt = match conn.prepare(
+            "SELECT page_index, url, status, attempts, error FROM ocr_job_pages
+             WHERE job_id = ? ORDER BY page_index",
+        ) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                warn!("Failed to prepare get_job_pages: {err}");
+                return Vec::new();
+            }
+        };
+        stmt.query_map(params![job_id], |row| {
+            let page_index: i64 = row.get(0)?;
+            let status: String = row.get(2)?;
+            let attempts: i64 = row.get(3)?;
+            Ok(JobPage {
+                page_index: page_index as usize,
+                url: row.get(1)?,
+                status: PageStatus::parse(&status),
+                attempts: attempts as u32,
+                error: row.get(4)?,
+            })
+        })
+        .map(|rows| rows.flatten().collect())
+        .unwrap_or_default()
+    }
+
+    pub fn count_job_pages(&self, job_id: &str) -> JobPageCounts {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for count_job_pages");
+            return JobPageCounts::default();
+        };
+        let mut counts = JobPageCounts::default();
+        let Ok(mut stmt) = conn
+            .prepare("SELECT status, COUNT(*) FROM ocr_job_pages WHERE job_id = ? GROUP BY status")
+        else {
+            return counts;
+        };
+        if let Ok(rows) = stmt.query_map(params![job_id], |row| {
+            let status: String = row.get(0)?;
+            let count: i64 = row.get(1)?;
+            Ok((PageStatus::parse(&status), count as usize))
+        }) {
+            for (status, count) in rows.flatten() {
+                match status {
+                    PageStatus::Pending => counts.pending += count,
+                    PageStatus::Done => counts.done += count,
+                    PageStatus::Failed => counts.failed += count,
+                }
+            }
+        }
+        counts
+    }
+
+    pub fn set_job_page_status(
+        &self,
+        job_id: &str,
+        page_index: usize,
+        status: PageStatus,
+        error: Option<&str>,
+    ) {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for set_job_page_status");
+            return;
+        };
+        let attempts_delta = i64::from(status != PageStatus::Pending);
+        let _ = conn.execute(
+            "UPDATE ocr_job_pages
+             SET status = ?, error = ?, attempts = attempts + ?, updated_at = ?
+             WHERE job_id = ? AND page_index = ?",
+            params![
+                status.as_str(),
+                error,
+                attempts_delta,
+                now_unix(),
+                job_id,
+                page_index as i64
+            ],
+        );
+    }
+
+    /// Puts failed pages of a job back to pending (all of them, or just `page_index`) and
+    /// requeues the job. Returns how many pages were reset.
+    pub fn retry_job_pages(&self, job_id: &str, page_index: Option<usize>) -> usize {
+        let Ok(conn) = self.pool.get() else {
+            warn!("Failed to get DB connection for retry_job_pages");
+            return 0;
+        };
+        let now = now_unix();
+        let reset = match page_index {
+            Some(index) => conn.execute(
+                "UPDATE ocr_job_pages SET status = 'pending', error = NULL, updated_at = ?
+                 WHERE job_id = ? AND page_index = ? AND status = 'failed'",
+                params![now, job_id, index as i64],
+            ),
+            None => conn.execute(
+                "UPDATE ocr_job_pages SET status = 'pending', error = NULL, updated_at = ?
+                 WHERE job_id = ? AND status = 'failed'",
+                params![now, job_id],
+            ),
+        }
+        .unwrap_or(0);
+        if reset > 0 {
+            let _ = conn.execute(
+                "UPDATE ocr_jobs SET status = 'queued', updated_at = ?
+                 WHERE job_id = ? AND status = 'failed'",
+                params![now, job_id],
+            );
+            self.job_notify.notify_one();
+        }
+        reset
+    }
+}
+
+fn load_config(conn: &rusqlite::Connection) -> OcrConfig {
+    let raw: Option<String> = conn
+        .query_row(
+            "SELECT value FROM metadata WHERE key = 'config'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()
+        .unwrap_or(None);
+
+    raw.and_then(|value| {
+        serde_json::from_str(&value)
+            .map_err(|err| warn!("Failed to parse stored OCR config: {err}"))
+            .ok()
+    })
+    .unwrap_or_default()
+}
+
+pub(crate) fn now_unix() -> i64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
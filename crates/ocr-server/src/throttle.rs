@@ -0,0 +1,355 @@
+//! Rate limiting, backoff and a circuit breaker around the OCR engines.
+//!
+//! Every engine handed out by [`AppState::resolve_engine`] goes through a guard shared by all
+//! readers and jobs using that engine. Calls are spaced to the configured rate, a rate-limit
+//! response pauses every caller with a growing backoff, and repeated failures open the breaker
+//! so requests fail fast until a single trial call succeeds again.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    engine::{EngineContext, EngineLine, OcrEngine},
+    language::OcrLanguage,
+    state::AppState,
+};
+
+/// Consecutive engine failures that open the breaker.
+const FAILURE_THRESHOLD: u32 = 5;
+/// How long the breaker stays open the first time; doubled after each failed trial.
+const BASE_COOLDOWN: Duration = Duration::from_secs(30);
+const MAX_COOLDOWN: Duration = Duration::from_secs(10 * 60);
+/// Pause after the first rate-limit response; doubled while they keep coming.
+const BASE_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// What went wrong with an OCR run. Attached to errors as context, so callers can decide
+/// whether trying again can help. Engines attach [`FailureKind::RateLimited`] themselves when
+/// they are told to slow down.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// The page image could not be downloaded.
+    Fetch,
+    /// The image is broken or in a format we can't read.
+    Decode,
+    /// The engine asked us to slow down.
+    RateLimited,
+    /// Any other engine failure.
+    Engine,
+    /// Refused without calling the engine because its breaker is open.
+    CircuitOpen,
+}
+
+impl FailureKind {
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        err.downcast_ref::<Self>().copied()
+    }
+
+    /// Whether the same call may succeed if made again.
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            FailureKind::Fetch | FailureKind::RateLimited | FailureKind::Engine
+        )
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailureKind::Fetch => "Failed to fetch page image",
+            FailureKind::Decode => "Failed to decode page image",
+            FailureKind::RateLimited => "OCR engine is rate limiting requests",
+            FailureKind::Engine => "OCR engine failed",
+            FailureKind::CircuitOpen => "OCR engine is unavailable",
+        })
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+struct Guard {
+    breaker: BreakerState,
+    consecutive_failures: u32,
+    cooldown: Duration,
+    open_until: Instant,
+    /// When the half-open trial call started; a trial that never reports back is replaced
+    /// after a cooldown.
+    trial_started: Option<Instant>,
+    backoff: Duration,
+    paused_until: Instant,
+    next_call_at: Instant,
+    rate_limited: u64,
+    engine_errors: u64,
+}
+
+impl Guard {
+    fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            breaker: BreakerState::Closed,
+            consecutive_failures: 0,
+            cooldown: BASE_COOLDOWN,
+            open_until: now,
+            trial_started: None,
+            backoff: Duration::ZERO,
+            paused_until: now,
+            next_call_at: now,
+            rate_limited: 0,
+            engine_errors: 0,
+        }
+    }
+}
+
+/// Breaker and backoff state of one engine, for the status endpoint.
+#[derive(Serialize, Clone, Debug)]
+pub struct GuardStats {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// Seconds until the open breaker lets a trial call through.
+    pub retry_in_secs: Option<u64>,
+    /// Seconds left of the pause after a rate-limit response.
+    pub backoff_secs: Option<u64>,
+    pub rate_limited: u64,
+    pub engine_errors: u64,
+}
+
+/// The guards of all engines, keyed by engine id.
+#[derive(Clone)]
+pub struct EngineGuards {
+    guards: Arc<Mutex<HashMap<String, Arc<Mutex<Guard>>>>>,
+    interval: Arc<Mutex<Option<Duration>>>,
+}
+
+impl EngineGuards {
+    pub fn new(requests_per_minute: Option<u32>) -> Self {
+        let guards = Self {
+            guards: Arc::default(),
+            interval: Arc::default(),
+        };
+        guards.set_rate(requests_per_minute);
+        guards
+    }
+
+    /// Changes the rate every engine is held to. `None` or `0` lifts the limit.
+    pub fn set_rate(&self, requests_per_minute: Option<u32>) {
+        *self.interval.lock().expect("lock poisoned") = requests_per_minute
+            .filter(|&rate| rate > 0)
+            .map(|rate| Duration::from_secs(60) / rate);
+    }
+
+    fn guard(&self, id: &str) -> Arc<Mutex<Guard>> {
+        self.guards
+            .lock()
+            .expect("lock poisoned")
+            .entry(id.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(Guard::new())))
+            .clone()
+    }
+
+    /// Wraps `engine` so its calls go through the engine's shared guard.
+    pub fn wrap(&self, engine: Arc<dyn OcrEngine>) -> Arc<dyn OcrEngine> {
+        Arc::new(GuardedEngine {
+            guard: self.guard(engine.id()),
+            interval: self.interval.clone(),
+            inner: engine,
+        })
+    }
+
+    pub fn stats(&self) -> BTreeMap<String, GuardStats> {
+        let now = Instant::now();
+        let remaining = |until: Instant| (until > now).then(|| (until - now).as_secs().max(1));
+        self.guards
+            .lock()
+            .expect("lock poisoned")
+            .iter()
+            .map(|(id, guard)| {
+                let guard = guard.lock().expect("lock poisoned");
+                let retry_in_secs = if guard.breaker == BreakerState::Open {
+                    remaining(guard.open_until)
+                } else {
+                    None
+                };
+                let stats = GuardStats {
+                    state: guard.breaker,
+                    consecutive_failures: guard.consecutive_failures,
+                    retry_in_secs,
+                    backoff_secs: remaining(guard.paused_until),
+                    rate_limited: guard.rate_limited,
+                    engine_errors: guard.engine_errors,
+                };
+                (id.clone(), stats)
+            })
+            .collect()
+    }
+}
+
+struct GuardedEngine {
+    inner: Arc<dyn OcrEngine>,
+    guard: Arc<Mutex<Guard>>,
+    interval: Arc<Mutex<Option<Duration>>>,
+}
+
+impl GuardedEngine {
+    /// Waits for the call's turn under the rate limit and any backoff, then checks the
+    /// breaker.
+    async fn admit(&self) -> anyhow::Result<()> {
+        let interval = *self.interval.lock().expect("lock poisoned");
+        let mut start_at = {
+            let mut guard = self.guard.lock().expect("lock poisoned");
+            let mut start_at = Instant::now().max(guard.paused_until);
+            if let Some(interval) = interval {
+                start_at = start_at.max(guard.next_call_at);
+                guard.next_call_at = start_at + interval;
+            }
+            start_at
+        };
+        loop {
+            tokio::time::sleep_until(start_at.into()).await;
+            // Another caller may have been rate limited while this one waited.
+            let paused_until = self.guard.lock().expect("lock poisoned").paused_until;
+            if paused_until <= Instant::now() {
+                break;
+            }
+            start_at = paused_until;
+        }
+
+        let mut guard = self.guard.lock().expect("lock poisoned");
+        let now = Instant::now();
+        if guard.breaker == BreakerState::Open && guard.open_until <= now {
+            guard.breaker = BreakerState::HalfOpen;
+            guard.trial_started = None;
+        }
+        let refused = match guard.breaker {
+            BreakerState::Closed => false,
+            BreakerState::Open => true,
+            BreakerState::HalfOpen => guard
+                .trial_started
+                .is_some_and(|started| now - started < BASE_COOLDOWN),
+        };
+        if refused {
+            // A half-open breaker waits for its trial call, or replaces it once it is overdue.
+            let retry_at = match guard.trial_started {
+                Some(started) if guard.breaker == BreakerState::HalfOpen => started + BASE_COOLDOWN,
+                _ => guard.open_until,
+            };
+            let retry_in = retry_at.saturating_duration_since(now).as_secs().max(1);
+            return Err(anyhow!(
+                "{} paused after repeated failures; trying again in {retry_in}s",
+                self.inner.id()
+            )
+            .context(FailureKind::CircuitOpen));
+        }
+        if guard.breaker == BreakerState::HalfOpen {
+            info!("Sending a trial request to OCR engine {}", self.inner.id());
+            guard.trial_started = Some(now);
+        }
+        Ok(())
+    }
+
+    fn record(&self, result: anyhow::Result<Vec<EngineLine>>) -> anyhow::Result<Vec<EngineLine>> {
+        let mut guard = self.guard.lock().expect("lock poisoned");
+        let err = match result {
+            Ok(lines) => {
+                if guard.breaker != BreakerState::Closed {
+                    info!("OCR engine {} recovered", self.inner.id());
+                }
+                guard.breaker = BreakerState::Closed;
+                guard.consecutive_failures = 0;
+                guard.cooldown = BASE_COOLDOWN;
+                guard.trial_started = None;
+                guard.backoff = Duration::ZERO;
+                return Ok(lines);
+            }
+            Err(err) => err,
+        };
+
+        let now = Instant::now();
+        let kind = match FailureKind::of(&err) {
+            Some(FailureKind::RateLimited) => FailureKind::RateLimited,
+            _ => FailureKind::Engine,
+        };
+        if kind == FailureKind::RateLimited {
+            guard.rate_limited += 1;
+            guard.backoff = if guard.backoff.is_zero() {
+                BASE_BACKOFF
+            } else {
+                (guard.backoff * 2).min(MAX_BACKOFF)
+            };
+            guard.paused_until = guard.paused_until.max(now + guard.backoff);
+            warn!(
+                "OCR engine {} is rate limiting; holding back all requests for {}s",
+                self.inner.id(),
+                guard.backoff.as_secs()
+            );
+        } else {
+            guard.engine_errors += 1;
+        }
+
+        guard.consecutive_failures += 1;
+        let failed_trial = guard.breaker == BreakerState::HalfOpen;
+        if failed_trial {
+            guard.cooldown = (guard.cooldown * 2).min(MAX_COOLDOWN);
+        }
+        if failed_trial || guard.consecutive_failures >= FAILURE_THRESHOLD {
+            if guard.breaker == BreakerState::Closed {
+                warn!(
+                    "OCR engine {} failed {} times in a row; pausing it for {}s",
+                    self.inner.id(),
+                    guard.consecutive_failures,
+                    guard.cooldown.as_secs()
+                );
+            }
+            guard.breaker = BreakerState::Open;
+            guard.open_until = now + guard.cooldown;
+            guard.trial_started = None;
+        }
+        if FailureKind::of(&err) == Some(kind) {
+            Err(err)
+        } else {
+            Err(err.context(kind))
+        }
+    }
+}
+
+impl OcrEngine for GuardedEngine {
+    fn id(&self) -> &str {
+        self.inner.id()
+    }
+
+    fn recognize<'a>(
+        &'a self,
+        image_png: &'a [u8],
+        language: OcrLanguage,
+        ctx: &'a EngineContext,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<EngineLine>>> {
+        Box::pin(async move {
+            self.admit().await?;
+            let result = self.inner.recognize(image_png, language, ctx).await;
+            self.record(result)
+        })
+    }
+}
+
+impl AppState {
+    /// Breaker and backoff state of every engine used since startup.
+    pub fn engine_health(&self) -> BTreeMap<String, GuardStats> {
+        self.engine_guards.stats()
+    }
+}
@@ -1,30 +1,62 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use manatan_ocr_server::{
+    engine::LensEngine,
+    language::OcrLanguage,
     logic::{self, RawChunk},
-    merge::{self, MergeConfig},
+    merge::MergeConfig,
 };
 use pretty_assertions::StrComparison;
 use serde_json::Value;
 use walkdir::WalkDir;
 
-fn sanitize_results(v: &mut Value) {
+/// Drops the fields the comparison ignores. Expected files of the external test data set were
+/// recorded before results had bubbles and a reading order, so `legacy` drops those as well.
+fn sanitize_results(v: &mut Value, legacy: bool) {
     match v {
         Value::Array(arr) => {
             for item in arr {
-                sanitize_results(item);
+                sanitize_results(item, legacy);
             }
         }
         Value::Object(map) => {
             map.remove("tightBoundingBox");
+            if legacy {
+                map.remove("bubbleId");
+                map.remove("readingOrder");
+            }
             for (_, value) in map.iter_mut() {
-                sanitize_results(value);
+                sanitize_results(value, legacy);
             }
         }
         _ => {}
     }
 }
 
+/// The page a test case is named after and whether its image exists. Bundled cases ship
+/// only `<name>.raw.json`, so they are named by that path without the extension.
+fn test_case_path(path: &Path) -> Option<(PathBuf, bool)> {
+    const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "webp", "avif"];
+
+    let file_name = path.file_name()?.to_str()?;
+    if let Some(name) = file_name.strip_suffix(".raw.json") {
+        let case_path = path.with_file_name(name);
+        let has_image = IMAGE_EXTENSIONS
+            .iter()
+            .any(|ext| case_path.with_extension(ext).exists());
+        // Cases with an image are picked up through the image itself.
+        return (!has_image).then_some((case_path, false));
+    }
+
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    IMAGE_EXTENSIONS
+        .contains(&ext.as_str())
+        .then(|| (path.to_path_buf(), true))
+}
+
 #[tokio::test]
 async fn run_merge_regression_tests() {
     // 1. Path Resolution
@@ -47,22 +79,21 @@ async fn run_merge_regression_tests() {
         None
     };
 
-    if test_data_path.is_none() {
-        if std::env::var("CI").is_ok() {
-            return;
-        }
-        eprintln!("❌ Test data not found.");
-        return;
+    // Synthetic cases without a page image always run.
+    let bundled_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let mut test_roots = vec![bundled_root.clone()];
+    if let Some(test_data_path) = test_data_path {
+        println!(
+            "📂 Using Test Data at: {:?}",
+            test_data_path
+                .canonicalize()
+                .unwrap_or(test_data_path.clone())
+        );
+        test_roots.push(test_data_path);
+    } else if std::env::var("CI").is_err() {
+        eprintln!("❌ Test data not found, running bundled cases only.");
     }
 
-    let test_data_path = test_data_path.unwrap();
-    println!(
-        "📂 Using Test Data at: {:?}",
-        test_data_path
-            .canonicalize()
-            .unwrap_or(test_data_path.clone())
-    );
-
     // Env flags
     let force_regen_raw = std::env::var("REGENERATE_RAW").is_ok();
     let only_generate_missing = std::env::var("ONLY_GENERATE_MISSING").is_ok();
@@ -73,119 +104,100 @@ async fn run_merge_regression_tests() {
     let mut skipped = 0;
     let mut failures = Vec::new();
 
-    for entry in WalkDir::new(&test_data_path)
-        .into_iter()
-        .filter_map(|e| e.ok())
+    for entry in test_roots
+        .iter()
+        .flat_map(|root| WalkDir::new(root).into_iter().filter_map(|e| e.ok()))
     {
-        let path = entry.path();
-
-        if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-            if ["png", "jpg", "jpeg", "webp", "avif"].contains(&ext.to_lowercase().as_str()) {
-                let file_stem = path.file_stem().unwrap().to_str().unwrap();
-                let parent_dir = path
-                    .parent()
-                    .and_then(|p| p.file_name())
-                    .and_then(|s| s.to_str())
-                    .unwrap_or("root");
-
-                let test_name = format!("{}/{}", parent_dir, file_stem);
-
-                let raw_cache_path = path.with_extension("raw.json");
-                let expected_path = path.with_extension("expected.json");
-
-                // Optimization: Skip processing if we only want new files and raw regen is NOT
-                // requested
-                if only_generate_missing
-                    && expected_path.exists()
-                    && !update_expected
-                    && !force_regen_raw
-                {
-                    skipped += 1;
-                    continue;
-                }
-
-                // 1. Get OCR Data
-                let raw_chunks: Vec<RawChunk> = if raw_cache_path.exists() && !force_regen_raw {
-                    let content = fs::read_to_string(&raw_cache_path).expect("Read raw cache");
-                    serde_json::from_str(&content).expect("Parse raw cache")
-                } else {
-                    println!("  [OCR] Running Lens OCR for {}...", test_name);
-                    let image_bytes = fs::read(path).expect("Read image");
-                    let chunks = logic::get_raw_ocr_data(&image_bytes, None, None)
-                        .await
-                        .expect("Lens OCR failed");
-
-                    let json = serde_json::to_string_pretty(&chunks).unwrap();
-                    fs::write(&raw_cache_path, json).expect("Write raw cache");
-                    chunks
-                };
-
-                // 2. Run Merge Logic
-                let config = MergeConfig::default();
-                let mut final_results = Vec::new();
-
-                for chunk in raw_chunks {
-                    let merged_lines =
-                        merge::auto_merge(chunk.lines, chunk.width, chunk.height, &config);
-
-                    for mut result in merged_lines {
-                        let global_pixel_y = result.tight_bounding_box.y + (chunk.global_y as f64);
-                        result.tight_bounding_box.x =
-                            result.tight_bounding_box.x / chunk.full_width as f64;
-                        result.tight_bounding_box.width =
-                            result.tight_bounding_box.width / chunk.full_width as f64;
-                        result.tight_bounding_box.y = global_pixel_y / chunk.full_height as f64;
-                        result.tight_bounding_box.height =
-                            result.tight_bounding_box.height / chunk.full_height as f64;
-                        final_results.push(result);
-                    }
-                }
+        let Some((path, has_image)) = test_case_path(entry.path()) else {
+            continue;
+        };
+        let path = path.as_path();
+        let legacy = !path.starts_with(&bundled_root);
+
+        let file_stem = path.file_stem().unwrap().to_str().unwrap();
+        let parent_dir = path
+            .parent()
+            .and_then(|p| p.file_name())
+            .and_then(|s| s.to_str())
+            .unwrap_or("root");
+
+        let test_name = format!("{}/{}", parent_dir, file_stem);
+
+        let raw_cache_path = path.with_extension("raw.json");
+        let expected_path = path.with_extension("expected.json");
+
+        // Optimization: Skip processing if we only want new files and raw regen is NOT
+        // requested
+        if only_generate_missing && expected_path.exists() && !update_expected && !force_regen_raw {
+            skipped += 1;
+            continue;
+        }
 
-                // Sanitize
-                let mut actual_value = serde_json::to_value(&final_results).expect("Serialize");
-                sanitize_results(&mut actual_value);
-                let actual_json_str = serde_json::to_string_pretty(&actual_value).unwrap();
-
-                // 3. Validation Logic
-                if expected_path.exists() {
-                    if update_expected {
-                        println!("  [UPDATE] Overwriting expected file for: {}", test_name);
-                        fs::write(&expected_path, actual_json_str).expect("Write expected file");
-                        generated += 1;
-                    } else if force_regen_raw {
-                        // skip validation during raw regen
-                    } else {
-                        // STANDARD TEST mode
-                        let expected_content =
-                            fs::read_to_string(&expected_path).expect("Read expected");
-                        let mut expected: Value =
-                            serde_json::from_str(&expected_content).expect("Invalid JSON");
-                        sanitize_results(&mut expected);
-
-                        let p_exp = serde_json::to_string_pretty(&expected).unwrap();
-                        let p_act = serde_json::to_string_pretty(&actual_value).unwrap();
-
-                        if p_act != p_exp {
-                            println!(
-                                "------------------------------------------------------------"
-                            );
-                            println!("❌ Mismatch in test case: {}", test_name);
-                            println!("Diff < left (actual) / right (expected) > :");
-                            println!("{}", StrComparison::new(&p_act, &p_exp));
-                            println!(
-                                "------------------------------------------------------------"
-                            );
-                            failures.push(test_name);
-                        } else {
-                            passed += 1;
-                        }
-                    }
+        // 1. Get OCR Data
+        let raw_chunks: Vec<RawChunk> =
+            if raw_cache_path.exists() && !(force_regen_raw && has_image) {
+                let content = fs::read_to_string(&raw_cache_path).expect("Read raw cache");
+                serde_json::from_str(&content).expect("Parse raw cache")
+            } else {
+                println!("  [OCR] Running Lens OCR for {}...", test_name);
+                let image_bytes = fs::read(path).expect("Read image");
+                let chunks = logic::get_raw_ocr_data(
+                    &image_bytes,
+                    &LensEngine,
+                    None,
+                    None,
+                    OcrLanguage::Japanese,
+                )
+                .await
+                .expect("Lens OCR failed");
+
+                let json = serde_json::to_string_pretty(&chunks).unwrap();
+                fs::write(&raw_cache_path, json).expect("Write raw cache");
+                chunks
+            };
+
+        // 2. Run Merge Logic
+        let config = MergeConfig::default();
+        let final_results = logic::merge_chunks(&raw_chunks, &config);
+
+        // Sanitize
+        let mut actual_value = serde_json::to_value(&final_results).expect("Serialize");
+        sanitize_results(&mut actual_value, legacy);
+        let actual_json_str = serde_json::to_string_pretty(&actual_value).unwrap() + "\n";
+
+        // 3. Validation Logic
+        if expected_path.exists() {
+            if update_expected {
+                println!("  [UPDATE] Overwriting expected file for: {}", test_name);
+                fs::write(&expected_path, actual_json_str).expect("Write expected file");
+                generated += 1;
+            } else if force_regen_raw {
+                // skip validation during raw regen
+            } else {
+                // STANDARD TEST mode
+                let expected_content = fs::read_to_string(&expected_path).expect("Read expected");
+                let mut expected: Value =
+                    serde_json::from_str(&expected_content).expect("Invalid JSON");
+                sanitize_results(&mut expected, legacy);
+
+                let p_exp = serde_json::to_string_pretty(&expected).unwrap();
+                let p_act = serde_json::to_string_pretty(&actual_value).unwrap();
+
+                if p_act != p_exp {
+                    println!("------------------------------------------------------------");
+                    println!("❌ Mismatch in test case: {}", test_name);
+                    println!("Diff < left (actual) / right (expected) > :");
+                    println!("{}", StrComparison::new(&p_act, &p_exp));
+                    println!("------------------------------------------------------------");
+                    failures.push(test_name);
                 } else {
-                    println!("  [NEW] Generating expected file for: {}", test_name);
-                    fs::write(&expected_path, actual_json_str).expect("Bootstrap expected file");
-                    generated += 1;
+                    passed += 1;
                 }
             }
+        } else {
+            println!("  [NEW] Generating expected file for: {}", test_name);
+            fs::write(&expected_path, actual_json_str).expect("Bootstrap expected file");
+            generated += 1;
         }
     }
 
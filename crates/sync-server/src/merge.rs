@@ -1,4 +1,4 @@
-use crate::types::{ConflictInfo, LNMetadata, LNProgress, SyncPayload};
+use crate::types::{ConflictInfo, LNMetadata, LNProgress, OcrPageCorrections, SyncPayload};
 use std::collections::HashMap;
 use tracing::debug;
 
@@ -29,6 +29,9 @@ pub fn merge_payloads(
     // Merge file manifest
     let merged_manifest = merge_simple_maps(local.file_manifest, remote.file_manifest);
 
+    // Merge OCR corrections (most recently edited page wins)
+    let merged_corrections = merge_corrections_maps(local.ocr_corrections, remote.ocr_corrections);
+
     let merged = SyncPayload {
         schema_version: SyncPayload::CURRENT_SCHEMA_VERSION,
         device_id: local_device_id.to_string(),
@@ -38,6 +41,7 @@ pub fn merge_payloads(
         ln_content: merged_content,
         ln_files: merged_files,
         file_manifest: merged_manifest,
+        ocr_corrections: merged_corrections,
     };
 
     (merged, conflicts)
@@ -172,6 +176,24 @@ fn merge_metadata_maps(
     (merged, conflicts)
 }
 
+fn merge_corrections_maps(
+    local: HashMap<String, OcrPageCorrections>,
+    remote: HashMap<String, OcrPageCorrections>,
+) -> HashMap<String, OcrPageCorrections> {
+    let mut merged = remote;
+    for (cache_key, corrections) in local {
+        match merged.get(&cache_key) {
+            Some(existing) if existing.updated_at > corrections.updated_at => {
+                debug!("Keeping remote OCR corrections for {}", cache_key);
+            }
+            _ => {
+                merged.insert(cache_key, corrections);
+            }
+        }
+    }
+    merged
+}
+
 fn merge_simple_maps<V: Clone>(
     local: HashMap<String, V>,
     remote: HashMap<String, V>,
@@ -224,6 +224,20 @@ pub enum FileType {
     Content,
 }
 
+// ============================================================================
+// OCR Corrections
+// ============================================================================
+
+/// User corrections of one manga page, as exported by the OCR server. The edits are passed
+/// through untouched; only `updated_at` matters for merging.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcrPageCorrections {
+    pub edits: serde_json::Value,
+    #[serde(alias = "updatedAt")]
+    pub updated_at: i64,
+}
+
 // ============================================================================
 // Sync Payload
 // ============================================================================
@@ -262,6 +276,11 @@ pub struct SyncPayload {
     /// File manifest for resumable sync
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub file_manifest: HashMap<String, FileReference>,
+
+    /// OCR corrections for each manga page (OCR cache key → corrections)
+    /// Only included if sync_config.ocr_corrections is true
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub ocr_corrections: HashMap<String, OcrPageCorrections>,
 }
 
 impl SyncPayload {
@@ -335,6 +354,8 @@ pub struct SyncConfig {
     pub ln_metadata: bool,
     pub ln_content: bool,
     pub ln_files: bool,
+    #[serde(default = "default_true")]
+    pub ocr_corrections: bool,
 
     // Sync triggers (matching Tachiyomi)
     pub sync_on_chapter_read: bool,
@@ -360,6 +381,7 @@ impl Default for SyncConfig {
             ln_metadata: true,
             ln_content: true,
             ln_files: false,
+            ocr_corrections: true,
             sync_on_chapter_read: false,
             sync_on_chapter_open: false,
             sync_on_app_start: false,
@@ -372,6 +394,10 @@ impl Default for SyncConfig {
     }
 }
 
+fn default_true() -> bool {
+    true
+}
+
 /// Google Drive folder type selection
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 #[serde(rename_all = "camelCase")]
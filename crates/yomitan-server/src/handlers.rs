@@ -12,7 +12,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::{Value, Value as JsonValue, json};
 use sha2::{Digest, Sha256};
 use tracing::{error, info};
-use wordbase_api::{DictionaryId, Record, Term, dict::yomitan::GlossaryTag};
+use wordbase_api::{DictionaryId, Record, RecordEntry, Term, dict::yomitan::GlossaryTag};
+
+use manatan_ocr_server::{language::OcrLanguage, logic::OcrResult, merge::MergeProfile};
 
 use crate::{ServerState, import, state::AppState};
 
@@ -31,6 +33,22 @@ pub struct LookupParams {
     pub language: Option<DictionaryLanguage>,
 }
 
+#[derive(Deserialize)]
+pub struct LookupPointParams {
+    /// Page image URL, as passed to the OCR server.
+    pub url: String,
+    /// Page-normalized coordinates (`0.0..=1.0`).
+    pub x: f64,
+    pub y: f64,
+    pub language: Option<DictionaryLanguage>,
+    /// Merge settings the page was OCR'd with, as passed to the OCR server.
+    pub merge_profile: Option<String>,
+    pub merge_enabled: Option<bool>,
+    pub font_size_ratio: Option<f64>,
+    pub add_space_on_merge: Option<bool>,
+    pub group: Option<bool>,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum AudioSource {
@@ -91,6 +109,22 @@ pub struct ApiGroupedResult {
     pub match_len: usize,
 }
 
+/// Char range of the OCR text a lookup matched.
+#[derive(Serialize)]
+pub struct ApiSpan {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize)]
+pub struct ApiPointLookup {
+    /// The OCR result under the point.
+    #[serde(rename = "box")]
+    pub ocr_box: OcrResult,
+    pub highlight: ApiSpan,
+    pub results: Vec<ApiGroupedResult>,
+}
+
 #[derive(Deserialize)]
 #[serde(tag = "action", content = "payload")]
 pub enum DictionaryAction {
@@ -1376,6 +1410,86 @@ pub async fn lookup_handler(
         language.to_deinflect_language(),
     );
 
+    Ok(Json(group_lookup_results(
+        &state,
+        raw_results,
+        should_group,
+    )))
+}
+
+/// Looks up the word under a point of an OCR'd page: finds the cached OCR result there,
+/// the character under the point, and searches the dictionaries from that character.
+pub async fn lookup_point_handler(
+    State(state): State<ServerState>,
+    Query(params): Query<LookupPointParams>,
+) -> Result<Json<ApiPointLookup>, (StatusCode, Json<Value>)> {
+    let language = params
+        .language
+        .or_else(|| load_preferred_language(&state.app))
+        .unwrap_or(DictionaryLanguage::Japanese);
+
+    if state.app.is_loading() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "loading", "message": "Dictionaries are importing..." })),
+        ));
+    }
+
+    let ocr_language = OcrLanguage::from_name(language.as_str()).unwrap_or_default();
+    let Some(hit) = state.ocr.ocr_result_at(
+        &params.url,
+        ocr_language,
+        params.merge_profile.as_deref(),
+        &MergeProfile {
+            enabled: params.merge_enabled,
+            font_size_ratio: params.font_size_ratio,
+            add_space_on_merge: params.add_space_on_merge,
+        },
+        params.x,
+        params.y,
+    ) else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "no_text", "message": "No OCR text at this point" })),
+        ));
+    };
+
+    // The OCR side counts chars, the lookup wants a byte offset.
+    let text = &hit.result.text;
+    let byte_offset = text
+        .char_indices()
+        .nth(hit.char_offset)
+        .map_or(text.len(), |(index, _)| index);
+    let raw_results = state.lookup.search(
+        &state.app,
+        text,
+        byte_offset,
+        language.to_deinflect_language(),
+    );
+    let results = group_lookup_results(&state, raw_results, params.group.unwrap_or(true));
+    let match_len = results
+        .iter()
+        .map(|result| result.match_len)
+        .max()
+        .unwrap_or(0);
+
+    Ok(Json(ApiPointLookup {
+        ocr_box: hit.result,
+        highlight: ApiSpan {
+            start: hit.char_offset,
+            end: hit.char_offset + match_len,
+        },
+        results,
+    }))
+}
+
+/// Turns raw dictionary hits into the API shape: definitions grouped per headword/reading
+/// (or one entry per hit without `should_group`), with frequencies attached.
+fn group_lookup_results(
+    state: &ServerState,
+    raw_results: Vec<(RecordEntry, Option<Vec<GlossaryTag>>)>,
+    should_group: bool,
+) -> Vec<ApiGroupedResult> {
     let dict_meta: std::collections::HashMap<DictionaryId, String> = {
         let dicts = state.app.dictionaries.read().expect("lock");
         dicts.iter().map(|(k, v)| (*k, v.name.clone())).collect()
@@ -1507,8 +1621,7 @@ pub async fn lookup_handler(
     }
 
     if should_group {
-        let final_results = map
-            .into_iter()
+        map.into_iter()
             .map(|mut agg| {
                 // Attach frequencies if they exist for this word
                 if let Some(freqs) = freq_map.get(&(agg.headword.clone(), agg.reading.clone())) {
@@ -1533,9 +1646,7 @@ pub async fn lookup_handler(
                     match_len: agg.match_len,
                 }
             })
-            .collect();
-
-        Ok(Json(final_results))
+            .collect()
     } else {
         // Iterate through results and attach frequencies to ALL of them.
         for res in &mut flat_results {
@@ -1544,7 +1655,7 @@ pub async fn lookup_handler(
             }
         }
 
-        Ok(Json(flat_results))
+        flat_results
     }
 }
 
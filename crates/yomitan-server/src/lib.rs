@@ -15,8 +15,8 @@ pub mod state;
 
 use handlers::{
     audio_handler, import_handler, install_defaults_handler, install_language_handler,
-    list_dictionaries_handler, lookup_handler, manage_dictionaries_handler, reset_db_handler,
-    unload_handler,
+    list_dictionaries_handler, lookup_handler, lookup_point_handler, manage_dictionaries_handler,
+    reset_db_handler, unload_handler,
 };
 use lookup::LookupService;
 use state::AppState;
@@ -25,18 +25,22 @@ use state::AppState;
 pub struct ServerState {
     pub app: AppState,
     pub lookup: Arc<LookupService>,
+    /// The OCR server's state, for looking up words straight from OCR'd pages.
+    pub ocr: manatan_ocr_server::state::AppState,
 }
 
-pub fn create_router(data_dir: PathBuf) -> Router {
+pub fn create_router(data_dir: PathBuf, ocr: manatan_ocr_server::state::AppState) -> Router {
     let state = ServerState {
         app: AppState::new(data_dir),
         lookup: Arc::new(LookupService::new()),
+        ocr,
     };
 
     let limit = 1024 * 1024 * 1024;
 
     Router::new()
         .route("/lookup", get(lookup_handler))
+        .route("/lookup-point", get(lookup_point_handler))
         .route("/audio", get(audio_handler))
         .route("/dictionaries", get(list_dictionaries_handler))
         .route("/import", post(import_handler))